@@ -0,0 +1,149 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use std::sync::mpsc::Sender;
+
+use log::info;
+
+/*
+ * How much more throughput another worker has to bring, as a fraction of the
+ * best seen so far, to count as an improvement. Anything less is noise.
+ */
+const TOLERANCE: f64 = 0.05;
+
+/*
+ * How many workers in a row may fail to improve throughput before we decide
+ * the target is saturated. One bad interval shouldn't end the search.
+ */
+const PATIENCE: u32 = 3;
+
+/*
+ * Finds the concurrency at which a target stops getting faster, for
+ * --auto-concurrency. The run starts with one worker, and after each interval
+ * the stat thread tells us the throughput it saw. While throughput keeps
+ * improving another worker is added, up to 'max'. Once it stops improving the
+ * run settles on the fewest workers that reached the best throughput, the
+ * knee, and holds there. The number of workers wanted is sent to the
+ * controller thread in main(), which starts and retires them.
+ */
+pub struct AutoConcurrency {
+    max: u32,
+    workers: u32,
+    best: f64,
+    knee: u32,
+    misses: u32,
+    done: bool,
+    tx: Sender<u32>,
+}
+
+impl AutoConcurrency {
+    pub fn new(max: u32, tx: Sender<u32>) -> AutoConcurrency {
+        AutoConcurrency {
+            max,
+            workers: 1,
+            best: 0.0,
+            knee: 1,
+            misses: 0,
+            done: false,
+            tx,
+        }
+    }
+
+    /* Take the throughput, in ops/s, of an interval that just ended. */
+    pub fn observe(&mut self, ops_per_sec: f64) {
+        if let Some(workers) = self.step(ops_per_sec) {
+            /* The controller is gone once the run is over. */
+            let _ = self.tx.send(workers);
+        }
+    }
+
+    /* The number of workers there should be right now. */
+    pub fn workers(&self) -> u32 {
+        self.workers
+    }
+
+    /* The number of workers settled on, once the search is over. */
+    pub fn knee(&self) -> Option<u32> {
+        if self.done {
+            Some(self.knee)
+        } else {
+            None
+        }
+    }
+
+    /* The number of workers to change to, if it should change. */
+    fn step(&mut self, ops_per_sec: f64) -> Option<u32> {
+        if self.done {
+            return None;
+        }
+
+        if ops_per_sec > self.best * (1.0 + TOLERANCE) {
+            self.best = ops_per_sec;
+            self.knee = self.workers;
+            self.misses = 0;
+        } else {
+            self.misses += 1;
+        }
+
+        if self.misses < PATIENCE && self.workers < self.max {
+            self.workers += 1;
+            return Some(self.workers);
+        }
+
+        self.done = true;
+        info!(
+            "auto-concurrency settled on {} workers ({:.1} ops/s)",
+            self.knee, self.best
+        );
+        if self.workers == self.knee {
+            None
+        } else {
+            self.workers = self.knee;
+            Some(self.knee)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    fn steps(max: u32, rates: &[f64]) -> (Vec<Option<u32>>, Option<u32>) {
+        let (tx, _rx) = channel();
+        let mut auto = AutoConcurrency::new(max, tx);
+        let steps = rates.iter().map(|r| auto.step(*r)).collect();
+        (steps, auto.knee())
+    }
+
+    #[test]
+    fn test_auto_concurrency() {
+        /* Throughput levels off at three workers, so we go back to three. */
+        let (s, knee) = steps(16, &[100.0, 190.0, 270.0, 272.0, 271.0, 260.0]);
+        assert_eq!(
+            s,
+            vec![Some(2), Some(3), Some(4), Some(5), Some(6), Some(3)]
+        );
+        assert_eq!(knee, Some(3));
+
+        /* A noisy interval doesn't end the search if the next one improves. */
+        let (s, knee) = steps(16, &[100.0, 100.0, 300.0]);
+        assert_eq!(s, vec![Some(2), Some(3), Some(4)]);
+        assert_eq!(knee, None);
+
+        /* Still improving at the most workers allowed, so stay there. */
+        let (s, knee) = steps(3, &[100.0, 200.0, 300.0, 300.0]);
+        assert_eq!(s, vec![Some(2), Some(3), None, None]);
+        assert_eq!(knee, Some(3));
+
+        /* One worker is as good as it gets. */
+        let (s, knee) = steps(2, &[100.0, 100.0]);
+        assert_eq!(s, vec![Some(2), Some(1)]);
+        assert_eq!(knee, Some(1));
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::utils::ChumError;
+use crate::worker::WorkerInfo;
+
+/* How often threads check on a probe that's in flight. */
+const PROBE_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Closed(u32),   /* consecutive failures so far */
+    Open(Instant), /* no requests until then */
+    Probing,       /* one request has been let through to test the target */
+}
+
+/*
+ * A circuit breaker for one target, shared by all worker threads.
+ *
+ * After 'threshold' operations in a row fail with a transient error (e.g. a
+ * 5xx or a timeout) the breaker opens, and threads wait in wait() instead of
+ * sending anything for 'cooldown'. Then one thread is let through to probe the
+ * target. If the probe succeeds the breaker closes and everyone carries on,
+ * otherwise it opens for another cooldown.
+ *
+ * Errors that aren't transient (e.g. a 404) show that the target is up, so
+ * they count as successes here.
+ */
+pub struct CircuitBreaker {
+    target: String,
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        target: &str,
+        threshold: u32,
+        cooldown: Duration,
+    ) -> CircuitBreaker {
+        CircuitBreaker {
+            target: target.to_string(),
+            threshold,
+            cooldown,
+            state: Mutex::new(State::Closed(0)),
+        }
+    }
+
+    /* Block until the breaker lets an operation through. */
+    pub fn wait(&self) {
+        loop {
+            let delay = {
+                let mut state = self.state.lock().unwrap();
+                match *state {
+                    State::Closed(_) => return,
+                    State::Open(until) => {
+                        let now = Instant::now();
+                        if now >= until {
+                            info!(
+                                "circuit breaker for {} probing the target",
+                                self.target
+                            );
+                            *state = State::Probing;
+                            return;
+                        }
+                        until - now
+                    }
+                    State::Probing => PROBE_POLL,
+                }
+            };
+
+            /* Don't hold the lock while sleeping. */
+            thread::sleep(delay);
+        }
+    }
+
+    /* Note the outcome of an operation that wait() let through. */
+    pub fn record(&self, res: &Result<Option<WorkerInfo>, ChumError>) {
+        let mut state = self.state.lock().unwrap();
+        let failed = match res {
+            Ok(Some(_)) => false,
+            Err(e) => e.is_transient(),
+
+            /*
+             * Nothing was sent, e.g. a read with nothing in the queue. A
+             * probe like this tells us nothing, so let someone else probe.
+             */
+            Ok(None) => {
+                if *state == State::Probing {
+                    *state = State::Open(Instant::now());
+                }
+                return;
+            }
+        };
+
+        *state = match (*state, failed) {
+            (State::Closed(_), false) => State::Closed(0),
+            (State::Closed(n), true) if n + 1 < self.threshold => {
+                State::Closed(n + 1)
+            }
+            (State::Closed(n), true) => {
+                warn!(
+                    "circuit breaker for {} opened after {} failures in a \
+                     row, pausing for {:?}",
+                    self.target,
+                    n + 1,
+                    self.cooldown
+                );
+                State::Open(Instant::now() + self.cooldown)
+            }
+            (State::Probing, false) => {
+                info!("circuit breaker for {} closed", self.target);
+                State::Closed(0)
+            }
+            (State::Probing, true) => {
+                warn!(
+                    "circuit breaker for {} probe failed, pausing for {:?}",
+                    self.target, self.cooldown
+                );
+                State::Open(Instant::now() + self.cooldown)
+            }
+
+            /* Operations that were in flight when the breaker opened. */
+            (State::Open(until), _) => State::Open(until),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ErrorKind;
+    use crate::worker::Operation;
+
+    fn ok() -> Result<Option<WorkerInfo>, ChumError> {
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Read,
+            size: 0,
+            ttfb: 0,
+            rtt: 0,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }))
+    }
+
+    fn err(kind: ErrorKind) -> Result<Option<WorkerInfo>, ChumError> {
+        Err(ChumError::with_kind(kind, "failed"))
+    }
+
+    fn state(cb: &CircuitBreaker) -> State {
+        *cb.state.lock().unwrap()
+    }
+
+    #[test]
+    fn test_breaker_opens() {
+        let cb = CircuitBreaker::new("t", 3, Duration::from_millis(100));
+
+        /* Only consecutive transient failures count. */
+        cb.record(&err(ErrorKind::Timeout));
+        cb.record(&err(ErrorKind::Server));
+        cb.record(&ok());
+        cb.record(&err(ErrorKind::Timeout));
+        cb.record(&err(ErrorKind::Client));
+        cb.record(&err(ErrorKind::Timeout));
+        cb.record(&err(ErrorKind::Connection));
+        assert_eq!(state(&cb), State::Closed(2));
+
+        cb.record(&err(ErrorKind::Timeout));
+        match state(&cb) {
+            State::Open(_) => (),
+            s => panic!("expected open, got {:?}", s),
+        }
+
+        /* The first thread through after the cooldown is the probe. */
+        let start = Instant::now();
+        cb.wait();
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(50), "{:?}", waited);
+        assert!(waited < Duration::from_millis(1000), "{:?}", waited);
+        assert_eq!(state(&cb), State::Probing);
+    }
+
+    #[test]
+    fn test_breaker_probe() {
+        let cb = CircuitBreaker::new("t", 1, Duration::from_millis(10));
+
+        cb.record(&err(ErrorKind::Server));
+        cb.wait();
+        cb.record(&err(ErrorKind::Server));
+        match state(&cb) {
+            State::Open(_) => (),
+            s => panic!("expected open, got {:?}", s),
+        }
+
+        /* A probe that sent nothing lets the next thread probe. */
+        cb.wait();
+        cb.record(&Ok(None));
+        cb.wait();
+        assert_eq!(state(&cb), State::Probing);
+
+        cb.record(&ok());
+        assert_eq!(state(&cb), State::Closed(0));
+    }
+}
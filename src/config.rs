@@ -0,0 +1,467 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use crate::utils::ChumError;
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::fs;
+
+/*
+ * Settings read from a TOML file given with --config. Keys are named after the
+ * long form of the equivalent command line flag, e.g.
+ *
+ *   target = "127.0.0.1:80"
+ *   concurrency = 50
+ *   distribution = "128k:8,512k,1m"
+ *   max-data = "10G"
+ *   verify = true
+ *
+ * Flags given on the command line take precedence over the file. Settings for
+ * other worker backends (e.g. 'http2' for an s3 run) are ignored.
+ */
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub target: Option<String>,
+    pub concurrency: Option<ConcurrencyValue>,
+    pub sleep: Option<u64>,
+    pub think_time: Option<String>,
+    pub distribution: Option<String>,
+    pub read_range: Option<String>,
+    pub read_distribution: Option<String>,
+    pub interval: Option<u64>,
+    pub workload: Option<String>,
+    pub format: Option<String>,
+    pub tui: Option<bool>,
+    pub timestamp: Option<String>,
+    pub tabular_header: Option<bool>,
+    pub tabular_separator: Option<String>,
+    pub stats_file: Option<String>,
+    pub event_log: Option<String>,
+    pub scatter_file: Option<String>,
+    pub prometheus_file: Option<String>,
+    pub statsd: Option<String>,
+    pub statsd_tags: Option<bool>,
+    pub max_data: Option<String>,
+    pub max_ops: Option<u64>,
+    pub max_bandwidth: Option<String>,
+    pub max_error_rate: Option<f64>,
+    pub max_errors: Option<u64>,
+    pub slow_threshold: Option<String>,
+    pub window: Option<u64>,
+    pub breaker_threshold: Option<u32>,
+    pub breaker_cooldown: Option<String>,
+    pub duration: Option<String>,
+    pub ramp: Option<String>,
+    pub auto_concurrency: Option<bool>,
+    pub warmup: Option<String>,
+    pub target_weights: Option<String>,
+    pub read_list: Option<String>,
+    pub verify_size: Option<bool>,
+    pub queue_save: Option<String>,
+    pub queue_max: Option<u64>,
+    pub queue_shards: Option<u64>,
+    pub min_objects: Option<u64>,
+    pub queue_mode: Option<String>,
+    pub delete_policy: Option<String>,
+    pub verify_delete: Option<bool>,
+    pub ttl: Option<String>,
+    pub zipf_theta: Option<f64>,
+    pub prefix: Option<String>,
+    pub name_scheme: Option<String>,
+    pub shard_by_thread: Option<bool>,
+    pub compressibility: Option<f64>,
+    pub source_dir: Option<String>,
+    pub io_buffer: Option<String>,
+    pub thread_stack_size: Option<String>,
+    pub seed: Option<u64>,
+    pub verify: Option<bool>,
+    pub content_md5: Option<bool>,
+    pub no_overwrite: Option<bool>,
+    pub retries: Option<u32>,
+    pub retry_backoff: Option<u64>,
+    pub connect_timeout: Option<String>,
+    pub request_timeout: Option<String>,
+    pub statemap_rotate: Option<String>,
+    pub debug: Option<bool>,
+
+    /* FS worker. */
+    pub percentage: Option<u32>,
+    pub no_sync: Option<bool>,
+    pub fs_sync: Option<String>,
+    pub fs_shard: Option<u32>,
+    pub fs_mmap: Option<bool>,
+    pub fs_direct: Option<bool>,
+
+    /* WebDAV (and other https) workers. */
+    pub http2: Option<bool>,
+    pub http2_prior_knowledge: Option<bool>,
+    pub chunked: Option<bool>,
+    pub expect_100: Option<bool>,
+    pub insecure: Option<bool>,
+    pub pin_dns: Option<bool>,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub keepalive: Option<String>,
+    pub max_connections: Option<u32>,
+    pub cacert: Option<String>,
+    pub header: Option<Vec<String>>,
+    pub write_ok_status: Option<String>,
+    pub read_ok_status: Option<String>,
+    pub delete_ok_status: Option<String>,
+
+    /* HTTP worker. */
+    pub expect_status: Option<String>,
+
+    /* S3 worker. */
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub region: Option<String>,
+    pub storage_class: Option<String>,
+    pub multipart_threshold: Option<String>,
+    pub multipart_part_size: Option<String>,
+    pub multipart_parallelism: Option<u32>,
+    pub batch_delete: Option<u64>,
+
+    /* S3 and Azure Blob workers. */
+    pub create_bucket: Option<bool>,
+    pub cleanup: Option<bool>,
+
+    /* Azure Blob worker. */
+    pub endpoint: Option<String>,
+}
+
+/*
+ * 'concurrency' is either a number of workers or, as with -c, a list of them
+ * by target, e.g. "host1:80=8,host2:80=4,2".
+ */
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ConcurrencyValue {
+    Count(u32),
+    List(String),
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, ChumError> {
+        let contents = fs::read_to_string(path).map_err(|e| {
+            ChumError::new(&format!(
+                "failed to read config file {}: {}",
+                path, e
+            ))
+        })?;
+        Config::parse(&contents).map_err(|e| {
+            ChumError::new(&format!("invalid config file {}: {}", path, e))
+        })
+    }
+
+    fn parse(contents: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /*
+     * Flatten the config into the same form clap gives us, keyed by flag
+     * name. Flags that take no value are only present if they're true, and
+     * flags that may be repeated are kept separately (see lists()).
+     */
+    fn values(&self) -> HashMap<&'static str, String> {
+        let mut vals = HashMap::new();
+
+        let strs = vec![
+            ("target", &self.target),
+            ("distribution", &self.distribution),
+            ("read-range", &self.read_range),
+            ("read-distribution", &self.read_distribution),
+            ("workload", &self.workload),
+            ("format", &self.format),
+            ("timestamp", &self.timestamp),
+            ("tabular-separator", &self.tabular_separator),
+            ("stats-file", &self.stats_file),
+            ("event-log", &self.event_log),
+            ("scatter-file", &self.scatter_file),
+            ("prometheus-file", &self.prometheus_file),
+            ("statsd", &self.statsd),
+            ("max-data", &self.max_data),
+            ("max-bandwidth", &self.max_bandwidth),
+            ("duration", &self.duration),
+            ("think-time", &self.think_time),
+            ("ramp", &self.ramp),
+            ("connect-timeout", &self.connect_timeout),
+            ("request-timeout", &self.request_timeout),
+            ("statemap-rotate", &self.statemap_rotate),
+            ("warmup", &self.warmup),
+            ("slow-threshold", &self.slow_threshold),
+            ("breaker-cooldown", &self.breaker_cooldown),
+            ("target-weights", &self.target_weights),
+            ("read-list", &self.read_list),
+            ("queue-save", &self.queue_save),
+            ("queue-mode", &self.queue_mode),
+            ("delete-policy", &self.delete_policy),
+            ("ttl", &self.ttl),
+            ("prefix", &self.prefix),
+            ("name-scheme", &self.name_scheme),
+            ("source-dir", &self.source_dir),
+            ("io-buffer", &self.io_buffer),
+            ("thread-stack-size", &self.thread_stack_size),
+            ("cacert", &self.cacert),
+            ("proxy", &self.proxy),
+            ("no-proxy", &self.no_proxy),
+            ("keepalive", &self.keepalive),
+            ("write-ok-status", &self.write_ok_status),
+            ("read-ok-status", &self.read_ok_status),
+            ("delete-ok-status", &self.delete_ok_status),
+            ("expect-status", &self.expect_status),
+            ("access-key", &self.access_key),
+            ("secret-key", &self.secret_key),
+            ("region", &self.region),
+            ("storage-class", &self.storage_class),
+            ("multipart-threshold", &self.multipart_threshold),
+            ("multipart-part-size", &self.multipart_part_size),
+            ("fs-sync", &self.fs_sync),
+            ("endpoint", &self.endpoint),
+        ];
+        for (name, val) in strs {
+            if let Some(v) = val {
+                vals.insert(name, v.clone());
+            }
+        }
+        match &self.concurrency {
+            Some(ConcurrencyValue::Count(n)) => {
+                vals.insert("concurrency", n.to_string());
+            }
+            Some(ConcurrencyValue::List(list)) => {
+                vals.insert("concurrency", list.clone());
+            }
+            None => (),
+        }
+
+        let nums = vec![
+            ("sleep", self.sleep),
+            ("interval", self.interval),
+            ("max-ops", self.max_ops),
+            ("max-errors", self.max_errors),
+            ("window", self.window),
+            ("queue-max", self.queue_max),
+            ("queue-shards", self.queue_shards),
+            ("min-objects", self.min_objects),
+            ("seed", self.seed),
+            ("retries", self.retries.map(u64::from)),
+            ("breaker-threshold", self.breaker_threshold.map(u64::from)),
+            ("retry-backoff", self.retry_backoff),
+            ("percentage", self.percentage.map(u64::from)),
+            (
+                "multipart-parallelism",
+                self.multipart_parallelism.map(u64::from),
+            ),
+            ("batch-delete", self.batch_delete),
+            ("fs-shard", self.fs_shard.map(u64::from)),
+            ("max-connections", self.max_connections.map(u64::from)),
+        ];
+        for (name, val) in nums {
+            if let Some(v) = val {
+                vals.insert(name, v.to_string());
+            }
+        }
+
+        if let Some(c) = self.compressibility {
+            vals.insert("compressibility", c.to_string());
+        }
+        if let Some(r) = self.max_error_rate {
+            vals.insert("max-error-rate", r.to_string());
+        }
+        if let Some(t) = self.zipf_theta {
+            vals.insert("zipf-theta", t.to_string());
+        }
+
+        let flags = vec![
+            ("verify", self.verify),
+            ("content-md5", self.content_md5),
+            ("no-overwrite", self.no_overwrite),
+            ("verify-delete", self.verify_delete),
+            ("verify-size", self.verify_size),
+            ("shard-by-thread", self.shard_by_thread),
+            ("auto-concurrency", self.auto_concurrency),
+            ("tui", self.tui),
+            ("tabular-header", self.tabular_header),
+            ("statsd-tags", self.statsd_tags),
+            ("debug", self.debug),
+            ("no-sync", self.no_sync),
+            ("fs-mmap", self.fs_mmap),
+            ("fs-direct", self.fs_direct),
+            ("http2", self.http2),
+            ("http2-prior-knowledge", self.http2_prior_knowledge),
+            ("chunked", self.chunked),
+            ("expect-100", self.expect_100),
+            ("insecure", self.insecure),
+            ("pin-dns", self.pin_dns),
+            ("create-bucket", self.create_bucket),
+            ("cleanup", self.cleanup),
+        ];
+        for (name, val) in flags {
+            if val == Some(true) {
+                vals.insert(name, String::new());
+            }
+        }
+
+        vals
+    }
+
+    /* Flags that may be given more than once, e.g. --header. */
+    fn lists(&self) -> HashMap<&'static str, Vec<String>> {
+        let mut lists = HashMap::new();
+        if let Some(headers) = &self.header {
+            lists.insert("header", headers.clone());
+        }
+        lists
+    }
+}
+
+/*
+ * Flags that need another flag, and flags that can't be used together. clap
+ * checks these for the command line, but a flag from the file can still break
+ * them, so they're checked again once the two are merged (see check()).
+ */
+const REQUIRES: &[(&str, &str)] =
+    &[("verify-size", "read-list"), ("statemap-rotate", "debug")];
+const CONFLICTS: &[(&str, &str)] = &[
+    ("think-time", "sleep"),
+    ("auto-concurrency", "ramp"),
+    ("fs-direct", "fs-mmap"),
+];
+
+/*
+ * The command line arguments merged with the config file. This mimics the bits
+ * of clap's ArgMatches that main() uses.
+ */
+pub struct Settings<'a, 'b> {
+    args: &'a ArgMatches<'b>,
+    file: HashMap<&'static str, String>,
+    file_lists: HashMap<&'static str, Vec<String>>,
+}
+
+impl<'a, 'b> Settings<'a, 'b> {
+    pub fn new(args: &'a ArgMatches<'b>, config: &Config) -> Self {
+        Settings {
+            args,
+            file: config.values(),
+            file_lists: config.lists(),
+        }
+    }
+
+    pub fn value_of(&self, name: &str) -> Option<&str> {
+        self.args
+            .value_of(name)
+            .or_else(|| self.file.get(name).map(String::as_str))
+    }
+
+    /*
+     * Values given on the command line replace those in the file, rather than
+     * adding to them.
+     */
+    pub fn values_of(&self, name: &str) -> Vec<&str> {
+        if let Some(vals) = self.args.values_of(name) {
+            return vals.collect();
+        }
+        if let Some(vals) = self.file_lists.get(name) {
+            return vals.iter().map(String::as_str).collect();
+        }
+        self.file
+            .get(name)
+            .map(String::as_str)
+            .into_iter()
+            .collect()
+    }
+
+    pub fn is_present(&self, name: &str) -> bool {
+        self.args.is_present(name) || self.file.contains_key(name)
+    }
+
+    pub fn check(&self) -> Result<(), ChumError> {
+        for (flag, needs) in REQUIRES {
+            if self.is_present(flag) && !self.is_present(needs) {
+                return Err(ChumError::new(&format!(
+                    "{} requires {}",
+                    flag, needs
+                )));
+            }
+        }
+        for (a, b) in CONFLICTS {
+            if self.is_present(a) && self.is_present(b) {
+                return Err(ChumError::new(&format!(
+                    "{} can't be used with {}",
+                    a, b
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+            # A mixed workload against a local server.
+            target = "127.0.0.1:80"
+            concurrency = 50
+            distribution = "128k:8,512k,1m"
+            max-data = "10G"
+            verify = true
+            http2 = false
+            "#,
+        )
+        .unwrap();
+
+        let vals = config.values();
+        assert_eq!(vals.get("target").unwrap(), "127.0.0.1:80");
+        assert_eq!(vals.get("concurrency").unwrap(), "50");
+        assert_eq!(vals.get("distribution").unwrap(), "128k:8,512k,1m");
+        assert_eq!(vals.get("max-data").unwrap(), "10G");
+        assert!(vals.contains_key("verify"));
+
+        /* False flags are the same as not giving the flag at all. */
+        assert!(!vals.contains_key("http2"));
+        assert!(!vals.contains_key("sleep"));
+
+        /* Concurrency may also be given for each target. */
+        let config = Config::parse("concurrency = \"host1:80=8,2\"").unwrap();
+        assert_eq!(config.values().get("concurrency").unwrap(), "host1:80=8,2");
+    }
+
+    /* The file can't get around what clap checks for the command line. */
+    #[test]
+    fn test_settings_check() {
+        let args = ArgMatches::new();
+        let check = |contents: &str| {
+            Settings::new(&args, &Config::parse(contents).unwrap()).check()
+        };
+        assert!(check("verify-size = true\nread-list = \"objs\"").is_ok());
+        assert!(check("statemap-rotate = \"10m\"\ndebug = true").is_ok());
+
+        assert!(check("verify-size = true").is_err());
+        assert!(check("statemap-rotate = \"10m\"").is_err());
+        assert!(check("think-time = \"exp:1s\"\nsleep = 10").is_err());
+        assert!(check("auto-concurrency = true\nramp = \"1m\"").is_err());
+        assert!(check("fs-direct = true\nfs-mmap = true").is_err());
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        let err = Config::parse("concurency = 50").unwrap_err();
+        assert!(err.to_string().contains("concurency"), "{}", err);
+
+        assert!(Config::parse("concurrency = true").is_err());
+    }
+}
@@ -0,0 +1,302 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use crate::utils::{ActiveWorkers, OpsRate, TimestampFormat};
+use crate::worker::{bytes_to_human, Operation, WorkerStat};
+
+/* How many intervals of throughput the sparklines show. */
+const HISTORY: usize = 30;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/* Move the cursor to the top left and clear the screen. */
+const CLEAR: &str = "\x1b[H\x1b[2J";
+
+/* Whether stdout is a terminal, which --tui needs to draw anything. */
+pub fn stdout_is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+/*
+ * A live view of the run for --tui, redrawn in place every interval instead
+ * of scrolling a block of lines. For each operation it shows this interval's
+ * throughput and latency, and a sparkline of objs/s over the last few
+ * intervals, with errors and totals for the whole run underneath.
+ */
+pub struct Dashboard {
+    history: HashMap<Operation, VecDeque<f64>>,
+}
+
+impl Dashboard {
+    pub fn new() -> Dashboard {
+        Dashboard {
+            history: HashMap::new(),
+        }
+    }
+
+    /*
+     * Draw one interval. 'secs' is how long the interval really lasted, which
+     * is what the rates are worked out from.
+     */
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        out: &mut dyn Write,
+        start_time: SystemTime,
+        secs: f64,
+        op_ticks: &HashMap<Operation, WorkerStat>,
+        op_agg: &HashMap<Operation, WorkerStat>,
+        ops_rate: Option<OpsRate>,
+        workers: ActiveWorkers,
+        timestamp: TimestampFormat,
+    ) -> io::Result<()> {
+        self.push(secs, op_ticks, op_agg);
+
+        let elapsed = start_time.elapsed().unwrap().as_secs();
+        write!(out, "{}", CLEAR)?;
+        writeln!(
+            out,
+            "chum: {}m{:02}s elapsed, at {}",
+            elapsed / 60,
+            elapsed % 60,
+            timestamp.format(SystemTime::now())
+        )?;
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:<8} {:>9} {:>14} {:>8} {:>8} {:>8}  objs/s history",
+            "op", "objs/s", "throughput", "p50", "p99", "max"
+        )?;
+
+        let mut ops: Vec<_> = self.history.keys().copied().collect();
+        ops.sort();
+        for op in ops {
+            let (objs, data, p50, p99, max) = match op_ticks.get(&op) {
+                Some(stat) if secs > 0.0 => (
+                    stat.objs as f64 / secs,
+                    (stat.data as f64 / secs) as u64,
+                    stat.rtt_hist.percentile(50.0),
+                    stat.rtt_hist.percentile(99.0),
+                    stat.rtt_hist.max(),
+                ),
+                _ => (0.0, 0, 0, 0, 0),
+            };
+            writeln!(
+                out,
+                "{:<8} {:>9.1} {:>14} {:>6}ms {:>6}ms {:>6}ms  {}",
+                op.to_string(),
+                objs,
+                format!("{}/s", bytes_to_human(data)),
+                p50,
+                p99,
+                max,
+                sparkline(&self.history[&op])
+            )?;
+        }
+        writeln!(out)?;
+
+        let mut errors: Vec<_> = op_agg
+            .iter()
+            .filter_map(|(op, stat)| match op {
+                Operation::Error(kind) => Some((*kind, stat.objs)),
+                _ => None,
+            })
+            .collect();
+        errors.sort();
+        let errors: Vec<_> = errors
+            .iter()
+            .map(|(kind, objs)| format!("{} {}", kind, objs))
+            .collect();
+        writeln!(
+            out,
+            "errors:  {}",
+            if errors.is_empty() {
+                String::from("none")
+            } else {
+                errors.join(", ")
+            }
+        )?;
+        for op in &[Operation::Retry, Operation::Exists, Operation::Substituted]
+        {
+            if let Some(stat) = op_agg.get(op) {
+                writeln!(
+                    out,
+                    "{:<8} {}",
+                    format!("{}:", op.count_label().unwrap()),
+                    stat.objs
+                )?;
+            }
+        }
+
+        let mut totals: Vec<_> = op_agg
+            .iter()
+            .filter(|(op, _)| op.count_label().is_none())
+            .collect();
+        totals.sort_by_key(|(op, _)| **op);
+        for (op, stat) in totals {
+            writeln!(
+                out,
+                "total:   {} {} objects, {}",
+                op,
+                stat.objs,
+                bytes_to_human(stat.data)
+            )?;
+        }
+
+        if let Some(rate) = ops_rate {
+            writeln!(
+                out,
+                "rate:    {:.1} ops/s achieved, {} ops/s target",
+                rate.achieved, rate.target
+            )?;
+        }
+        writeln!(
+            out,
+            "workers: {} of {} active",
+            workers.active, workers.target
+        )?;
+
+        Ok(())
+    }
+
+    /*
+     * Add this interval to the history of every operation seen so far, so an
+     * operation that stops still shows up, and its sparkline drops to nothing.
+     */
+    fn push(
+        &mut self,
+        secs: f64,
+        op_ticks: &HashMap<Operation, WorkerStat>,
+        op_agg: &HashMap<Operation, WorkerStat>,
+    ) {
+        for op in op_agg.keys().filter(|op| op.count_label().is_none()) {
+            self.history.entry(*op).or_default();
+        }
+        for (op, history) in self.history.iter_mut() {
+            let objs = match op_ticks.get(op) {
+                Some(stat) if secs > 0.0 => stat.objs as f64 / secs,
+                _ => 0.0,
+            };
+            if history.len() == HISTORY {
+                history.pop_front();
+            }
+            history.push_back(objs);
+        }
+    }
+}
+
+/* Draw the values as a row of bars, scaled to the biggest of them. */
+fn sparkline(vals: &VecDeque<f64>) -> String {
+    let max = vals.iter().cloned().fold(0.0, f64::max);
+    vals.iter()
+        .map(|v| {
+            if max <= 0.0 {
+                SPARKS[0]
+            } else {
+                let i = (v / max * (SPARKS.len() - 1) as f64).round();
+                SPARKS[i as usize]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ErrorKind;
+    use crate::worker::WorkerInfo;
+    use std::thread;
+
+    fn stats(results: &[(Operation, u128)]) -> HashMap<Operation, WorkerStat> {
+        let mut stats = HashMap::new();
+        for (op, rtt) in results {
+            stats.entry(*op).or_insert_with(WorkerStat::new).add_result(
+                &WorkerInfo {
+                    id: thread::current().id(),
+                    op: *op,
+                    size: 1024,
+                    ttfb: 0,
+                    rtt: *rtt,
+                    continue_ms: None,
+                    new_conn: None,
+                    entries: 0,
+                },
+            );
+        }
+        stats
+    }
+
+    fn workers(active: usize) -> ActiveWorkers {
+        ActiveWorkers { active, target: 2 }
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let vals: VecDeque<f64> = vec![0.0, 1.0, 7.0, 14.0].into();
+        assert_eq!(sparkline(&vals), "▁▂▅█");
+        let vals: VecDeque<f64> = vec![0.0, 0.0].into();
+        assert_eq!(sparkline(&vals), "▁▁");
+    }
+
+    #[test]
+    fn test_dashboard() {
+        let mut dash = Dashboard::new();
+        let tick = stats(&[
+            (Operation::Write, 4),
+            (Operation::Write, 8),
+            (Operation::Error(ErrorKind::Timeout), 0),
+        ]);
+        let mut out = vec![];
+        dash.draw(
+            &mut out,
+            SystemTime::now(),
+            2.0,
+            &tick,
+            &tick,
+            None,
+            workers(1),
+            TimestampFormat::Rfc3339,
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with(CLEAR));
+        assert!(text.contains("write          1.0"), "{}", text);
+        assert!(text.contains("errors:  timeout 1"), "{}", text);
+        assert!(
+            text.contains("total:   write 2 objects, 2.000KB"),
+            "{}",
+            text
+        );
+        assert!(text.contains("workers: 1 of 2 active"), "{}", text);
+
+        /* A quiet interval still shows the operation, with nothing going on. */
+        let mut out = vec![];
+        dash.draw(
+            &mut out,
+            SystemTime::now(),
+            2.0,
+            &HashMap::new(),
+            &tick,
+            None,
+            workers(0),
+            TimestampFormat::Epoch,
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("write          0.0"), "{}", text);
+        assert!(text.contains("workers: 0 of 2 active"), "{}", text);
+        assert_eq!(dash.history[&Operation::Write].len(), 2);
+        assert!(!dash
+            .history
+            .contains_key(&Operation::Error(ErrorKind::Timeout)));
+    }
+}
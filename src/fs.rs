@@ -7,29 +7,220 @@
  */
 
 use crate::state::State;
-use crate::utils::ChumError;
+use crate::utils::{fill_payload, new_rng, ChumError, PayloadReader};
 use crate::worker::*;
 
-use rand::seq::SliceRandom;
-use rand::thread_rng;
-use rand::AsByteSliceMut;
-use rand::Rng;
+use log::debug;
+use rand::rngs::StdRng;
 
 use chrono::{DateTime, Datelike, Utc};
 
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Read, Write};
+use std::os::unix::fs::{FileExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread;
 use std::time::Instant;
 use std::vec::Vec;
 
-use uuid::Uuid;
-
 const DEF_MAX_DIRENTS: u64 = 100_000;
 
+/* Each level of --fs-shard uses two hex digits of the object's UUID. */
+pub const MAX_SHARD_DEPTH: u32 = 16;
+
+/*
+ * What O_DIRECT IO is aligned to, for --fs-direct. Devices may get by with
+ * less (often 512 bytes), but this suits any of them.
+ */
+const DIRECT_ALIGN: usize = 4096;
+
+/*
+ * O_DIRECT is Linux's, other systems have their own ways of skipping the page
+ * cache (e.g. directio(3C) on illumos). Elsewhere --fs-direct is refused.
+ */
+pub const DIRECT_SUPPORTED: bool = cfg!(target_os = "linux");
+#[cfg(target_os = "linux")]
+const O_DIRECT: libc::c_int = libc::O_DIRECT;
+#[cfg(not(target_os = "linux"))]
+const O_DIRECT: libc::c_int = 0;
+
+/*
+ * How much of each write to make durable before the write is counted as done,
+ * from --fs-sync.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FsSync {
+    None, /* leave it to the filesystem to write back. */
+    Data, /* fdatasync(2), the data and only the metadata needed to read it. */
+    Full, /* fsync(2), the data and all of the file's metadata. */
+}
+
+impl std::str::FromStr for FsSync {
+    type Err = ChumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(FsSync::None),
+            "data" => Ok(FsSync::Data),
+            "full" => Ok(FsSync::Full),
+            _ => Err(ChumError::new(&format!(
+                "invalid fs-sync mode '{}', expected none, data, or full",
+                s
+            ))),
+        }
+    }
+}
+
+/*
+ * A read-only mapping of a whole file, for --fs-mmap. It's unmapped when
+ * dropped.
+ */
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    /*
+     * Map 'file', or return None if it can't be mapped so the caller can read
+     * it the usual way. Empty files can't be mapped at all.
+     */
+    fn new(file: &File) -> Option<Mmap> {
+        let len = file.metadata().ok()?.len() as usize;
+        if len == 0 {
+            return None;
+        }
+
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            debug!(
+                "mmap failed, falling back to read: {}",
+                std::io::Error::last_os_error()
+            );
+            return None;
+        }
+        Some(Mmap { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/* Round 'n' up to a multiple of 'to', which must be a power of two. */
+fn round_up(n: usize, to: usize) -> usize {
+    (n + to - 1) & !(to - 1)
+}
+
+/*
+ * A buffer for --fs-direct. O_DIRECT IO has to be done to and from memory
+ * aligned to the device's block size, which a Vec doesn't promise, so this
+ * allocates a little extra and uses the aligned part of it. Its length is
+ * rounded up to a whole number of blocks too.
+ */
+struct AlignedBuf {
+    data: Vec<u8>,
+    start: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    fn new(len: usize) -> AlignedBuf {
+        let len = round_up(len, DIRECT_ALIGN);
+        let data = vec![0u8; len + DIRECT_ALIGN];
+        let start = data.as_ptr().align_offset(DIRECT_ALIGN);
+        AlignedBuf { data, start, len }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data[self.start..self.start + self.len]
+    }
+}
+
+/*
+ * Write a payload to a file opened with O_DIRECT, a buffer at a time. The last
+ * part of an object that isn't a whole number of blocks can't be written with
+ * O_DIRECT, so it's written through the page cache instead.
+ */
+fn write_direct(
+    file: &File,
+    buf: &mut [u8],
+    src: &[u8],
+    size: u64,
+) -> std::io::Result<()> {
+    let mut out = file;
+    let mut offset = 0;
+    while offset < size {
+        let mut n = 0;
+        while n < buf.len() {
+            let copied = fill_payload(&mut buf[n..], src, &mut offset, size);
+            if copied == 0 {
+                break;
+            }
+            n += copied;
+        }
+
+        let whole = n - n % DIRECT_ALIGN;
+        out.write_all(&buf[..whole])?;
+        if whole < n {
+            let fd = file.as_raw_fd();
+            let ret = unsafe {
+                let flags = libc::fcntl(fd, libc::F_GETFL, 0);
+                libc::fcntl(fd, libc::F_SETFL, flags & !O_DIRECT)
+            };
+            if ret == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            out.write_all(&buf[whole..n])?;
+        }
+    }
+    Ok(())
+}
+
+/*
+ * Read 'len' bytes at 'offset' from a file opened with O_DIRECT. The read is
+ * widened to whole blocks on either side, since that's all O_DIRECT can do.
+ */
+fn read_direct_at(file: &File, offset: u64, len: u64) -> std::io::Result<()> {
+    let start = offset - offset % DIRECT_ALIGN as u64;
+    let want = (offset + len - start) as usize;
+    let mut buf = AlignedBuf::new(want);
+    let buf = buf.as_mut_slice();
+
+    let mut done = 0;
+    while done < want {
+        let n = file.read_at(&mut buf[done..], start + done as u64)?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        done += n;
+    }
+    Ok(())
+}
+
 pub struct Fs {
-    buf: Vec<u8>,
+    buf: Arc<Vec<u8>>, /* shared by all workers, see WorkerOptions */
+    direct: Option<AlignedBuf>, /* the IO buffer for --fs-direct */
+    rng: StdRng,
     obj_cnt_dir: u64,
     dir_shard: u32,
     wopts: WorkerOptions,
@@ -37,57 +228,157 @@ pub struct Fs {
 
 impl Fs {
     pub fn new(wopts: WorkerOptions) -> Fs {
-        let mut rng = thread_rng();
-
-        /*
-         * Create a random buffer. This is the data that will be sent
-         * to the target.
-         */
-        let mut buf = [0u8; 65536];
-        rng.fill(&mut buf[..]);
-        let arr = buf.as_byte_slice_mut();
-        let mut vec: Vec<u8> = Vec::new();
-        vec.extend_from_slice(arr);
+        let rng = new_rng(wopts.seed);
+        let direct = if wopts.fs_direct {
+            Some(AlignedBuf::new(wopts.io_buffer))
+        } else {
+            None
+        };
 
         Fs {
-            buf: vec,
+            buf: wopts.payload.clone(),
+            direct,
+            rng,
             obj_cnt_dir: 0,
             dir_shard: 0,
             wopts,
         }
     }
 
-    /* Common function to handle creating filesystem path. */
+    /*
+     * Open a file, with O_DIRECT for --fs-direct. Filesystems that don't
+     * support O_DIRECT (e.g. tmpfs) fail the open with EINVAL, which isn't
+     * much of a hint on its own.
+     */
+    fn open(
+        &self,
+        path: &Path,
+        opts: &mut OpenOptions,
+    ) -> Result<File, ChumError> {
+        if self.direct.is_some() {
+            opts.custom_flags(O_DIRECT);
+        }
+        opts.open(path).map_err(|e| {
+            if self.direct.is_some() && e.raw_os_error() == Some(libc::EINVAL) {
+                ChumError::new(&format!(
+                    "Opening {} with O_DIRECT failed, the filesystem may not \
+                     support --fs-direct: {}",
+                    path.display(),
+                    e
+                ))
+            } else {
+                e.into()
+            }
+        })
+    }
+
+    /*
+     * Common function to handle creating filesystem path.
+     *
+     * Without --fs-shard each worker moves on to a new directory after every
+     * DEF_MAX_DIRENTS objects. With it, objects are spread across nested
+     * directories named after the leading digits of their name instead.
+     */
     fn get_path(&mut self, fname: String) -> PathBuf {
         let today = Utc::today();
-        self.obj_cnt_dir += 1;
-        if self.obj_cnt_dir > DEF_MAX_DIRENTS {
-            self.obj_cnt_dir = 0;
-            self.dir_shard += 1;
-        }
+        let shard = if self.wopts.fs_shard > 0 {
+            shard_dirs(&fname, self.wopts.fs_shard)
+        } else {
+            self.obj_cnt_dir += 1;
+            if self.obj_cnt_dir > DEF_MAX_DIRENTS {
+                self.obj_cnt_dir = 0;
+                self.dir_shard += 1;
+            }
+            self.dir_shard.to_string()
+        };
+        let target = match &self.wopts.prefix {
+            Some(prefix) => format!("{}/{}", self.wopts.target, prefix),
+            None => self.wopts.target.clone(),
+        };
         Path::new(&format!(
             "{}/{}/{}{}/{}/{}",
-            self.wopts.target,
+            target,
             today.year(),
             today.month(),
             today.day(),
-            self.dir_shard,
+            shard,
             fname
         ))
         .to_path_buf()
     }
 
+    fn append_to(
+        &mut self,
+        fname: &str,
+    ) -> Result<Option<WorkerInfo>, ChumError> {
+        let (src, size) =
+            self.wopts.choose_payload(&self.buf, &mut self.rng)?;
+
+        /*
+         * Appends almost never land on an aligned offset, so O_DIRECT isn't
+         * used here even with --fs-direct.
+         */
+        let mut begin = Utc::now();
+        let rtt_start = Instant::now();
+        let file =
+            OpenOptions::new().append(true).open(fname).map_err(|e| {
+                ChumError::new(&format!("Opening {} failed: {}", fname, e))
+            })?;
+        let mut end = Utc::now();
+        self.send_state(Operation::Append, "open", Some(size), begin, end);
+
+        begin = Utc::now();
+        let mut bw = BufWriter::with_capacity(self.wopts.io_buffer, &file);
+        std::io::copy(&mut PayloadReader::new(&src, size), &mut bw)?;
+        bw.flush()?;
+        drop(bw);
+        end = Utc::now();
+        self.send_state(Operation::Append, "write", Some(size), begin, end);
+
+        begin = Utc::now();
+        let synced = match self.wopts.fs_sync {
+            FsSync::None => None,
+            FsSync::Data => Some(("fdatasync", file.sync_data())),
+            FsSync::Full => Some(("fsync", file.sync_all())),
+        };
+        if let Some((call, res)) = synced {
+            if let Err(e) = res {
+                return Err(ChumError::new(&format!("{} failed: {}", call, e)));
+            }
+            end = Utc::now();
+            self.send_state(Operation::Append, call, Some(size), begin, end);
+        }
+        let rtt = rtt_start.elapsed().as_millis();
+
+        self.wopts.append_checksum(fname, &src, size);
+
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Append,
+            size,
+            ttfb: 0,
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }))
+    }
+
     #[allow(clippy::single_match)]
     fn send_state(
         &self,
+        op: Operation,
         state: &str,
+        size: Option<u64>,
         begin: DateTime<Utc>,
         end: DateTime<Utc>,
     ) {
         if let Some(c) = &self.wopts.debug_tx {
             match c.send(State {
                 host: format!("{:?}", thread::current().id()),
+                op,
                 state: state.to_owned(),
+                size,
                 start_time: begin,
                 end_time: end,
             }) {
@@ -100,15 +391,12 @@ impl Fs {
 
 impl Backend for Fs {
     fn write(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
-        let fname = Uuid::new_v4();
-        let mut rng = thread_rng();
-        let size = *self
-            .wopts
-            .distribution
-            .choose(&mut rng)
-            .expect("choosing file size failed");
-
-        let full_path = self.get_path(fname.to_string());
+        let fname = self.wopts.namer.name();
+        /* Randomly choose a file size from the list (or a source file). */
+        let (src, size) =
+            self.wopts.choose_payload(&self.buf, &mut self.rng)?;
+
+        let full_path = self.get_path(fname);
         let mut begin: DateTime<Utc>;
         let mut end: DateTime<Utc>;
 
@@ -129,14 +417,20 @@ impl Backend for Fs {
              */
         }
         end = Utc::now();
-        self.send_state("write::mkdir", begin, end);
+        self.send_state(Operation::Write, "mkdir", Some(size), begin, end);
 
         begin = Utc::now();
-        let file = File::create(&full_path)?;
+        let file = self.open(
+            &full_path,
+            OpenOptions::new().write(true).create(true).truncate(true),
+        )?;
         end = Utc::now();
-        self.send_state("write::open", begin, end);
+        self.send_state(Operation::Write, "open", Some(size), begin, end);
 
-        let p = full_path.into_os_string().into_string().expect("failed to convert path to string");
+        let p = full_path
+            .into_os_string()
+            .into_string()
+            .expect("failed to convert path to string");
 
         /*
          * Add fallocate support?
@@ -150,84 +444,63 @@ impl Backend for Fs {
          * }
          */
 
-        let mut bw = BufWriter::new(&file);
-
-        let mut buf: Vec<u8> = Vec::with_capacity(size as usize);
-        let mut bytes_to_go = size;
-        while bytes_to_go > 0 {
-            if bytes_to_go < self.buf.len() as u64 {
-                let tail = &self.buf[0..(bytes_to_go - 1) as usize];
-                buf.extend(tail);
-                break;
-            }
-            buf.extend(&self.buf);
-            bytes_to_go -= self.buf.len() as u64;
-        }
-
         /*
-         * Write the data to the file and then optionally issue an fsync.
+         * Write the data to the file and then optionally issue an fsync or
+         * fdatasync, depending on --fs-sync.
          *
          * Durability is a constraint, not a feature, at least in this
          * implementor's opinion.
          */
         begin = Utc::now();
-        bw.write_all(&buf)?;
-        bw.flush()?;
+        match &mut self.direct {
+            Some(buf) => write_direct(&file, buf.as_mut_slice(), &src, size)?,
+            None => {
+                let mut bw =
+                    BufWriter::with_capacity(self.wopts.io_buffer, &file);
+                std::io::copy(&mut PayloadReader::new(&src, size), &mut bw)?;
+                bw.flush()?;
+            }
+        }
         end = Utc::now();
-        self.send_state("write::write", begin, end);
+        self.send_state(Operation::Write, "write", Some(size), begin, end);
 
-        if self.wopts.sync {
-            begin = Utc::now();
-            match file.sync_all() {
-                Err(e) => Err(ChumError::new(&format!("fsync failed: {}", e))),
-                Ok(_) => {
-                    if self.wopts.read_queue {
-                        self.wopts
-                            .queue
-                            .lock()
-                            .unwrap()
-                            .insert(p)
-                    }
-
-                    let rtt = rtt_start.elapsed().as_millis();
-                    end = Utc::now();
-                    self.send_state("write::fsync", begin, end);
-
-                    Ok(Some(WorkerInfo {
-                        id: thread::current().id(),
-                        op: Operation::Write,
-                        size,
-                        ttfb: 0, /* not supported */
-                        rtt,
-                    }))
-                }
+        begin = Utc::now();
+        let synced = match self.wopts.fs_sync {
+            FsSync::None => None,
+            FsSync::Data => Some(("fdatasync", file.sync_data())),
+            FsSync::Full => Some(("fsync", file.sync_all())),
+        };
+        if let Some((call, res)) = synced {
+            if let Err(e) = res {
+                return Err(ChumError::new(&format!("{} failed: {}", call, e)));
             }
-        } else {
-            self.wopts.queue.lock().unwrap().insert(p);
+            end = Utc::now();
+            self.send_state(Operation::Write, call, Some(size), begin, end);
+        }
+        let rtt = rtt_start.elapsed().as_millis();
 
-            let rtt = rtt_start.elapsed().as_millis();
-            Ok(Some(WorkerInfo {
-                id: thread::current().id(),
-                op: Operation::Write,
-                size,
-                ttfb: 0, /* not supported */
-                rtt,
-            }))
+        self.wopts.record_checksum(&p, &src, size);
+        if self.wopts.read_queue {
+            self.wopts.queue.insert(p)
         }
+
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Write,
+            size,
+            ttfb: 0, /* not supported */
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }))
     }
 
     fn read(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
-        let fname: String;
-        {
-            let mut q = self.wopts.queue.lock().unwrap();
-            let qi = q.get();
-            if qi.is_none() {
-                return Ok(None);
-            }
-            let qi = qi.unwrap();
-
-            fname = qi.clone();
-        }
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
 
         let mut begin: DateTime<Utc>;
         let mut end: DateTime<Utc>;
@@ -235,37 +508,147 @@ impl Backend for Fs {
         let rtt_start = Instant::now();
 
         let mut buf = Vec::new();
+        let mut hasher = crc32fast::Hasher::new();
         begin = Utc::now();
-        let mut file = File::open(fname)?;
+        let mut file =
+            self.open(Path::new(&fname), OpenOptions::new().read(true))?;
         end = Utc::now();
-        self.send_state("read::open", begin, end);
+        let len = file.metadata()?.len();
+        self.send_state(Operation::Read, "open", Some(len), begin, end);
 
-        begin = Utc::now();
-        let size = file.read_to_end(&mut buf)?;
-        end = Utc::now();
-        self.send_state("read::read", begin, end);
+        /* Read part of the file if the user asked for range reads. */
+        let range = if self.wopts.partial_reads() {
+            self.wopts.choose_read(len, &mut self.rng)
+        } else {
+            None
+        };
+
+        /*
+         * With --fs-mmap the data is copied out of a mapping of the file, so
+         * it's paged in by faults rather than read(2) calls. The copy is done
+         * --io-buffer bytes at a time to match what read(2) would do.
+         */
+        let mapped = if self.wopts.fs_mmap {
+            begin = Utc::now();
+            let mapped = Mmap::new(&file);
+            end = Utc::now();
+            self.send_state(Operation::Read, "mmap", Some(len), begin, end);
+            mapped
+        } else {
+            None
+        };
+
+        let size = match (range, &mapped) {
+            (Some((offset, len)), Some(m)) => {
+                let data =
+                    &m.as_slice()[offset as usize..(offset + len) as usize];
+                buf.resize(self.wopts.io_buffer, 0);
+                begin = Utc::now();
+                for chunk in data.chunks(buf.len()) {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                }
+                end = Utc::now();
+                self.send_state(
+                    Operation::Read,
+                    "fault",
+                    Some(len),
+                    begin,
+                    end,
+                );
+                data.len()
+            }
+            (None, Some(m)) => {
+                let data = m.as_slice();
+                let verify = self.wopts.checksums.is_some();
+                buf.resize(self.wopts.io_buffer, 0);
+                begin = Utc::now();
+                for chunk in data.chunks(buf.len()) {
+                    buf[..chunk.len()].copy_from_slice(chunk);
+                    if verify {
+                        hasher.update(chunk);
+                    }
+                }
+                end = Utc::now();
+                self.send_state(
+                    Operation::Read,
+                    "fault",
+                    Some(len),
+                    begin,
+                    end,
+                );
+                data.len()
+            }
+            (Some((offset, len)), None) => {
+                begin = Utc::now();
+                if self.direct.is_some() {
+                    read_direct_at(&file, offset, len)?;
+                } else {
+                    buf.resize(len as usize, 0);
+                    file.read_exact_at(&mut buf, offset)?;
+                }
+                end = Utc::now();
+                self.send_state(
+                    Operation::Read,
+                    "pread",
+                    Some(len),
+                    begin,
+                    end,
+                );
+                len as usize
+            }
+            (None, None) => {
+                /* Read the file --io-buffer bytes at a time. */
+                let verify = self.wopts.checksums.is_some();
+                let mut size = 0;
+                let rbuf = match &mut self.direct {
+                    Some(direct) => direct.as_mut_slice(),
+                    None => {
+                        buf.resize(self.wopts.io_buffer, 0);
+                        &mut buf[..]
+                    }
+                };
+                begin = Utc::now();
+                loop {
+                    let n = file.read(rbuf)?;
+                    if n == 0 {
+                        break;
+                    }
+                    if verify {
+                        hasher.update(&rbuf[..n]);
+                    }
+                    size += n;
+                }
+                end = Utc::now();
+                self.send_state(Operation::Read, "read", Some(len), begin, end);
+                size
+            }
+        };
 
         let rtt = rtt_start.elapsed().as_millis();
 
+        /* We can only verify whole objects. */
+        if range.is_none() {
+            self.wopts.verify_checksum(&fname, || hasher.finalize())?;
+            self.wopts.verify_size(&fname, size as u64)?;
+        }
+
         Ok(Some(WorkerInfo {
             id: thread::current().id(),
             op: Operation::Read,
             size: size as u64,
             ttfb: 0,
             rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
         }))
     }
 
     fn delete(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
-        let fname: String;
-        {
-            let mut q = self.wopts.queue.lock().unwrap();
-            let qi = q.remove();
-            if qi.is_none() {
-                return Ok(None);
-            }
-            fname = qi.unwrap();
-        }
+        let fname = match self.wopts.queue.remove_by(self.wopts.delete_policy) {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
         let begin: DateTime<Utc>;
         let end: DateTime<Utc>;
 
@@ -274,10 +657,10 @@ impl Backend for Fs {
 
         let res = std::fs::remove_file(&fname);
         end = Utc::now();
-        self.send_state("delete::rm", begin, end);
+        self.send_state(Operation::Delete, "rm", None, begin, end);
 
         if let Err(e) = res {
-            self.wopts.queue.lock().unwrap().insert(fname.clone());
+            self.wopts.queue.insert(fname.clone());
 
             return Err(ChumError::new(&format!(
                 "Deleting {} \
@@ -287,6 +670,13 @@ impl Backend for Fs {
         }
 
         let rtt = rtt_start.elapsed().as_millis();
+        self.wopts.forget_checksum(&fname);
+        if self.wopts.verify_delete && Path::new(&fname).exists() {
+            return Err(ChumError::undeleted(&format!(
+                "{} still exists after being deleted",
+                fname
+            )));
+        }
 
         Ok(Some(WorkerInfo {
             id: thread::current().id(),
@@ -294,6 +684,180 @@ impl Backend for Fs {
             size: 0,
             ttfb: 0,
             rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }))
+    }
+    /*
+     * Objects are spread across many directories, so list the directory that
+     * one of the objects we know about is in.
+     */
+    fn list(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let dir = match Path::new(&fname).parent() {
+            Some(parent) => parent.to_path_buf(),
+            None => return Ok(None),
+        };
+
+        let begin = Utc::now();
+        let rtt_start = Instant::now();
+        let entries = std::fs::read_dir(&dir)
+            .and_then(|rd| rd.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| {
+                ChumError::new(&format!(
+                    "Listing {} failed: {}",
+                    dir.display(),
+                    e
+                ))
+            })?
+            .len();
+        let rtt = rtt_start.elapsed().as_millis();
+        let end = Utc::now();
+        self.send_state(Operation::List, "readdir", None, begin, end);
+
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::List,
+            size: 0,
+            ttfb: 0,
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: entries as u64,
         }))
     }
+
+    fn stat(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+
+        let begin = Utc::now();
+        let rtt_start = Instant::now();
+        let len = std::fs::metadata(&fname)
+            .map_err(|e| {
+                ChumError::new(&format!("Stat of {} failed: {}", fname, e))
+            })?
+            .len();
+        let rtt = rtt_start.elapsed().as_millis();
+        let end = Utc::now();
+        self.send_state(Operation::Stat, "stat", Some(len), begin, end);
+
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Stat,
+            size: 0,
+            ttfb: 0,
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }))
+    }
+
+    /*
+     * Append a chunk of payload to an object, e.g. like a log file. The
+     * object is taken out of the queue while it's appended to, so that no
+     * other thread reads it half appended, or appends to it at the same time
+     * (which would leave its checksum in the wrong order).
+     */
+    fn append(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.remove() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let res = self.append_to(&fname);
+
+        /* Some of the data may have made it, so there's nothing to verify. */
+        if res.is_err() {
+            self.wopts.forget_checksum(&fname);
+        }
+        self.wopts.queue.insert(fname);
+        res
+    }
+
+    /* There's no server to copy for us, so this reads and writes the file. */
+    fn copy(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let full_path = self.get_path(self.wopts.namer.name());
+        let copy = full_path.to_str().unwrap().to_string();
+
+        let begin = Utc::now();
+        let rtt_start = Instant::now();
+        if let Some(dir) = full_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let len = std::fs::copy(&fname, &full_path).map_err(|e| {
+            ChumError::new(&format!("Copy of {} failed: {}", fname, e))
+        })?;
+        let rtt = rtt_start.elapsed().as_millis();
+        let end = Utc::now();
+        self.send_state(Operation::Copy, "copy", Some(len), begin, end);
+
+        self.wopts.copy_checksum(&fname, &copy);
+        if self.wopts.read_queue {
+            self.wopts.queue.insert(copy);
+        }
+
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Copy,
+            size: 0,
+            ttfb: 0,
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }))
+    }
+}
+
+/*
+ * The directories an object goes in with --fs-shard, e.g. 'ab/cd' for object
+ * 'abcd1234-...' with a depth of 2. Dashes in the name are skipped.
+ */
+fn shard_dirs(fname: &str, depth: u32) -> String {
+    let digits: Vec<char> = fname.chars().filter(|c| *c != '-').collect();
+    digits
+        .chunks(2)
+        .take(depth as usize)
+        .map(|c| c.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aligned_buf() {
+        for len in &[1, 1024, DIRECT_ALIGN, DIRECT_ALIGN + 1] {
+            let mut buf = AlignedBuf::new(*len);
+            let buf = buf.as_mut_slice();
+            assert_eq!(buf.as_ptr() as usize % DIRECT_ALIGN, 0);
+            assert_eq!(buf.len() % DIRECT_ALIGN, 0);
+            assert!(buf.len() >= *len && buf.len() < len + DIRECT_ALIGN);
+        }
+    }
+
+    #[test]
+    fn test_shard_dirs() {
+        let name = "abcdef12-3456-7890-abcd-ef1234567890";
+        assert_eq!(shard_dirs(name, 1), "ab");
+        assert_eq!(shard_dirs(name, 2), "ab/cd");
+        assert_eq!(shard_dirs(name, 5), "ab/cd/ef/12/34");
+        assert_eq!(
+            shard_dirs(name, MAX_SHARD_DEPTH),
+            "ab/cd/ef/12/34/56/78/90/ab/cd/ef/12/34/56/78/90"
+        );
+    }
 }
@@ -0,0 +1,244 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+/*
+ * Values below this are counted exactly, one bucket per value.
+ */
+const LINEAR_BUCKETS: u64 = 64;
+
+/*
+ * Each power of two above LINEAR_BUCKETS is split into this many buckets. This
+ * bounds the error of a reported value to roughly 1/SUB_BUCKETS (~3%).
+ */
+const SUB_BUCKETS: u64 = 32;
+const SUB_BUCKET_BITS: u32 = 5;
+const LINEAR_BITS: u32 = 6;
+
+/*
+ * A bounded, log-linear latency histogram in the spirit of HDR histograms.
+ *
+ * Small values (the common case for millisecond latencies) are counted
+ * exactly. Larger values are bucketed by their power of two and then by the
+ * next few most significant bits. The bucket array only grows as large as the
+ * largest value recorded requires, so empty histograms are cheap to create and
+ * throw away every interval.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: u64,
+}
+
+fn bucket_index(val: u64) -> usize {
+    if val < LINEAR_BUCKETS {
+        return val as usize;
+    }
+
+    let exp = 63 - val.leading_zeros();
+    let sub = (val >> (exp - SUB_BUCKET_BITS)) & (SUB_BUCKETS - 1);
+    (LINEAR_BUCKETS + u64::from(exp - LINEAR_BITS) * SUB_BUCKETS + sub) as usize
+}
+
+/* The largest value that lands in the given bucket. */
+fn bucket_ceiling(idx: usize) -> u64 {
+    let idx = idx as u64;
+    if idx < LINEAR_BUCKETS {
+        return idx;
+    }
+
+    let exp = (idx - LINEAR_BUCKETS) / SUB_BUCKETS + u64::from(LINEAR_BITS);
+    let sub = (idx - LINEAR_BUCKETS) % SUB_BUCKETS;
+    let width = 1u64 << (exp - u64::from(SUB_BUCKET_BITS));
+    ((1u64 << exp) - 1) + (sub + 1) * width
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            counts: Vec::new(),
+            total: 0,
+            max: 0,
+        }
+    }
+
+    pub fn record(&mut self, val: u64) {
+        let idx = bucket_index(val);
+        if idx >= self.counts.len() {
+            self.counts.resize(idx + 1, 0);
+        }
+        self.counts[idx] += 1;
+        self.total += 1;
+        if val > self.max {
+            self.max = val;
+        }
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (i, cnt) in other.counts.iter().enumerate() {
+            self.counts[i] += cnt;
+        }
+        self.total += other.total;
+        if other.max > self.max {
+            self.max = other.max;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.clear();
+        self.total = 0;
+        self.max = 0;
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /*
+     * Return how many values were at most 'val'. Like percentile() this works
+     * in whole buckets, so values in the bucket that 'val' falls in are only
+     * counted if the whole bucket is at or below 'val'.
+     */
+    pub fn count_at_most(&self, val: u64) -> u64 {
+        self.counts
+            .iter()
+            .enumerate()
+            .take_while(|(i, _)| bucket_ceiling(*i) <= val)
+            .map(|(_, cnt)| cnt)
+            .sum()
+    }
+
+    /*
+     * Return the value at the given percentile (e.g. 99.0). The value
+     * returned is the top of the bucket that the percentile falls in, so it
+     * may overstate the real value slightly, but never by more than the
+     * bucket width and never beyond the largest value recorded.
+     */
+    pub fn percentile(&self, pct: f64) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+
+        let rank = ((pct / 100.0) * self.total as f64).ceil() as u64;
+        let rank = std::cmp::max(rank, 1);
+
+        let mut seen = 0;
+        for (i, cnt) in self.counts.iter().enumerate() {
+            seen += cnt;
+            if seen >= rank {
+                return std::cmp::min(bucket_ceiling(i), self.max);
+            }
+        }
+        self.max
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_empty() {
+        let h = Histogram::new();
+        assert_eq!(h.max(), 0);
+        assert_eq!(h.percentile(50.0), 0);
+        assert_eq!(h.percentile(99.0), 0);
+    }
+
+    #[test]
+    fn test_histogram_exact_small_values() {
+        let mut h = Histogram::new();
+        for v in 1..=10 {
+            h.record(v);
+        }
+        assert_eq!(h.percentile(50.0), 5);
+        assert_eq!(h.percentile(90.0), 9);
+        assert_eq!(h.percentile(100.0), 10);
+        assert_eq!(h.max(), 10);
+    }
+
+    #[test]
+    fn test_histogram_percentiles() {
+        let mut h = Histogram::new();
+        for v in 1..=10_000 {
+            h.record(v);
+        }
+
+        /* Allow for the bucket resolution. */
+        let within = |got: u64, want: u64| {
+            let err = (got as f64 - want as f64).abs() / want as f64;
+            assert!(err < 0.04, "got {} want {}", got, want);
+        };
+        within(h.percentile(50.0), 5000);
+        within(h.percentile(90.0), 9000);
+        within(h.percentile(99.0), 9900);
+        assert_eq!(h.percentile(100.0), 10_000);
+        assert_eq!(h.max(), 10_000);
+    }
+
+    #[test]
+    fn test_histogram_bucket_bounds() {
+        for v in (0..100_000).chain(vec![u64::MAX / 2, u64::MAX]) {
+            let idx = bucket_index(v);
+            assert!(bucket_ceiling(idx) >= v);
+            if idx > 0 {
+                assert!(bucket_ceiling(idx - 1) < v);
+            }
+        }
+    }
+
+    #[test]
+    fn test_histogram_count_at_most() {
+        let mut h = Histogram::new();
+        for v in 1..=1000 {
+            h.record(v);
+        }
+        assert_eq!(h.count_at_most(0), 0);
+        assert_eq!(h.count_at_most(10), 10);
+        assert_eq!(h.count_at_most(63), 63);
+        assert_eq!(h.count_at_most(u64::MAX), 1000);
+
+        /*
+         * Larger values share buckets, and a bucket that 'val' falls in the
+         * middle of isn't counted.
+         */
+        for val in &[250, 1000] {
+            let n = h.count_at_most(*val);
+            assert!(n <= *val && n as f64 > *val as f64 * 0.96, "{}", n);
+        }
+    }
+
+    #[test]
+    fn test_histogram_merge() {
+        let mut a = Histogram::new();
+        let mut b = Histogram::new();
+        let mut all = Histogram::new();
+        for v in 0..500 {
+            a.record(v);
+            all.record(v);
+        }
+        for v in 500..5000 {
+            b.record(v);
+            all.record(v);
+        }
+
+        a.merge(&b);
+        assert_eq!(a.total, all.total);
+        assert_eq!(a.max(), all.max());
+        for pct in &[50.0, 90.0, 99.0, 100.0] {
+            assert_eq!(a.percentile(*pct), all.percentile(*pct));
+        }
+
+        a.clear();
+        assert_eq!(a.total, 0);
+        assert_eq!(a.percentile(50.0), 0);
+    }
+}
@@ -0,0 +1,328 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use crate::utils::{fill_payload, new_rng, range_header, ChumError};
+use crate::webdav::{
+    header_list, is_continue, new_client, opened_connection, verify_deleted,
+    write_error, write_exists, write_header_list,
+};
+use crate::worker::{Backend, Operation, WorkerInfo, WorkerOptions};
+
+use curl::easy::Easy;
+
+use rand::rngs::StdRng;
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use std::vec::Vec;
+
+/* Object names are substituted into the target URL in place of this. */
+pub const NAME_PLACEHOLDER: &str = "{name}";
+
+/*
+ * A backend for plain RESTful object services. Objects are written with PUT,
+ * read with GET, and removed with DELETE, at a URL built from the target
+ * template, e.g. 'http://host:8080/objects/{name}'.
+ */
+pub struct Http {
+    buf: Arc<Vec<u8>>, /* shared by all workers, see WorkerOptions */
+    rng: StdRng,
+    client: Easy,
+    wopts: WorkerOptions,
+}
+
+impl Http {
+    pub fn new(wopts: WorkerOptions) -> Http {
+        let rng = new_rng(wopts.seed);
+
+        Http {
+            buf: wopts.payload.clone(),
+            rng,
+            client: new_client(&wopts),
+            wopts,
+        }
+    }
+}
+
+impl Backend for Http {
+    fn write(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let client = &mut self.client;
+
+        let fname = match &self.wopts.prefix {
+            Some(prefix) => format!("{}/{}", prefix, self.wopts.namer.name()),
+            None => self.wopts.namer.name(),
+        };
+        let full_path = get_path(&self.wopts.target, &fname);
+
+        /* Randomly choose a file size from the list (or a source file). */
+        let (src, size) =
+            self.wopts.choose_payload(&self.buf, &mut self.rng)?;
+
+        /*
+         * The handle is reused for every request, so set the method each time
+         * rather than relying on what the last request left behind.
+         */
+        client.url(&full_path)?;
+        client.http_headers(write_header_list(&self.wopts, &src, size)?)?;
+        client.custom_request("PUT")?;
+        client.upload(true)?;
+        if !self.wopts.chunked {
+            client.in_filesize(size)?;
+        }
+
+        let b = &src;
+        let mut offset = 0;
+        let start = Instant::now();
+        let mut continue_ms = None;
+        {
+            let mut transfer = client.transfer();
+            transfer.read_function(|into| {
+                Ok(fill_payload(into, b, &mut offset, size))
+            })?;
+            if self.wopts.expect_100 {
+                transfer.header_function(|header| {
+                    if continue_ms.is_none() && is_continue(header) {
+                        continue_ms = Some(start.elapsed().as_millis() as u64);
+                    }
+                    true
+                })?;
+            }
+            transfer.perform()?;
+        }
+
+        let code = client.response_code()?;
+        let ttfb = client.starttransfer_time()?.as_millis();
+        let rtt = client.total_time()?.as_millis();
+        let new_conn = opened_connection(client);
+        if self.wopts.ok_status.write.contains(&code) {
+            self.wopts.record_checksum(&fname, &src, size);
+            if self.wopts.read_queue {
+                self.wopts.queue.insert(fname);
+            }
+            Ok(Some(WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Write,
+                size,
+                ttfb,
+                rtt,
+                continue_ms,
+                new_conn,
+                entries: 0,
+            }))
+        } else if code == 412 && self.wopts.no_overwrite {
+            Ok(Some(write_exists(&mut self.client)?))
+        } else {
+            Err(write_error(&self.wopts, &full_path, code))
+        }
+    }
+
+    fn read(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let full_path = get_path(&self.wopts.target, &fname);
+
+        self.client.url(&full_path)?;
+        self.client.http_headers(header_list(&self.wopts)?)?;
+
+        /*
+         * For range reads we need to know how big the object is to pick a
+         * range within it, so ask the server first. The HEAD isn't counted
+         * in the latency of the read.
+         */
+        let mut headers = header_list(&self.wopts)?;
+        let mut ranged = false;
+        if self.wopts.partial_reads() {
+            self.client.custom_request("HEAD")?;
+            self.client.get(true)?;
+            self.client.nobody(true)?;
+            self.client.perform()?;
+            let code = self.client.response_code()?;
+            if !self.wopts.ok_status.read.contains(&code) {
+                return Err(ChumError::http_status(
+                    &format!("Reading {} failed", full_path),
+                    code,
+                ));
+            }
+            let objsize = self.client.content_length_download()?;
+            if objsize > 0.0 {
+                if let Some((offset, len)) =
+                    self.wopts.choose_read(objsize as u64, &mut self.rng)
+                {
+                    headers.append(&format!(
+                        "Range: {}",
+                        range_header(offset, len)
+                    ))?;
+                    ranged = true;
+                }
+            }
+        }
+
+        let client = &mut self.client;
+        client.http_headers(headers)?;
+        client.custom_request("GET")?;
+        client.get(true)?;
+
+        /* We can only verify whole objects. */
+        let verify = self.wopts.checksums.is_some() && !ranged;
+        let mut hasher = crc32fast::Hasher::new();
+
+        let mut size = 0;
+        {
+            let mut transfer = client.transfer();
+            transfer.write_function(|data| {
+                size += data.len();
+                if verify {
+                    hasher.update(data);
+                }
+                Ok(data.len())
+            })?;
+            transfer.perform()?;
+        }
+
+        let code = client.response_code()?;
+        let ttfb = client.starttransfer_time()?.as_millis();
+        let rtt = client.total_time()?.as_millis();
+        let new_conn = opened_connection(client);
+        if self.wopts.ok_status.read.contains(&code) {
+            if verify {
+                self.wopts.verify_checksum(&fname, || hasher.finalize())?;
+            }
+            if !ranged {
+                self.wopts.verify_size(&fname, size as u64)?;
+            }
+            Ok(Some(WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Read,
+                size: size as u64,
+                ttfb,
+                rtt,
+                continue_ms: None,
+                new_conn,
+                entries: 0,
+            }))
+        } else {
+            Err(ChumError::http_status(
+                &format!("Reading {} failed", full_path),
+                code,
+            ))
+        }
+    }
+
+    fn delete(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.remove_by(self.wopts.delete_policy) {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let full_path = get_path(&self.wopts.target, &fname);
+
+        let client = &mut self.client;
+        client.url(&full_path)?;
+        client.http_headers(header_list(&self.wopts)?)?;
+        client.custom_request("DELETE")?;
+        client.get(true)?;
+        let res = client.perform();
+
+        let code = client.response_code()?;
+        if res.is_ok() && self.wopts.ok_status.delete.contains(&code) {
+            let ttfb = self.client.starttransfer_time()?.as_millis();
+            let rtt = self.client.total_time()?.as_millis();
+            let new_conn = opened_connection(&mut self.client);
+            self.wopts.forget_checksum(&fname);
+            if self.wopts.verify_delete {
+                verify_deleted(&mut self.client, &self.wopts, &full_path)?;
+            }
+            Ok(Some(WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Delete,
+                size: 0,
+                ttfb,
+                rtt,
+                continue_ms: None,
+                new_conn,
+                entries: 0,
+            }))
+        } else {
+            /*
+             * Re-insert the object to make it available for future read or
+             * delete operations.
+             */
+            self.wopts.queue.insert(fname);
+            match res {
+                Err(e) => Err(e.into()),
+                Ok(_) => Err(ChumError::http_status(
+                    &format!("Deleting {} failed", full_path),
+                    code,
+                )),
+            }
+        }
+    }
+
+    /* A metadata-only request for an object we wrote. */
+    fn stat(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let full_path = get_path(&self.wopts.target, &fname);
+
+        let client = &mut self.client;
+        client.url(&full_path)?;
+        client.http_headers(header_list(&self.wopts)?)?;
+        client.custom_request("HEAD")?;
+        client.get(true)?;
+        client.nobody(true)?;
+        client.perform()?;
+
+        let code = client.response_code()?;
+        if self.wopts.ok_status.read.contains(&code) {
+            let ttfb = self.client.starttransfer_time()?.as_millis();
+            let rtt = self.client.total_time()?.as_millis();
+            let new_conn = opened_connection(&mut self.client);
+            Ok(Some(WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Stat,
+                size: 0,
+                ttfb,
+                rtt,
+                continue_ms: None,
+                new_conn,
+                entries: 0,
+            }))
+        } else {
+            Err(ChumError::http_status(
+                &format!("Getting metadata for {} failed", full_path),
+                code,
+            ))
+        }
+    }
+}
+
+/* Substitute the object name into the target URL template. */
+fn get_path(template: &str, fname: &str) -> String {
+    template.replace(NAME_PLACEHOLDER, fname)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_path() {
+        assert_eq!(
+            get_path("http://host:8080/objects/{name}", "abcd"),
+            "http://host:8080/objects/abcd"
+        );
+        assert_eq!(
+            get_path("https://host/{name}?owner=chum", "run2/abcd"),
+            "https://host/run2/abcd?owner=chum"
+        );
+    }
+}
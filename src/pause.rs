@@ -0,0 +1,143 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+/* How often paused threads check whether they can carry on. */
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+
+struct State {
+    since: Option<Instant>, /* when the current pause started */
+    total: Duration,        /* time spent in pauses that are over */
+    stopped: bool,
+}
+
+/*
+ * Pauses and resumes the workload, shared by all worker threads. Each SIGUSR1
+ * flips it, and while it's paused workers wait in wait() instead of starting
+ * another operation, so that an operator can see how the target recovers
+ * from load. Operations that were in flight when it paused still finish.
+ *
+ * The stat thread keeps reporting while the workload is paused, and takes
+ * the time spent paused out of its rates, see paused_at().
+ */
+pub struct PauseSwitch {
+    state: Mutex<State>,
+}
+
+impl PauseSwitch {
+    pub fn new() -> PauseSwitch {
+        PauseSwitch {
+            state: Mutex::new(State {
+                since: None,
+                total: Duration::from_secs(0),
+                stopped: false,
+            }),
+        }
+    }
+
+    /* Pause if running, or resume if paused. Returns whether it's paused. */
+    pub fn toggle(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.stopped {
+            return false;
+        }
+        match state.since.take() {
+            Some(since) => {
+                let paused = since.elapsed();
+                state.total += paused;
+                info!("resumed after {:.1}s paused", paused.as_secs_f64());
+                false
+            }
+            None => {
+                state.since = Some(Instant::now());
+                info!("paused, send SIGUSR1 again to resume");
+                true
+            }
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().since.is_some()
+    }
+
+    /*
+     * Resume for good, once the run is over, so that paused workers notice
+     * that it's over and exit.
+     */
+    pub fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stopped = true;
+        if let Some(since) = state.since.take() {
+            state.total += since.elapsed();
+        }
+    }
+
+    /* Block while paused. */
+    pub fn wait(&self) {
+        while self.is_paused() {
+            thread::sleep(PAUSE_POLL);
+        }
+    }
+
+    /*
+     * How long the workload had spent paused as of 'now', counting a pause
+     * that's still going on. The time paused between two instants is the
+     * difference between this at each of them.
+     */
+    pub fn paused_at(&self, now: Instant) -> Duration {
+        let state = self.state.lock().unwrap();
+        match state.since {
+            Some(since) => state.total + now.saturating_duration_since(since),
+            None => state.total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_pause_switch() {
+        let switch = Arc::new(PauseSwitch::new());
+        let start = Instant::now();
+        assert_eq!(switch.paused_at(start), Duration::from_secs(0));
+
+        assert!(switch.toggle());
+        let paused = Instant::now();
+        let waiter = {
+            let switch = switch.clone();
+            thread::spawn(move || switch.wait())
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+
+        /* A pause that's going on counts up to the instant asked about. */
+        let later = paused + Duration::from_secs(10);
+        assert!(switch.paused_at(later) >= Duration::from_secs(10));
+
+        assert!(!switch.toggle());
+        waiter.join().unwrap();
+        let total = switch.paused_at(Instant::now());
+        assert!(total >= Duration::from_millis(50));
+        assert_eq!(switch.paused_at(later), total);
+
+        /* Once stopped it stays running. */
+        assert!(switch.toggle());
+        switch.stop();
+        assert!(!switch.is_paused());
+        assert!(!switch.toggle());
+        switch.wait();
+    }
+}
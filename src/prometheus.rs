@@ -0,0 +1,211 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File};
+use std::io::Write;
+
+use crate::utils::ChumError;
+use crate::worker::{Operation, WorkerStat};
+
+/*
+ * Upper bounds of the chum_rtt_seconds buckets in milliseconds, the same as
+ * the Prometheus client libraries' defaults.
+ */
+const RTT_BUCKETS_MS: [u64; 11] =
+    [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000];
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    /* Writing to a String can't fail. */
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/*
+ * Render the stats for the whole run so far in the Prometheus text exposition
+ * format. Everything is a running total, so the file can be scraped like the
+ * counters of any other process.
+ */
+fn render(op_agg: &HashMap<Operation, WorkerStat>) -> String {
+    let mut ops: Vec<_> = op_agg
+        .iter()
+        .filter(|(op, _)| op.count_label().is_none())
+        .collect();
+    ops.sort_by_key(|(op, _)| **op);
+
+    let mut errors: Vec<_> = op_agg
+        .iter()
+        .filter_map(|(op, stat)| match op {
+            Operation::Error(kind) => Some((*kind, stat.objs)),
+            _ => None,
+        })
+        .collect();
+    errors.sort();
+
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "chum_objects_total",
+        "counter",
+        "Operations completed.",
+    );
+    for (op, stat) in &ops {
+        writeln!(out, "chum_objects_total{{op=\"{}\"}} {}", op, stat.objs)
+            .unwrap();
+    }
+
+    header(
+        &mut out,
+        "chum_bytes_total",
+        "counter",
+        "Bytes read or written.",
+    );
+    for (op, stat) in &ops {
+        writeln!(out, "chum_bytes_total{{op=\"{}\"}} {}", op, stat.data)
+            .unwrap();
+    }
+
+    header(
+        &mut out,
+        "chum_errors_total",
+        "counter",
+        "Errors, by cause.",
+    );
+    for (kind, objs) in &errors {
+        writeln!(out, "chum_errors_total{{kind=\"{}\"}} {}", kind, objs)
+            .unwrap();
+    }
+
+    header(
+        &mut out,
+        "chum_retries_total",
+        "counter",
+        "Operations retried.",
+    );
+    writeln!(
+        out,
+        "chum_retries_total {}",
+        op_agg.get(&Operation::Retry).map_or(0, |stat| stat.objs)
+    )
+    .unwrap();
+
+    header(
+        &mut out,
+        "chum_rtt_seconds",
+        "histogram",
+        "Round trip time of operations.",
+    );
+    for (op, stat) in &ops {
+        for ms in RTT_BUCKETS_MS.iter() {
+            writeln!(
+                out,
+                "chum_rtt_seconds_bucket{{op=\"{}\",le=\"{}\"}} {}",
+                op,
+                *ms as f64 / 1000.0,
+                stat.rtt_hist.count_at_most(*ms)
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            "chum_rtt_seconds_bucket{{op=\"{}\",le=\"+Inf\"}} {}",
+            op, stat.objs
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "chum_rtt_seconds_sum{{op=\"{}\"}} {}",
+            op,
+            stat.rtt as f64 / 1000.0
+        )
+        .unwrap();
+        writeln!(out, "chum_rtt_seconds_count{{op=\"{}\"}} {}", op, stat.objs)
+            .unwrap();
+    }
+
+    out
+}
+
+/*
+ * Write the metrics to 'path' for --prometheus-file. They're written to a
+ * temporary file next to it first and renamed into place, so that a scrape
+ * (e.g. by node_exporter's textfile collector) never sees half a file. The
+ * collector only reads files ending in '.prom', so the temporary one is
+ * skipped.
+ */
+pub fn write_metrics(
+    path: &str,
+    op_agg: &HashMap<Operation, WorkerStat>,
+) -> Result<(), ChumError> {
+    let err = |e: std::io::Error| {
+        ChumError::new(&format!("failed to write metrics to {}: {}", path, e))
+    };
+
+    let tmp = format!("{}.tmp", path);
+    let mut file = File::create(&tmp).map_err(err)?;
+    file.write_all(render(op_agg).as_bytes()).map_err(err)?;
+    file.sync_all().map_err(err)?;
+    fs::rename(&tmp, path).map_err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ErrorKind;
+    use crate::worker::WorkerInfo;
+    use std::thread;
+
+    #[test]
+    fn test_render() {
+        let mut op_agg = HashMap::new();
+        for (op, rtt) in &[
+            (Operation::Read, 3),
+            (Operation::Read, 40),
+            (Operation::Read, 20_000),
+            (Operation::Error(ErrorKind::Timeout), 0),
+        ] {
+            op_agg
+                .entry(*op)
+                .or_insert_with(WorkerStat::new)
+                .add_result(&WorkerInfo {
+                    id: thread::current().id(),
+                    op: *op,
+                    size: 1024,
+                    ttfb: 0,
+                    rtt: *rtt,
+                    continue_ms: None,
+                    new_conn: None,
+                    entries: 0,
+                });
+        }
+
+        let text = render(&op_agg);
+        let lines: Vec<&str> = text.lines().collect();
+        for want in &[
+            "# TYPE chum_objects_total counter",
+            "chum_objects_total{op=\"read\"} 3",
+            "chum_bytes_total{op=\"read\"} 3072",
+            "chum_errors_total{kind=\"timeout\"} 1",
+            "chum_retries_total 0",
+            "# TYPE chum_rtt_seconds histogram",
+            "chum_rtt_seconds_bucket{op=\"read\",le=\"0.005\"} 1",
+            "chum_rtt_seconds_bucket{op=\"read\",le=\"0.05\"} 2",
+            "chum_rtt_seconds_bucket{op=\"read\",le=\"10\"} 2",
+            "chum_rtt_seconds_bucket{op=\"read\",le=\"+Inf\"} 3",
+            "chum_rtt_seconds_sum{op=\"read\"} 20.043",
+            "chum_rtt_seconds_count{op=\"read\"} 3",
+        ] {
+            assert!(lines.contains(want), "missing {}", want);
+        }
+
+        /* Errors aren't operations in their own right. */
+        assert!(!text.contains("op=\"error"));
+    }
+}
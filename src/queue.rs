@@ -6,22 +6,35 @@
  * Copyright 2020 Joyent, Inc.
  */
 
+use crate::utils::{new_rng, ChumError};
+
+use rand::rngs::StdRng;
 use rand::Rng;
 
+use std::collections::hash_map::DefaultHasher;
 use std::error;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const DEF_QUEUE_CAP: usize = 1_000_000;
 
-const DEF_QUEUE_CAP: usize = 1_000_000;
+/* The skew of QueueMode::Zipf unless the user asks for something else. */
+pub const DEF_ZIPF_THETA: f64 = 0.99;
 
 /*
  * Operating modes that the queue supports. See the block comment above the
  * Queue impl for an explanation.
  */
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum QueueMode {
     Lru,
     Mru,
     Rand,
+    Zipf(f64), /* the skew, theta */
 }
 
 #[derive(Debug)]
@@ -48,6 +61,7 @@ impl FromStr for QueueMode {
             "lru" => Some(QueueMode::Lru),
             "mru" => Some(QueueMode::Mru),
             "rand" => Some(QueueMode::Rand),
+            "zipf" => Some(QueueMode::Zipf(DEF_ZIPF_THETA)),
             _ => None,
         };
 
@@ -64,16 +78,63 @@ impl fmt::Display for QueueMode {
             QueueMode::Lru => "lru",
             QueueMode::Mru => "mru",
             QueueMode::Rand => "rand",
+            QueueMode::Zipf(_) => "zipf",
         };
         write!(f, "{}", strmode)
     }
 }
 
+/*
+ * Which object a delete removes from the queue (--delete-policy). By default
+ * it's up to the queue mode, see Queue::remove().
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeletePolicy {
+    Queue,
+    Oldest, /* the first written */
+    Random,
+    Lru, /* the one least recently read (or written) */
+}
+
+impl FromStr for DeletePolicy {
+    type Err = ChumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queue" => Ok(DeletePolicy::Queue),
+            "oldest" => Ok(DeletePolicy::Oldest),
+            "random" => Ok(DeletePolicy::Random),
+            "lru" => Ok(DeletePolicy::Lru),
+            _ => Err(ChumError::new(&format!(
+                "'{}' is not a delete policy (queue, oldest, random, lru)",
+                s
+            ))),
+        }
+    }
+}
+
+/*
+ * When an item was added to the queue and last handed out by get(), as ticks
+ * of the queue's own clock. Kept alongside the items for DeletePolicy. The
+ * wall clock time it was added is kept too, for --ttl, and how often it should
+ * be read compared to the others, for a weighted --read-list.
+ */
+#[derive(Clone, Copy)]
+struct Stamp {
+    added: u64,
+    used: u64,
+    inserted: Instant,
+    weight: u64,
+}
+
 pub struct Queue<T> {
     items: Vec<T>,
+    stamps: Vec<Stamp>,
+    clock: u64,
     cap: usize,
     mode: QueueMode,
     cursor: usize,
+    rng: StdRng,
+    weighted: bool, /* whether any item has a weight other than 1 */
 }
 
 /*
@@ -88,14 +149,35 @@ pub struct Queue<T> {
  *   new item is added to the top of the stack.
  * - Rand (random). Operates like an array. Random items are returned when using
  *   the accessor function. New items replace a random item.
+ * - Zipf. Operates like an array where the items at the front are hot. Items
+ *   are returned following a Zipfian distribution over their position, so the
+ *   first item is returned most often, then the second, and so on. Theta sets
+ *   the skew: 0 is uniform, and larger values concentrate on fewer items.
+ *   Items are removed from the back, and when the queue is at capacity new
+ *   items replace the last item, so the hot set stays the same for the run.
+ *
+ * Items may also be given a weight. Once any item has a weight other than 1,
+ * whatever the mode, get() returns items at random in proportion to their
+ * weights, with items inserted without one having a weight of 1. Everything
+ * else, like which item is removed or replaced, still follows the mode.
  */
 impl<T> Queue<T> {
-    pub fn new(mode: QueueMode) -> Queue<T> {
+    /*
+     * Create a queue that holds at most 'cap' items. Only the default amount
+     * of space is allocated up front, so a large cap doesn't cost anything
+     * until the queue actually grows.
+     */
+    pub fn new(mode: QueueMode, seed: Option<u64>, cap: usize) -> Queue<T> {
+        let cap = cap.max(1);
         Queue {
-            items: Vec::with_capacity(DEF_QUEUE_CAP),
-            cap: DEF_QUEUE_CAP,
+            items: Vec::with_capacity(cap.min(DEF_QUEUE_CAP)),
+            stamps: Vec::with_capacity(cap.min(DEF_QUEUE_CAP)),
+            clock: 0,
+            cap,
             mode,
             cursor: 0,
+            rng: new_rng(seed),
+            weighted: false,
         }
     }
 
@@ -104,12 +186,24 @@ impl<T> Queue<T> {
      * Removes an item if the queue has hit its capacity.
      */
     pub fn insert(&mut self, qi: T) {
+        self.insert_weighted(qi, 1);
+    }
+
+    /* Insert an item that get() should return 'weight' times as often. */
+    pub fn insert_weighted(&mut self, qi: T, weight: u64) {
+        let mut stamp = self.stamp();
+        stamp.weight = weight;
+        if weight != 1 {
+            self.weighted = true;
+        }
+
         if self.items.len() < self.cap {
             self.items.push(qi);
+            self.stamps.push(stamp);
             return;
         }
 
-        self.replace(qi);
+        self.replace(qi, stamp);
     }
 
     /*
@@ -121,13 +215,18 @@ impl<T> Queue<T> {
             return None;
         }
 
-        match self.mode {
-            QueueMode::Lru => self.items.get(0),
-            QueueMode::Mru => self.items.get(self.items.len()),
-            QueueMode::Rand => self
-                .items
-                .get(rand::thread_rng().gen_range(0, self.items.len())),
-        }
+        let i = match self.mode {
+            _ if self.weighted => self.weighted_index(),
+            QueueMode::Lru => 0,
+            QueueMode::Mru => self.items.len() - 1,
+            QueueMode::Rand => self.rng.gen_range(0, self.items.len()),
+            QueueMode::Zipf(theta) => {
+                zipf_rank(self.items.len(), theta, &mut self.rng)
+            }
+        };
+        self.clock += 1;
+        self.stamps[i].used = self.clock;
+        self.items.get(i)
     }
 
     pub fn remove(&mut self) -> Option<T> {
@@ -136,9 +235,10 @@ impl<T> Queue<T> {
         }
 
         match self.mode {
-            QueueMode::Lru => Some(self.items.remove(0)),
-            QueueMode::Mru => Some(self.items.remove(0)),
+            QueueMode::Lru | QueueMode::Mru => Some(self.take(0)),
+            QueueMode::Zipf(_) => Some(self.take(self.items.len() - 1)),
             QueueMode::Rand => {
+                self.stamps.swap_remove(self.cursor);
                 let ret = Some(self.items.swap_remove(self.cursor));
                 if !self.items.is_empty() {
                     self.cursor = (self.cursor + 1) % self.items.len();
@@ -150,7 +250,108 @@ impl<T> Queue<T> {
         }
     }
 
-    pub fn replace(&mut self, qi: T) {
+    /*
+     * Remove an item to be deleted, chosen by 'policy'. Finding the oldest or
+     * least recently used item means looking at every item, which is fine
+     * next to the cost of the delete itself.
+     */
+    pub fn remove_by(&mut self, policy: DeletePolicy) -> Option<T> {
+        if self.items.is_empty() {
+            return None;
+        }
+
+        let oldest = |stamps: &[Stamp], key: fn(&Stamp) -> u64| {
+            (0..stamps.len()).min_by_key(|i| key(&stamps[*i])).unwrap()
+        };
+        let i = match policy {
+            DeletePolicy::Queue => return self.remove(),
+            DeletePolicy::Oldest => oldest(&self.stamps, |s| s.added),
+            DeletePolicy::Lru => oldest(&self.stamps, |s| s.used),
+            DeletePolicy::Random => self.rng.gen_range(0, self.items.len()),
+        };
+        Some(self.take(i))
+    }
+
+    /*
+     * Remove every item that has been in the queue for at least 'ttl', keeping
+     * the rest in order. Items are returned oldest first.
+     */
+    pub fn remove_expired(&mut self, ttl: Duration) -> Vec<T> {
+        let now = Instant::now();
+        let items = std::mem::take(&mut self.items);
+        let stamps = std::mem::take(&mut self.stamps);
+        let mut expired = Vec::new();
+        for (item, stamp) in items.into_iter().zip(stamps) {
+            if now.duration_since(stamp.inserted) >= ttl {
+                expired.push((stamp.added, item));
+            } else {
+                self.items.push(item);
+                self.stamps.push(stamp);
+            }
+        }
+        if self.cursor >= self.items.len() {
+            self.cursor = 0;
+        }
+        expired.sort_by_key(|(added, _)| *added);
+        expired.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /*
+     * Choose an item at random in proportion to its weight. This looks at
+     * every item, which is fine next to the cost of the read it's for.
+     */
+    fn weighted_index(&mut self) -> usize {
+        let total: u64 = self.stamps.iter().map(|s| s.weight).sum();
+        let mut r = self.rng.gen_range(0, total);
+        for (i, stamp) in self.stamps.iter().enumerate() {
+            if r < stamp.weight {
+                return i;
+            }
+            r -= stamp.weight;
+        }
+        self.stamps.len() - 1
+    }
+
+    /* Remove the item at 'i', keeping the rest in order. */
+    fn take(&mut self, i: usize) -> T {
+        self.stamps.remove(i);
+        let item = self.items.remove(i);
+        if self.cursor >= self.items.len() {
+            self.cursor = 0;
+        }
+        item
+    }
+
+    /* A new item counts as just used. */
+    fn stamp(&mut self) -> Stamp {
+        self.clock += 1;
+        Stamp {
+            added: self.clock,
+            used: self.clock,
+            inserted: Instant::now(),
+            weight: 1,
+        }
+    }
+
+    /*
+     * The items in the queue, in the order they'd be restored in if they were
+     * inserted into a new queue of the same mode.
+     */
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /* The weight of each item, in the same order as items(). */
+    pub fn weights(&self) -> Vec<u64> {
+        self.stamps.iter().map(|s| s.weight).collect()
+    }
+
+    /*
+     * Evict an item to make room for a new one. In both Lru and Mru modes the
+     * oldest item goes, so that the new item ends up at the end of the queue
+     * where Mru reads from.
+     */
+    fn replace(&mut self, qi: T, stamp: Stamp) {
         if self.items.is_empty() {
             return;
         }
@@ -158,12 +359,174 @@ impl<T> Queue<T> {
         let len = self.items.len();
 
         match self.mode {
-            QueueMode::Lru => self.items[len] = qi,
-            QueueMode::Mru => self.items[len] = qi,
+            QueueMode::Lru | QueueMode::Mru => {
+                self.take(0);
+                self.items.push(qi);
+                self.stamps.push(stamp);
+            }
             QueueMode::Rand => {
                 self.items[self.cursor] = qi;
+                self.stamps[self.cursor] = stamp;
                 self.cursor = (self.cursor + 1) % len;
             }
+            QueueMode::Zipf(_) => {
+                self.items[len - 1] = qi;
+                self.stamps[len - 1] = stamp;
+            }
+        }
+    }
+}
+
+/*
+ * The queue of object names shared by the worker threads. With one lock every
+ * insert and get by every thread is serialized, which caps throughput at high
+ * concurrency, so the names can be spread over several Queues ('shards') with
+ * a lock each.
+ *
+ * A name is inserted into the shard its hash picks. Gets and removes take
+ * turns between the shards, moving on past any that are empty. Each shard
+ * follows the queue mode on its own, so e.g. Lru order is kept within a shard
+ * but only roughly across them. With one shard this is exactly a Queue.
+ */
+pub struct SharedQueue {
+    shards: Vec<Mutex<Queue<String>>>,
+    next: AtomicUsize,
+}
+
+impl SharedQueue {
+    /* The cap is shared out between the shards. */
+    pub fn new(
+        mode: QueueMode,
+        seed: Option<u64>,
+        cap: usize,
+        shards: usize,
+    ) -> SharedQueue {
+        let shards = shards.max(1);
+        let shard_cap = cap.div_ceil(shards);
+        SharedQueue {
+            shards: (0..shards)
+                .map(|i| {
+                    let seed = seed.map(|s| s.wrapping_add(i as u64));
+                    Mutex::new(Queue::new(mode, seed, shard_cap))
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn insert(&self, qi: String) {
+        let i = self.shard(&qi);
+        self.shards[i].lock().unwrap().insert(qi);
+    }
+
+    /*
+     * Insert an item with a weight, see Queue::insert_weighted(). Weights only
+     * hold within a shard, since gets take turns between the shards.
+     */
+    pub fn insert_weighted(&self, qi: String, weight: u64) {
+        let i = self.shard(&qi);
+        self.shards[i].lock().unwrap().insert_weighted(qi, weight);
+    }
+
+    /* The shard an item goes into. */
+    fn shard(&self, qi: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        qi.hash(&mut hasher);
+        hasher.finish() as usize % self.shards.len()
+    }
+
+    /* A copy of an item, chosen according to the queue mode. */
+    pub fn get(&self) -> Option<String> {
+        self.first(|q| q.get().cloned())
+    }
+
+    pub fn remove(&self) -> Option<String> {
+        self.first(|q| q.remove())
+    }
+
+    pub fn remove_by(&self, policy: DeletePolicy) -> Option<String> {
+        self.first(|q| q.remove_by(policy))
+    }
+
+    /* Remove the expired items from every shard, see Queue::remove_expired. */
+    pub fn remove_expired(&self, ttl: Duration) -> Vec<String> {
+        self.shards
+            .iter()
+            .flat_map(|q| q.lock().unwrap().remove_expired(ttl))
+            .collect()
+    }
+
+    /* How many items there are across the shards. */
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|q| q.lock().unwrap().items().len())
+            .sum()
+    }
+
+    /*
+     * The items in every shard along with their weights, see Queue::items().
+     */
+    pub fn weighted_items(&self) -> Vec<(String, u64)> {
+        self.shards
+            .iter()
+            .flat_map(|q| {
+                let q = q.lock().unwrap();
+                q.items()
+                    .iter()
+                    .cloned()
+                    .zip(q.weights())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /*
+     * Try 'f' on each shard in turn, starting with the next one along, until
+     * one has something.
+     */
+    fn first<F>(&self, mut f: F) -> Option<String>
+    where
+        F: FnMut(&mut Queue<String>) -> Option<String>,
+    {
+        let n = self.shards.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..n)
+            .find_map(|i| f(&mut self.shards[(start + i) % n].lock().unwrap()))
+    }
+}
+
+/*
+ * Choose a position in a list of 'n' items following a Zipfian distribution
+ * with skew 'theta', where position 0 is the most popular. This is the
+ * rejection-inversion method of Hörmann and Derflinger, which doesn't need
+ * any precomputed tables, so it copes with the queue growing and shrinking.
+ */
+fn zipf_rank<R: Rng>(n: usize, theta: f64, rng: &mut R) -> usize {
+    let nf = n as f64;
+    let (t, q) = if (theta - 1.0).abs() > 1e-9 {
+        let q = 1.0 / (1.0 - theta);
+        ((nf.powf(1.0 - theta) - theta) * q, q)
+    } else {
+        (1.0 + nf.ln(), 0.0)
+    };
+
+    loop {
+        let pt = rng.gen::<f64>() * t;
+        let inv_b = if pt <= 1.0 {
+            pt
+        } else if q != 0.0 {
+            (pt * (1.0 - theta) + theta).powf(q)
+        } else {
+            (pt - 1.0).exp()
+        };
+        let x = (inv_b + 1.0).floor();
+        let mut ratio = x.powf(-theta);
+        if x > 1.0 {
+            ratio *= inv_b.powf(theta);
+        }
+        if rng.gen::<f64>() < ratio {
+            return (x as usize - 1).min(n - 1);
         }
     }
 }
@@ -189,7 +552,7 @@ mod tests {
 
     #[test]
     fn test_queue_overwrite() {
-        let mut q = Queue::new(QueueMode::Rand);
+        let mut q = Queue::new(QueueMode::Rand, None, DEF_QUEUE_CAP);
         let start = Instant::now();
         for _ in 0..DEF_QUEUE_CAP {
             q.insert("testobj".to_string());
@@ -206,9 +569,95 @@ mod tests {
         println!("adding {} overflow items took {}ms", noverflow, end);
     }
 
+    /*
+     * Like test_queue_overwrite, this prints timings rather than checking
+     * them: a read-heavy mix from 64 threads against one lock, and against a
+     * lock per shard. It takes a while, so it's only run when asked for, with
+     * 'cargo test -- --ignored --nocapture'.
+     */
+    #[test]
+    #[ignore]
+    fn test_shared_queue_contention() {
+        for shards in &[1, 16] {
+            let q = std::sync::Arc::new(SharedQueue::new(
+                QueueMode::Rand,
+                None,
+                DEF_QUEUE_CAP,
+                *shards,
+            ));
+            for i in 0..10_000 {
+                q.insert(format!("obj{}", i));
+            }
+
+            let start = Instant::now();
+            let threads: Vec<_> = (0..64)
+                .map(|t| {
+                    let q = q.clone();
+                    std::thread::spawn(move || {
+                        for i in 0..10_000 {
+                            if i % 10 == 0 {
+                                q.insert(format!("obj{}-{}", t, i));
+                            } else {
+                                assert!(q.get().is_some());
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for t in threads {
+                t.join().unwrap();
+            }
+            println!(
+                "64 threads with {} shard(s) took {}ms",
+                shards,
+                start.elapsed().as_millis()
+            );
+        }
+    }
+
+    #[test]
+    fn test_shared_queue() {
+        /* One shard behaves just like a Queue. */
+        let q = SharedQueue::new(QueueMode::Lru, None, 10, 1);
+        for i in 0..3 {
+            q.insert(i.to_string());
+        }
+        assert_eq!(q.get().unwrap(), "0");
+        assert_eq!(q.remove().unwrap(), "0");
+        assert_eq!(q.remove_by(DeletePolicy::Oldest).unwrap(), "1");
+        assert_eq!(q.weighted_items(), vec![(String::from("2"), 1)]);
+
+        /* Everything can be found whichever shard it went into. */
+        let q = SharedQueue::new(QueueMode::Rand, Some(1), 100, 8);
+        for i in 0..50 {
+            q.insert(i.to_string());
+        }
+        assert_eq!(q.len(), 50);
+        let mut items: Vec<_> = q
+            .weighted_items()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        items.sort_by_key(|i| i.parse::<u32>().unwrap());
+        assert_eq!(items, (0..50).map(|i| i.to_string()).collect::<Vec<_>>());
+        let mut removed = 0;
+        while q.remove().is_some() {
+            removed += 1;
+        }
+        assert_eq!(removed, 50);
+        assert!(q.get().is_none());
+
+        /* The cap is shared out, rounding up. */
+        let q = SharedQueue::new(QueueMode::Rand, None, 10, 4);
+        for i in 0..100 {
+            q.insert(i.to_string());
+        }
+        assert!(q.len() <= 12);
+    }
+
     #[test]
     fn test_queue_clear() {
-        let mut q = Queue::new(QueueMode::Rand);
+        let mut q = Queue::new(QueueMode::Rand, None, DEF_QUEUE_CAP);
         let start = Instant::now();
         for _ in 0..DEF_QUEUE_CAP {
             q.insert("testobj".to_string());
@@ -223,4 +672,167 @@ mod tests {
         let end = start.elapsed().as_millis();
         println!("removing {} items took {}ms", DEF_QUEUE_CAP, end);
     }
+
+    #[test]
+    fn test_queue_seed() {
+        let picks = |seed| {
+            let mut q = Queue::new(QueueMode::Rand, Some(seed), DEF_QUEUE_CAP);
+            for i in 0..1000 {
+                q.insert(i);
+            }
+            (0..100).map(|_| *q.get().unwrap()).collect::<Vec<u32>>()
+        };
+        assert_eq!(picks(7), picks(7));
+    }
+
+    #[test]
+    fn test_queue_cap() {
+        for mode in &["lru", "mru", "rand"] {
+            let mut q = Queue::new(mode.parse().unwrap(), Some(1), 10);
+            for i in 0..100 {
+                q.insert(i);
+                assert!(q.items().len() <= 10);
+            }
+            assert_eq!(q.items().len(), 10);
+
+            /* The newest item is always kept in the ordered modes. */
+            match *mode {
+                "lru" => {
+                    assert_eq!(q.items(), &(90..100).collect::<Vec<_>>()[..]);
+                    assert_eq!(q.get(), Some(&90));
+                }
+                "mru" => assert_eq!(q.get(), Some(&99)),
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn test_queue_zipf() {
+        let mut q = Queue::new(QueueMode::Zipf(0.99), Some(1), DEF_QUEUE_CAP);
+        for i in 0..1000 {
+            q.insert(i);
+        }
+
+        let mut counts = vec![0; 1000];
+        for _ in 0..100_000 {
+            counts[*q.get().unwrap()] += 1;
+        }
+
+        /*
+         * With a theta of 0.99 over 1000 items the first item should get
+         * around 13% of the picks, and the first 100 around 69%.
+         */
+        assert!((10_000..17_000).contains(&counts[0]), "{}", counts[0]);
+        assert!(counts[0] > counts[1] && counts[1] > counts[9]);
+        let hot: u32 = counts[..100].iter().sum();
+        assert!((64_000..74_000).contains(&hot), "{}", hot);
+
+        /* A theta of zero is uniform. */
+        let mut q = Queue::new(QueueMode::Zipf(0.0), Some(1), DEF_QUEUE_CAP);
+        for i in 0..1000 {
+            q.insert(i);
+        }
+        let hot = (0..100_000).filter(|_| *q.get().unwrap() < 100).count();
+        assert!((9_000..11_000).contains(&hot), "{}", hot);
+
+        /* The hot items stay put as the queue fills up. */
+        let mut q = Queue::new(QueueMode::Zipf(0.99), Some(1), 10);
+        for i in 0..100 {
+            q.insert(i);
+        }
+        assert_eq!(&q.items()[..9], &(0..9).collect::<Vec<_>>()[..]);
+        assert_eq!(q.items()[9], 99);
+    }
+
+    #[test]
+    fn test_queue_delete_policy() {
+        let fill = || {
+            let mut q = Queue::new(QueueMode::Mru, Some(1), DEF_QUEUE_CAP);
+            for i in 0..10 {
+                q.insert(i);
+            }
+            q
+        };
+
+        /* Mru reads from the end, so the front goes unread. */
+        let mut q = fill();
+        assert_eq!(q.get(), Some(&9));
+        assert_eq!(q.remove_by(DeletePolicy::Oldest), Some(0));
+        assert_eq!(q.remove_by(DeletePolicy::Lru), Some(1));
+        assert_eq!(q.remove_by(DeletePolicy::Queue), Some(2));
+        assert_eq!(q.items(), &(3..10).collect::<Vec<_>>()[..]);
+
+        /* Reading an item makes it recently used. */
+        let mut q = Queue::new(QueueMode::Lru, Some(1), DEF_QUEUE_CAP);
+        for i in 0..3 {
+            q.insert(i);
+        }
+        assert_eq!(q.get(), Some(&0));
+        assert_eq!(q.remove_by(DeletePolicy::Lru), Some(1));
+        assert_eq!(q.remove_by(DeletePolicy::Oldest), Some(0));
+
+        let mut q = fill();
+        let mut gone: Vec<_> = (0..10)
+            .map(|_| q.remove_by(DeletePolicy::Random).unwrap())
+            .collect();
+        assert_eq!(q.remove_by(DeletePolicy::Random), None);
+        gone.sort();
+        assert_eq!(gone, (0..10).collect::<Vec<_>>());
+
+        assert!("oldest".parse::<DeletePolicy>().is_ok());
+        assert!("newest".parse::<DeletePolicy>().is_err());
+    }
+
+    #[test]
+    fn test_queue_expired() {
+        let mut q = Queue::new(QueueMode::Rand, Some(1), DEF_QUEUE_CAP);
+        for i in 0..3 {
+            q.insert(i);
+        }
+        std::thread::sleep(Duration::from_millis(50));
+        q.insert(3);
+        q.insert(4);
+
+        assert!(q.remove_expired(Duration::from_secs(60)).is_empty());
+        assert_eq!(q.remove_expired(Duration::from_millis(50)), vec![0, 1, 2]);
+        assert_eq!(q.items(), &[3, 4]);
+        assert_eq!(q.remove_expired(Duration::from_secs(0)), vec![3, 4]);
+        assert_eq!(q.remove(), None);
+
+        let q = SharedQueue::new(QueueMode::Lru, Some(1), DEF_QUEUE_CAP, 4);
+        for i in 0..10 {
+            q.insert(i.to_string());
+        }
+        assert_eq!(q.remove_expired(Duration::from_secs(0)).len(), 10);
+        assert_eq!(q.len(), 0);
+    }
+
+    #[test]
+    fn test_queue_weighted() {
+        /* Weights win out over the queue mode. */
+        let mut q = Queue::new(QueueMode::Lru, Some(1), DEF_QUEUE_CAP);
+        q.insert_weighted("cold", 1);
+        q.insert_weighted("hot", 9);
+        q.insert("new");
+        let hot = (0..1100).filter(|_| q.get() == Some(&"hot")).count();
+        assert!(hot > 800 && hot < 1000, "{}", hot);
+        assert_eq!(q.weights(), vec![1, 9, 1]);
+
+        /* Without weights the mode decides as usual. */
+        let mut q = Queue::new(QueueMode::Lru, Some(1), DEF_QUEUE_CAP);
+        q.insert_weighted("first", 1);
+        q.insert("second");
+        assert!((0..10).all(|_| q.get() == Some(&"first")));
+
+        let q = SharedQueue::new(QueueMode::Rand, Some(1), DEF_QUEUE_CAP, 2);
+        q.insert_weighted(String::from("hot"), 5);
+        q.insert(String::from("cold"));
+        let mut items = q.weighted_items();
+        items.sort();
+        assert_eq!(
+            items,
+            vec![(String::from("cold"), 1), (String::from("hot"), 5)]
+        );
+    }
 }
@@ -0,0 +1,129 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/*
+ * A token bucket shared by all worker threads.
+ *
+ * Tokens are added at 'rate' per second, up to one second's worth. Callers
+ * take tokens with consume() and are held up by wait() until the bucket is
+ * no longer in debt. The bucket is allowed to go negative because we often
+ * don't know the cost of an operation (e.g. the size of an object being read)
+ * until the operation is done. Any debt is paid off by the next caller(s) to
+ * wait(), so the aggregate rate across all threads converges to 'rate'.
+ */
+pub struct RateLimiter {
+    rate: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> RateLimiter {
+        RateLimiter {
+            rate: rate as f64,
+            bucket: Mutex::new(Bucket {
+                tokens: 0.0,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.rate);
+        bucket.last = now;
+    }
+
+    pub fn consume(&self, amount: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket);
+        bucket.tokens -= amount as f64;
+    }
+
+    /* Block until the bucket is out of debt. */
+    pub fn wait(&self) {
+        loop {
+            let delay = {
+                let mut bucket = self.bucket.lock().unwrap();
+                self.refill(&mut bucket);
+                if bucket.tokens >= 0.0 {
+                    return;
+                }
+                -bucket.tokens / self.rate
+            };
+
+            /* Don't hold the lock while sleeping. */
+            thread::sleep(Duration::from_micros((delay * 1_000_000.0) as u64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_ratelimit_wait() {
+        let rl = RateLimiter::new(10_000);
+
+        /* No debt, no waiting. */
+        let start = Instant::now();
+        rl.wait();
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        /* 2000 units at 10000/s is 200ms worth of debt. */
+        rl.consume(2000);
+        let start = Instant::now();
+        rl.wait();
+        let waited = start.elapsed();
+        assert!(waited >= Duration::from_millis(150), "{:?}", waited);
+        assert!(waited < Duration::from_millis(1000), "{:?}", waited);
+    }
+
+    /*
+     * The limit is shared, so threads consuming concurrently should take as
+     * long in aggregate as one thread consuming everything.
+     */
+    #[test]
+    fn test_ratelimit_shared() {
+        let rl = Arc::new(RateLimiter::new(10_000));
+        let start = Instant::now();
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let rl = rl.clone();
+                thread::spawn(move || {
+                    for _ in 0..5 {
+                        rl.wait();
+                        rl.consume(100);
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        rl.wait();
+
+        /* 20 ops of 100 units at 10000/s is roughly 200ms. */
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(150), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+    }
+}
@@ -6,270 +6,814 @@
  * Copyright 2020 Joyent, Inc.
  */
 
-extern crate uuid;
-
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
-use rand::AsByteSliceMut;
-use rand::Rng;
 
+use std::collections::HashSet;
 use std::env;
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use std::vec::Vec;
 
-use rusoto_core::{Region, RusotoError};
-use rusoto_credential::EnvironmentProvider;
+use rusoto_core::{Region, RusotoError, RusotoFuture};
+use rusoto_credential::StaticProvider;
 use rusoto_s3::{
-    CreateBucketRequest, DeleteObjectRequest, GetObjectRequest,
-    PutObjectRequest, S3Client, S3 as S3Trait,
+    AbortMultipartUploadRequest, CompleteMultipartUploadRequest,
+    CompletedMultipartUpload, CompletedPart, CopyObjectRequest,
+    CreateBucketRequest, CreateMultipartUploadRequest, Delete,
+    DeleteObjectRequest, DeleteObjectsRequest, GetObjectRequest,
+    HeadObjectError, HeadObjectRequest, ListObjectsV2Request, ObjectIdentifier,
+    PutObjectRequest, S3Client, UploadPartRequest, S3 as S3Trait,
+};
+
+use crate::utils::{
+    build_payload, build_payload_range, new_rng, range_header, spawn_named,
+    ChumError, ErrorKind,
 };
+use crate::worker::{Backend, Operation, WorkerInfo, WorkerOptions};
 
-use uuid::Uuid;
+const BUCKET: &str = "chum";
 
-use crate::utils::ChumError;
-use crate::worker::{Backend, Operation, WorkerInfo, WorkerOptions, DIR};
+/* Objects are named v2/<prefix>/... within the bucket. */
+pub const DEF_PREFIX: &str = "chum";
+
+/* The minio defaults, used if the user doesn't provide anything else. */
+const DEF_ACCESS_KEY: &str = "minioadmin";
+const DEF_SECRET_KEY: &str = "minioadmin";
+const DEF_REGION: &str = "us-east-1";
 
 pub struct S3 {
-    buf: Vec<u8>,
+    buf: Arc<Vec<u8>>, /* shared by all workers, see WorkerOptions */
+    rng: StdRng,
     client: S3Client,
     wopts: WorkerOptions,
 }
 
 impl S3 {
-    pub fn new(wopts: WorkerOptions) -> S3 {
-        let mut rng = thread_rng();
-
-        /*
-         * Create a random buffer. This is the data that will be sent
-         * to the target server.
-         */
-        let mut buf = [0u8; 65536];
-        rng.fill(&mut buf[..]);
-        let arr = buf.as_byte_slice_mut();
-        let mut vec: Vec<u8> = Vec::new();
-        vec.extend_from_slice(arr);
+    pub fn new(wopts: WorkerOptions) -> Result<S3, ChumError> {
+        let rng = new_rng(wopts.seed);
 
         /*
-         * Users may supply access keys in environment variables. We use
-         * the minio defaults if keys are not provided.
+         * Users may supply access keys on the command line or in environment
+         * variables. We use the minio defaults if keys are not provided.
          */
-        if env::var("AWS_ACCESS_KEY_ID").is_err() {
-            env::set_var("AWS_ACCESS_KEY_ID", "minioadmin");
-        }
-        if env::var("AWS_SECRET_ACCESS_KEY").is_err() {
-            env::set_var("AWS_SECRET_ACCESS_KEY", "minioadmin");
-        }
-
-        let region = Region::Custom {
-            name: "chum-s3".to_owned(),
-            endpoint: format!("http://{}", wopts.target),
-        };
-
-        let client: S3Client = S3Client::new_with(
-            rusoto_core::request::HttpClient::new()
-                .expect("failed to create S3 HTTP client"),
-            EnvironmentProvider::default(),
-            region,
+        let access_key =
+            get_setting(&wopts.access_key, "AWS_ACCESS_KEY_ID", DEF_ACCESS_KEY);
+        let secret_key = get_setting(
+            &wopts.secret_key,
+            "AWS_SECRET_ACCESS_KEY",
+            DEF_SECRET_KEY,
         );
+        let region_name =
+            get_setting(&wopts.region, "AWS_DEFAULT_REGION", DEF_REGION);
+
+        let client =
+            new_client(&wopts.target, access_key, secret_key, region_name);
 
         let mut s3 = S3 {
-            buf: vec,
+            buf: wopts.payload.clone(),
+            rng,
             client,
             wopts,
         };
 
-        s3.setup();
+        if s3.wopts.create_bucket {
+            s3.setup()?;
+        }
 
-        s3
+        Ok(s3)
     }
 
-    fn setup(&mut self) {
+    /*
+     * Apply --request-timeout to a request. rusoto's timeout covers the whole
+     * request, connecting included, so --connect-timeout isn't used.
+     */
+    fn timed<T, E>(&self, req: RusotoFuture<T, E>) -> RusotoFuture<T, E> {
+        timed(req, self.wopts.request_timeout)
+    }
+
+    fn setup(&mut self) -> Result<(), ChumError> {
         let cbr = CreateBucketRequest {
-            bucket: DIR.to_string(),
+            bucket: BUCKET.to_string(),
             ..Default::default()
         };
 
-        if let Err(e) = self.client.create_bucket(cbr).sync() {
-            match e {
-                RusotoError::Service(_) => { /* bucket already created */ }
-                _ => panic!("Creating bucket failed: {}", e),
+        match self.timed(self.client.create_bucket(cbr)).sync() {
+            Ok(_) => Ok(()),
+            Err(RusotoError::Service(_)) => Ok(()), /* already created */
+            Err(e) => {
+                Err(s3_error(&format!("Creating bucket {} failed", BUCKET), e))
             }
+        }
+    }
+
+    /*
+     * Upload an object using an S3 multipart upload.
+     *
+     * Each part is generated just before it is sent so we don't hold the
+     * whole object in memory. This means that the time spent filling each
+     * part's buffer is included in the latency of the upload, but that's
+     * small compared to sending it over the network.
+     */
+    fn put_multipart(
+        &self,
+        key: &str,
+        src: &Arc<Vec<u8>>,
+        size: u64,
+        storage_class: Option<String>,
+    ) -> Result<(), ChumError> {
+        let cmur = CreateMultipartUploadRequest {
+            bucket: BUCKET.to_string(),
+            key: key.to_string(),
+            storage_class,
+            ..Default::default()
+        };
+
+        let upload_id = self
+            .timed(self.client.create_multipart_upload(cmur))
+            .sync()
+            .map_err(|e| {
+                s3_error(
+                    &format!("Creating multipart upload for {} failed", key),
+                    e,
+                )
+            })?
+            .upload_id
+            .ok_or_else(|| {
+                ChumError::new(&format!("No upload id returned for {}", key))
+            })?;
+
+        let res =
+            self.put_parts(key, &upload_id, src, size)
+                .and_then(|parts| {
+                    let cmur = CompleteMultipartUploadRequest {
+                        bucket: BUCKET.to_string(),
+                        key: key.to_string(),
+                        upload_id: upload_id.clone(),
+                        multipart_upload: Some(CompletedMultipartUpload {
+                            parts: Some(parts),
+                        }),
+                        ..Default::default()
+                    };
+
+                    self.timed(self.client.complete_multipart_upload(cmur))
+                        .sync()
+                        .map(|_| ())
+                        .map_err(|e| {
+                            s3_error(
+                                &format!(
+                                    "Completing multipart upload for {} failed",
+                                    key
+                                ),
+                                e,
+                            )
+                        })
+                });
+
+        if res.is_err() {
+            /* Don't leave orphaned parts lying around on the target. */
+            let amur = AbortMultipartUploadRequest {
+                bucket: BUCKET.to_string(),
+                key: key.to_string(),
+                upload_id,
+                ..Default::default()
+            };
+            let _ = self.timed(self.client.abort_multipart_upload(amur)).sync();
+        }
+
+        res
+    }
+
+    fn put_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+        src: &Arc<Vec<u8>>,
+        size: u64,
+    ) -> Result<Vec<CompletedPart>, ChumError> {
+        let uploader = PartUploader {
+            client: self.client.clone(),
+            buf: src.clone(),
+            key: key.to_string(),
+            upload_id: upload_id.to_string(),
+            request_timeout: self.wopts.request_timeout,
+            content_md5: self.wopts.content_md5,
         };
+
+        let mut offset = 0;
+        let parts: Vec<Part> = part_sizes(size, self.wopts.multipart_part_size)
+            .into_iter()
+            .enumerate()
+            .map(|(i, part_size)| {
+                /* Part numbers start at one. */
+                let part = (i as i64 + 1, offset, part_size);
+                offset += part_size;
+                part
+            })
+            .collect();
+
+        let nthreads = std::cmp::min(
+            self.wopts.multipart_parallelism as usize,
+            parts.len(),
+        );
+        if nthreads <= 1 {
+            return parts.into_iter().map(|part| uploader.put(part)).collect();
+        }
+        put_parts_parallel(uploader, parts, nthreads)
     }
 
     fn get_path(&self, fname: String) -> PathBuf {
-        let first_two = &fname[0..2];
-        Path::new(&format!("v2/{}/{}/{}", DIR, first_two, fname)).to_path_buf()
+        let prefix = self.wopts.prefix.as_deref().unwrap_or(DEF_PREFIX);
+        Path::new(&object_key(prefix, &fname)).to_path_buf()
     }
-}
 
-impl Backend for S3 {
-    fn write(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
-        /* This should be similar to how muskie generates objectids. */
-        let fname = Uuid::new_v4();
+    /*
+     * With --verify-delete, HEAD an object that was just deleted. The body of
+     * a HEAD response is empty, so rusoto usually can't tell that it's a
+     * NoSuchKey and we get the bare 404 instead.
+     */
+    fn verify_deleted(&self, key: &str) -> Result<(), ChumError> {
+        let hr = HeadObjectRequest {
+            bucket: BUCKET.to_string(),
+            key: key.to_string(),
+            ..Default::default()
+        };
 
-        let mut rng = thread_rng();
-        let size = *self
-            .wopts
-            .distribution
-            .choose(&mut rng)
-            .expect("choosing file size failed");
+        match self.timed(self.client.head_object(hr)).sync() {
+            Ok(_) => Err(ChumError::undeleted(&format!(
+                "{} still exists after being deleted",
+                key
+            ))),
+            Err(RusotoError::Service(HeadObjectError::NoSuchKey(_))) => Ok(()),
+            Err(RusotoError::Unknown(ref res))
+                if res.status.as_u16() == 404 =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(s3_error(
+                &format!("Checking {} was deleted failed", key),
+                e,
+            )),
+        }
+    }
 
-        /*
-         * The S3 client library that we're using doesn't have simply
-         * sync-friendly buffered IO support. Here we just create one giant
-         * buffer to send along.
-         */
-        let mut buf: Vec<u8> = Vec::with_capacity(size as usize);
-        let mut bytes_to_go = size;
-        while bytes_to_go > 0 {
-            if bytes_to_go < self.buf.len() as u64 {
-                let tail = &self.buf[0..(bytes_to_go - 1) as usize];
-                buf.extend(tail);
-                break;
+    /*
+     * With --batch-delete, take up to 'n' objects off the queue and delete
+     * them all with one DeleteObjects request. This counts as one delete that
+     * moves no data, with the number of objects deleted as its entries. Objects that couldn't be
+     * deleted go back in the queue, and the request counts as an error.
+     */
+    fn delete_batch(
+        &mut self,
+        n: usize,
+    ) -> Result<Option<WorkerInfo>, ChumError> {
+        let mut names = vec![];
+        while names.len() < n {
+            match self.wopts.queue.remove_by(self.wopts.delete_policy) {
+                Some(fname) => names.push(fname),
+                None => break,
+            }
+        }
+        if names.is_empty() {
+            return Ok(None);
+        }
+
+        let keys: Vec<String> = names
+            .iter()
+            .map(|fname| {
+                self.get_path(fname.to_string())
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        let dr = DeleteObjectsRequest {
+            bucket: BUCKET.to_string(),
+            delete: Delete {
+                objects: keys
+                    .iter()
+                    .map(|key| ObjectIdentifier {
+                        key: key.clone(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                quiet: Some(true),
+            },
+            ..Default::default()
+        };
+
+        let rtt_start = Instant::now();
+        let res = self.timed(self.client.delete_objects(dr)).sync();
+        let rtt = rtt_start.elapsed().as_millis();
+
+        let out = match res {
+            Ok(out) => out,
+            Err(e) => {
+                for fname in names {
+                    self.wopts.queue.insert(fname);
+                }
+                return Err(s3_error(
+                    &format!("Deleting {} objects failed", keys.len()),
+                    e,
+                ));
+            }
+        };
+
+        /* Quiet mode only tells us about the ones that failed. */
+        let failed: HashSet<String> = out
+            .errors
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|e| e.key)
+            .collect();
+        for (fname, key) in names.into_iter().zip(&keys) {
+            if failed.contains(key) {
+                self.wopts.queue.insert(fname);
+            } else {
+                self.wopts.forget_checksum(&fname);
+            }
+        }
+        if !failed.is_empty() {
+            return Err(ChumError::new(&format!(
+                "Deleting {} of {} objects failed",
+                failed.len(),
+                keys.len()
+            )));
+        }
+
+        if self.wopts.verify_delete {
+            for key in &keys {
+                self.verify_deleted(key)?;
             }
-            buf.extend(&self.buf);
-            bytes_to_go -= self.buf.len() as u64;
         }
 
-        let full_path = self.get_path(fname.to_string());
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Delete,
+            size: 0,
+            ttfb: 0,
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: keys.len() as u64,
+        }))
+    }
+}
+
+/* A part of a multipart upload: its number, offset, and size. */
+type Part = (i64, u64, u64);
+
+/*
+ * What's needed to upload the parts of one multipart upload. It's cloned into
+ * each thread when parts are uploaded in parallel.
+ */
+#[derive(Clone)]
+struct PartUploader {
+    client: S3Client,
+    buf: Arc<Vec<u8>>,
+    key: String,
+    upload_id: String,
+    request_timeout: Option<Duration>,
+    content_md5: bool,
+}
+
+impl PartUploader {
+    fn put(&self, part: Part) -> Result<CompletedPart, ChumError> {
+        let (part_number, offset, part_size) = part;
+        let buf = build_payload_range(&self.buf, offset, part_size);
 
-        let pr = PutObjectRequest {
-            bucket: DIR.to_string(),
-            key: full_path.to_str().unwrap().to_string(),
+        let upr = UploadPartRequest {
+            bucket: BUCKET.to_string(),
+            key: self.key.clone(),
+            upload_id: self.upload_id.clone(),
+            part_number,
+            content_length: Some(part_size as i64),
+            content_md5: content_md5(self.content_md5, &buf),
             body: Some(buf.into()),
             ..Default::default()
         };
 
-        let rtt_start = Instant::now();
+        let upo = timed(self.client.upload_part(upr), self.request_timeout)
+            .sync()
+            .map_err(|e| {
+                s3_error(
+                    &format!(
+                        "Uploading part {} of {} failed",
+                        part_number, self.key
+                    ),
+                    e,
+                )
+            })?;
+
+        Ok(CompletedPart {
+            e_tag: upo.e_tag,
+            part_number: Some(part_number),
+        })
+    }
+}
+
+/*
+ * Upload the parts of an object from 'nthreads' threads for
+ * --multipart-parallelism. Each thread takes the next part from a shared list
+ * until there are none left. Once a part fails the rest are abandoned, since
+ * the upload will be aborted anyway. All of the threads are joined before
+ * returning so the object's latency covers every part.
+ */
+fn put_parts_parallel(
+    uploader: PartUploader,
+    parts: Vec<Part>,
+    nthreads: usize,
+) -> Result<Vec<CompletedPart>, ChumError> {
+    /* Reversed so that parts are popped off in order. */
+    let todo: Vec<Part> = parts.into_iter().rev().collect();
+    let todo = Arc::new(Mutex::new(todo));
+
+    let handles: Vec<_> = (0..nthreads)
+        .map(|_| {
+            let uploader = uploader.clone();
+            let todo = Arc::clone(&todo);
+            spawn_named("chum-upload", None, move || {
+                let mut done = Vec::new();
+                loop {
+                    let part = todo.lock().unwrap().pop();
+                    match part.map(|part| uploader.put(part)) {
+                        Some(Ok(cp)) => done.push(cp),
+                        Some(Err(e)) => {
+                            todo.lock().unwrap().clear();
+                            return Err(e);
+                        }
+                        None => return Ok(done),
+                    }
+                }
+            })
+        })
+        .collect();
+
+    let mut completed = Vec::new();
+    let mut first_err = None;
+    for handle in handles {
+        match handle.join().expect("multipart upload thread panicked") {
+            Ok(done) => completed.extend(done),
+            Err(e) => {
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+    if let Some(e) = first_err {
+        return Err(e);
+    }
+
+    /* Parts must be listed in order to complete the upload. */
+    completed.sort_by_key(|cp| cp.part_number);
+    Ok(completed)
+}
+
+/* Apply --request-timeout to a request. */
+fn timed<T, E>(
+    req: RusotoFuture<T, E>,
+    timeout: Option<Duration>,
+) -> RusotoFuture<T, E> {
+    match timeout {
+        Some(t) => req.with_timeout(t),
+        None => req,
+    }
+}
+
+/* The Content-MD5 to send with 'buf' for --content-md5. */
+fn content_md5(enabled: bool, buf: &[u8]) -> Option<String> {
+    if enabled {
+        Some(base64::encode(&md5::compute(buf).0))
+    } else {
+        None
+    }
+}
+
+/* e.g. v2/chum/ab/abcd... */
+fn object_key(prefix: &str, fname: &str) -> String {
+    format!("v2/{}/{}/{}", prefix, &fname[0..2], fname)
+}
+
+/*
+ * Split an object into multipart upload parts. Every part is 'part_size'
+ * bytes except the last, which holds whatever is left over.
+ */
+fn part_sizes(size: u64, part_size: u64) -> Vec<u64> {
+    let mut parts = vec![part_size; (size / part_size) as usize];
+    let rem = size % part_size;
+    if rem > 0 {
+        parts.push(rem);
+    }
+    parts
+}
 
+/*
+ * Wrap an error from the S3 client, noting what caused it. Errors the service
+ * knows about (e.g. NoSuchKey) are its response to a bad request.
+ */
+fn s3_error<E: std::error::Error + 'static>(
+    msg: &str,
+    err: RusotoError<E>,
+) -> ChumError {
+    let msg = format!("{}: {}", msg, err);
+    match err {
+        /* rusoto has no better way to tell a timeout apart. */
+        RusotoError::HttpDispatch(ref e)
+            if e.to_string().contains("timed out") =>
+        {
+            ChumError::with_kind(ErrorKind::Timeout, &msg)
+        }
+        RusotoError::HttpDispatch(_) => {
+            ChumError::with_kind(ErrorKind::Connection, &msg)
+        }
+        RusotoError::Service(_) => {
+            ChumError::with_kind(ErrorKind::Client, &msg)
+        }
         /*
-         * For the moment we don't have latency stats for S3 requests. Maybe
-         * we could grab these from the underlying reqwest structures. Or maybe
-         * not.
+         * A Content-MD5 that doesn't match the data the service got. rusoto
+         * doesn't parse these error codes for PutObject or UploadPart.
          */
-        match self.client.put_object(pr).sync() {
-            Err(e) => Err(ChumError::new(&e.to_string())),
+        RusotoError::Unknown(ref res)
+            if res.body_as_str().contains("<Code>BadDigest</Code>")
+                || res.body_as_str().contains("<Code>InvalidDigest</Code>") =>
+        {
+            ChumError::corruption(&msg)
+        }
+        RusotoError::Unknown(ref res) => {
+            let kind = ErrorKind::from_status(u32::from(res.status.as_u16()));
+            ChumError::with_kind(kind, &msg)
+        }
+        _ => ChumError::new(&msg),
+    }
+}
+
+/*
+ * Settings given on the command line take precedence over those in the
+ * environment, which take precedence over our defaults.
+ */
+fn get_setting(arg: &Option<String>, var: &str, default: &str) -> String {
+    match arg {
+        Some(val) => val.clone(),
+        None => env::var(var).unwrap_or_else(|_| default.to_string()),
+    }
+}
+
+/* A client whose requests are SigV4-signed with the given credentials. */
+fn new_client(
+    target: &str,
+    access_key: String,
+    secret_key: String,
+    region_name: String,
+) -> S3Client {
+    S3Client::new_with(
+        rusoto_core::request::HttpClient::new()
+            .expect("failed to create S3 HTTP client"),
+        StaticProvider::new_minimal(access_key, secret_key),
+        get_region(target, region_name),
+    )
+}
+
+/*
+ * Requests are signed using the region name, and sent to the target. The
+ * target may include a scheme (e.g. https://host:443). Plain http is assumed
+ * if it doesn't.
+ */
+fn get_region(target: &str, name: String) -> Region {
+    let endpoint =
+        if target.starts_with("http://") || target.starts_with("https://") {
+            target.to_string()
+        } else {
+            format!("http://{}", target)
+        };
+
+    Region::Custom { name, endpoint }
+}
+
+impl Backend for S3 {
+    fn write(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        /* This should be similar to how muskie generates objectids. */
+        let fname = self.wopts.namer.name();
+
+        /* Randomly choose a file size from the list (or a source file). */
+        let (src, size) =
+            self.wopts.choose_payload(&self.buf, &mut self.rng)?;
+
+        let full_path = self.get_path(fname.clone());
+        let key = full_path.to_str().unwrap().to_string();
+
+        let multipart = match self.wopts.multipart_threshold {
+            Some(threshold) => size > threshold,
+            None => false,
+        };
+
+        /* Randomly choose a storage class, if we were given any. */
+        let storage_class =
+            self.wopts.storage_classes.choose(&mut self.rng).cloned();
+
+        let rtt_start: Instant;
+        let res = if multipart {
+            rtt_start = Instant::now();
+            self.put_multipart(&key, &src, size, storage_class)
+        } else {
+            /*
+             * The S3 client library that we're using doesn't have simply
+             * sync-friendly buffered IO support. Here we just create one
+             * giant buffer to send along.
+             */
+            let buf = build_payload(&src, size);
+
+            let pr = PutObjectRequest {
+                bucket: BUCKET.to_string(),
+                key: key.clone(),
+                content_md5: content_md5(self.wopts.content_md5, &buf),
+                storage_class,
+                body: Some(buf.into()),
+                ..Default::default()
+            };
+
+            rtt_start = Instant::now();
+
+            /*
+             * For the moment we don't have latency stats for S3 requests.
+             * Maybe we could grab these from the underlying reqwest
+             * structures. Or maybe not.
+             */
+            self.timed(self.client.put_object(pr))
+                .sync()
+                .map(|_| ())
+                .map_err(|e| s3_error(&format!("Writing {} failed", key), e))
+        };
+
+        match res {
+            Err(e) => Err(e),
             Ok(_) => {
+                let rtt = rtt_start.elapsed().as_millis();
+
+                let name = fname;
+                self.wopts.record_checksum(&name, &src, size);
                 if self.wopts.read_queue {
-                    self.wopts.queue.lock().unwrap().insert(fname.to_string());
+                    self.wopts.queue.insert(name);
                 }
 
-                let rtt = rtt_start.elapsed().as_millis();
                 Ok(Some(WorkerInfo {
                     id: thread::current().id(),
                     op: Operation::Write,
                     size,
                     ttfb: 0, /* not supported */
                     rtt,
+                    continue_ms: None,
+                    new_conn: None,
+                    entries: 0,
                 }))
             }
         }
     }
 
     fn read(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let full_path =
+            self.get_path(fname.clone()).to_str().unwrap().to_string();
+
         /*
-         * Create a scope here to ensure that we don't keep the queue locked
-         * for longer than necessary.
+         * For range reads we need to know how big the object is to pick a
+         * range within it, so ask the server first. The HEAD isn't counted
+         * in the latency of the read.
          */
-        let full_path: String;
-        {
-            let mut q = self.wopts.queue.lock().unwrap();
-            let qi = q.get();
-            if qi.is_none() {
-                return Ok(None);
-            }
-            let qi = qi.unwrap();
-
-            let fname = qi.clone();
-            full_path = self.get_path(fname).to_str().unwrap().to_string();
+        let mut range = None;
+        if self.wopts.partial_reads() {
+            let hr = HeadObjectRequest {
+                bucket: BUCKET.to_string(),
+                key: full_path.clone(),
+                ..Default::default()
+            };
+            let objsize = match self.timed(self.client.head_object(hr)).sync() {
+                Err(e) => {
+                    Err(s3_error(&format!("failed to read {}", full_path), e))
+                }
+                Ok(res) => Ok(res.content_length.unwrap_or(0)),
+            }?;
+            range = self
+                .wopts
+                .choose_read(objsize as u64, &mut self.rng)
+                .map(|(offset, len)| range_header(offset, len));
         }
 
+        /* We can only verify whole objects. */
+        let whole = range.is_none();
+        let verify = whole && self.wopts.checksums.is_some();
+
         let gr = GetObjectRequest {
-            bucket: DIR.to_string(),
+            bucket: BUCKET.to_string(),
             key: full_path.clone(),
+            range,
             ..Default::default()
         };
 
         let rtt_start = Instant::now();
-        let res = match self.client.get_object(gr).sync() {
-            Err(e) => Err(ChumError::new(&format!(
-                "failed to read {}: {}",
-                full_path, e
-            ))),
+        let res = match self.timed(self.client.get_object(gr)).sync() {
+            Err(e) => {
+                Err(s3_error(&format!("failed to read {}", full_path), e))
+            }
             Ok(res) => Ok(res),
         }?;
 
         /*
-         * Read the response buffer and throw it away. We don't care about the
-         * data.
+         * Read the response --io-buffer bytes at a time and throw it away.
+         * We don't care about the data, unless we're verifying it. The body
+         * can still fail part way through, e.g. if the connection is reset.
          */
+        let mut hasher = crc32fast::Hasher::new();
+        let mut received = 0;
         if res.body.is_some() {
             let mut stream = res.body.unwrap().into_blocking_read();
-            let mut body = Vec::new();
-            stream.read_to_end(&mut body).expect(
-                "failed to read response \
-                 body",
-            );
+            let mut buf = vec![0u8; self.wopts.io_buffer];
+            loop {
+                let n = stream.read(&mut buf).map_err(|e| {
+                    let kind = match e.kind() {
+                        std::io::ErrorKind::TimedOut => ErrorKind::Timeout,
+                        _ => ErrorKind::Connection,
+                    };
+                    ChumError::with_kind(
+                        kind,
+                        &format!("failed to read {}: {}", full_path, e),
+                    )
+                })?;
+                if n == 0 {
+                    break;
+                }
+                received += n as u64;
+                if verify {
+                    hasher.update(&buf[..n]);
+                }
+            }
         }
 
-        let size = res.content_length.expect("failed to get content-length");
         let rtt = rtt_start.elapsed().as_millis();
 
+        if verify {
+            self.wopts.verify_checksum(&fname, || hasher.finalize())?;
+        }
+        if whole {
+            self.wopts.verify_size(&fname, received)?;
+        }
+
         Ok(Some(WorkerInfo {
             id: thread::current().id(),
             op: Operation::Read,
-            size: size as u64,
+            size: received,
             ttfb: 0,
             rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
         }))
     }
 
     fn delete(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
-        let full_path: String;
-        let fname: String;
-        {
-            let mut q = self.wopts.queue.lock().unwrap();
-            let qi = q.remove();
-            if qi.is_none() {
-                return Ok(None);
-            }
-            fname = qi.unwrap();
-
-            full_path = self
-                .get_path(fname.to_string())
-                .to_str()
-                .unwrap()
-                .to_string();
+        if let Some(n) = self.wopts.batch_delete {
+            return self.delete_batch(n);
         }
 
+        let fname = match self.wopts.queue.remove_by(self.wopts.delete_policy) {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let full_path = self
+            .get_path(fname.to_string())
+            .to_str()
+            .unwrap()
+            .to_string();
+
         let dr = DeleteObjectRequest {
-            bucket: DIR.to_string(),
+            bucket: BUCKET.to_string(),
             key: full_path.clone(),
             ..Default::default()
         };
 
         let rtt_start = Instant::now();
 
-        let res = self.client.delete_object(dr).sync();
+        let res = self.timed(self.client.delete_object(dr)).sync();
 
         /*
          * Re-insert the object to make it available for future read or delete
          * operations if there was an error during the delete.
          */
         if let Err(e) = res {
-            self.wopts.queue.lock().unwrap().insert(fname);
+            self.wopts.queue.insert(fname);
 
-            return Err(ChumError::new(&format!(
-                "Deleting {} failed: {}",
-                full_path, e
-            )));
+            return Err(s3_error(&format!("Deleting {} failed", full_path), e));
         }
 
         let rtt = rtt_start.elapsed().as_millis();
+        self.wopts.forget_checksum(&fname);
+        if self.wopts.verify_delete {
+            self.verify_deleted(&full_path)?;
+        }
 
         Ok(Some(WorkerInfo {
             id: thread::current().id(),
@@ -277,6 +821,405 @@ impl Backend for S3 {
             size: 0,
             ttfb: 0,
             rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }))
+    }
+
+    /*
+     * List the first page (up to 1000 keys, the S3 default) of objects under
+     * our prefix.
+     */
+    fn list(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let prefix = self.wopts.prefix.as_deref().unwrap_or(DEF_PREFIX);
+        let lr = ListObjectsV2Request {
+            bucket: BUCKET.to_string(),
+            prefix: Some(format!("v2/{}/", prefix)),
+            ..Default::default()
+        };
+
+        let rtt_start = Instant::now();
+        let res = match self.timed(self.client.list_objects_v2(lr)).sync() {
+            Err(e) => Err(s3_error(&format!("Listing {} failed", prefix), e)),
+            Ok(res) => Ok(res),
+        }?;
+        let rtt = rtt_start.elapsed().as_millis();
+
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::List,
+            size: 0,
+            ttfb: 0,
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: res.contents.map_or(0, |c| c.len() as u64),
+        }))
+    }
+
+    /*
+     * Delete everything under our prefix, a page of the listing (up to 1000
+     * objects) at a time, with one request for each page.
+     */
+    fn cleanup(&mut self) -> Result<u64, ChumError> {
+        let prefix = format!(
+            "v2/{}/",
+            self.wopts.prefix.as_deref().unwrap_or(DEF_PREFIX)
+        );
+        let mut deleted = 0;
+        let mut token = None;
+        loop {
+            let lr = ListObjectsV2Request {
+                bucket: BUCKET.to_string(),
+                prefix: Some(prefix.clone()),
+                continuation_token: token.take(),
+                ..Default::default()
+            };
+            let res =
+                self.timed(self.client.list_objects_v2(lr)).sync().map_err(
+                    |e| s3_error(&format!("Listing {} failed", prefix), e),
+                )?;
+
+            let objects: Vec<ObjectIdentifier> = res
+                .contents
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|o| o.key)
+                .map(|key| ObjectIdentifier {
+                    key,
+                    ..Default::default()
+                })
+                .collect();
+            if !objects.is_empty() {
+                let count = objects.len() as u64;
+                let dr = DeleteObjectsRequest {
+                    bucket: BUCKET.to_string(),
+                    delete: Delete {
+                        objects,
+                        quiet: Some(true),
+                    },
+                    ..Default::default()
+                };
+                let out = self
+                    .timed(self.client.delete_objects(dr))
+                    .sync()
+                    .map_err(|e| {
+                        s3_error(&format!("Cleaning up {} failed", prefix), e)
+                    })?;
+
+                /* Quiet mode only tells us about the ones that failed. */
+                let failed = out.errors.map_or(0, |e| e.len() as u64);
+                if failed > 0 {
+                    return Err(ChumError::new(&format!(
+                        "Cleaning up {} failed for {} objects",
+                        prefix, failed
+                    )));
+                }
+                deleted += count;
+            }
+
+            match res.next_continuation_token {
+                Some(t) if res.is_truncated == Some(true) => token = Some(t),
+                _ => return Ok(deleted),
+            }
+        }
+    }
+
+    /* A metadata-only request for an object we wrote. */
+    fn stat(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let full_path = self.get_path(fname).to_str().unwrap().to_string();
+
+        let hr = HeadObjectRequest {
+            bucket: BUCKET.to_string(),
+            key: full_path.clone(),
+            ..Default::default()
+        };
+
+        let rtt_start = Instant::now();
+        if let Err(e) = self.timed(self.client.head_object(hr)).sync() {
+            return Err(s3_error(
+                &format!("Getting metadata for {} failed", full_path),
+                e,
+            ));
+        }
+        let rtt = rtt_start.elapsed().as_millis();
+
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Stat,
+            size: 0,
+            ttfb: 0,
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
         }))
     }
+
+    /* Copy an object we wrote to a new key with CopyObject. */
+    fn copy(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        let fname = match self.wopts.queue.get() {
+            Some(fname) => fname,
+            None => return Ok(None),
+        };
+        let copy = self.wopts.namer.name();
+        let src_key =
+            self.get_path(fname.clone()).to_str().unwrap().to_string();
+        let key = self.get_path(copy.clone()).to_str().unwrap().to_string();
+
+        let cr = CopyObjectRequest {
+            bucket: BUCKET.to_string(),
+            key,
+            copy_source: format!("{}/{}", BUCKET, src_key),
+            ..Default::default()
+        };
+
+        let rtt_start = Instant::now();
+        if let Err(e) = self.timed(self.client.copy_object(cr)).sync() {
+            return Err(s3_error(&format!("Copying {} failed", src_key), e));
+        }
+        let rtt = rtt_start.elapsed().as_millis();
+
+        self.wopts.copy_checksum(&fname, &copy);
+        if self.wopts.read_queue {
+            self.wopts.queue.insert(copy);
+        }
+
+        Ok(Some(WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Copy,
+            size: 0,
+            ttfb: 0,
+            rtt,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::payload_checksum;
+
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /* A client for a local MinIO server with its default credentials. */
+    fn minio_client(secret_key: &str) -> S3Client {
+        let client = new_client(
+            "127.0.0.1:9000",
+            DEF_ACCESS_KEY.to_string(),
+            secret_key.to_string(),
+            DEF_REGION.to_string(),
+        );
+        let cbr = CreateBucketRequest {
+            bucket: BUCKET.to_string(),
+            ..Default::default()
+        };
+        let _ = client.create_bucket(cbr).sync();
+        client
+    }
+
+    /*
+     * Upload an object in parts, the way a worker does for objects over
+     * --multipart-threshold, and check that it reads back as the payload it
+     * was built from. This needs MinIO listening on 127.0.0.1:9000, so it's
+     * not run by default.
+     */
+    #[test]
+    #[ignore]
+    fn test_multipart_minio() {
+        let client = minio_client(DEF_SECRET_KEY);
+        let src: Arc<Vec<u8>> =
+            Arc::new((0..65536).map(|i| (i % 251) as u8).collect());
+        /* Every part but the last has to be at least 5MB. */
+        let part_size = 5 * 1024 * 1024;
+        let size = 2 * part_size + 12345;
+        let key = object_key("chum-test", "0c5bd9c3-multipart");
+
+        let cmur = CreateMultipartUploadRequest {
+            bucket: BUCKET.to_string(),
+            key: key.clone(),
+            ..Default::default()
+        };
+        let upload_id = client
+            .create_multipart_upload(cmur)
+            .sync()
+            .unwrap()
+            .upload_id;
+        let uploader = PartUploader {
+            client: client.clone(),
+            buf: src.clone(),
+            key: key.clone(),
+            upload_id: upload_id.unwrap(),
+            request_timeout: None,
+            content_md5: true,
+        };
+
+        let mut offset = 0;
+        let parts: Vec<Part> = part_sizes(size, part_size)
+            .into_iter()
+            .enumerate()
+            .map(|(i, part_size)| {
+                let part = (i as i64 + 1, offset, part_size);
+                offset += part_size;
+                part
+            })
+            .collect();
+        assert_eq!(parts.len(), 3);
+        let completed = put_parts_parallel(uploader.clone(), parts, 2).unwrap();
+
+        let cmur = CompleteMultipartUploadRequest {
+            bucket: BUCKET.to_string(),
+            key: key.clone(),
+            upload_id: uploader.upload_id.clone(),
+            multipart_upload: Some(CompletedMultipartUpload {
+                parts: Some(completed),
+            }),
+            ..Default::default()
+        };
+        client.complete_multipart_upload(cmur).sync().unwrap();
+
+        let gr = GetObjectRequest {
+            bucket: BUCKET.to_string(),
+            key: key.clone(),
+            ..Default::default()
+        };
+        let res = client.get_object(gr).sync().unwrap();
+        let mut body = Vec::new();
+        res.body
+            .unwrap()
+            .into_blocking_read()
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body.len() as u64, size);
+        assert_eq!(crc32fast::hash(&body), payload_checksum(&src, size));
+
+        let dr = DeleteObjectRequest {
+            bucket: BUCKET.to_string(),
+            key,
+            ..Default::default()
+        };
+        client.delete_object(dr).sync().unwrap();
+    }
+
+    #[test]
+    fn test_get_setting() {
+        let var = "CHUM_TEST_S3_SETTING";
+
+        env::remove_var(var);
+        assert_eq!(get_setting(&None, var, "default"), "default");
+        assert_eq!(
+            get_setting(&Some("arg".to_string()), var, "default"),
+            "arg"
+        );
+
+        env::set_var(var, "env");
+        assert_eq!(get_setting(&None, var, "default"), "env");
+        assert_eq!(
+            get_setting(&Some("arg".to_string()), var, "default"),
+            "arg"
+        );
+        env::remove_var(var);
+    }
+
+    #[test]
+    fn test_object_key() {
+        let fname = "0c5bd9c3-3f39-4d6a-b5ce-6b0c3c3a8e7e";
+        assert_eq!(
+            object_key(DEF_PREFIX, fname),
+            format!("v2/chum/0c/{}", fname)
+        );
+        assert_eq!(object_key("run2", fname), format!("v2/run2/0c/{}", fname));
+    }
+
+    #[test]
+    fn test_part_sizes() {
+        let m = 1024 * 1024;
+        assert_eq!(part_sizes(16 * m, 8 * m), vec![8 * m, 8 * m]);
+        assert_eq!(part_sizes(20 * m, 8 * m), vec![8 * m, 8 * m, 4 * m]);
+        assert_eq!(part_sizes(5 * m, 8 * m), vec![5 * m]);
+        assert_eq!(part_sizes(8 * m + 1, 8 * m), vec![8 * m, 1]);
+
+        let size = 1024 * m + 12345;
+        assert_eq!(part_sizes(size, 5 * m).iter().sum::<u64>(), size);
+    }
+
+    /*
+     * Requests carry a SigV4 signature made with the credentials they were
+     * given. A listener stands in for the server and hands back the headers
+     * of the one request it gets.
+     */
+    #[test]
+    fn test_signed_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                headers.push(line.trim().to_string());
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n",
+                )
+                .unwrap();
+            headers
+        });
+
+        let client = new_client(
+            &target,
+            "chumkey".to_string(),
+            "chumsecret".to_string(),
+            "us-west-2".to_string(),
+        );
+        let dor = DeleteObjectRequest {
+            bucket: BUCKET.to_string(),
+            key: object_key(DEF_PREFIX, "0c5bd9c3-signed"),
+            ..Default::default()
+        };
+        client.delete_object(dor).sync().unwrap();
+
+        let headers = server.join().unwrap();
+        let auth = headers
+            .iter()
+            .find(|h| h.to_lowercase().starts_with("authorization:"))
+            .expect("request wasn't signed");
+        assert!(auth.contains("AWS4-HMAC-SHA256"), "{}", auth);
+        assert!(auth.contains("Credential=chumkey/"), "{}", auth);
+        assert!(auth.contains("/us-west-2/s3/aws4_request"), "{}", auth);
+        assert!(auth.contains("Signature="), "{}", auth);
+    }
+
+    #[test]
+    fn test_get_region() {
+        assert_eq!(
+            get_region("127.0.0.1:9000", "us-east-1".to_string()),
+            Region::Custom {
+                name: "us-east-1".to_string(),
+                endpoint: "http://127.0.0.1:9000".to_string(),
+            }
+        );
+        assert_eq!(
+            get_region("https://s3.example.com", "us-west-2".to_string()),
+            Region::Custom {
+                name: "us-west-2".to_string(),
+                endpoint: "https://s3.example.com".to_string(),
+            }
+        );
+    }
 }
@@ -8,31 +8,202 @@
 
 use std::fs::File;
 use std::io::Write;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
 
 use statemap::Statemap;
 
+use crate::utils::{size_bucket, size_label};
+use crate::worker::Operation;
+
 pub struct State {
-    pub host: String,  /* Host name */
-    pub state: String, /* Name of the state, e.g. "putobject" or "fsync" */
+    pub host: String,      /* Host name */
+    pub op: Operation,     /* The operation the state is part of */
+    pub state: String,     /* Name of the state, e.g. "putobject" or "fsync" */
+    pub size: Option<u64>, /* Size of the object, if it's known */
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
 }
 
-pub fn state_listener(rx: Receiver<State>) {
+impl State {
+    /*
+     * The name the state goes by in the statemap, e.g. "write::fsync 4k",
+     * which is what the statemap colors it by. Objects are named by the size
+     * bucket they're counted in, so there are no more states than buckets.
+     */
+    fn name(&self, buckets: &[u64]) -> String {
+        let name = format!("{}::{}", self.op, self.state);
+        match self.size.and_then(|size| size_bucket(buckets, size)) {
+            Some(bucket) => format!("{} {}", name, size_label(bucket)),
+            None => name,
+        }
+    }
+}
+
+/*
+ * Collect states until the workers are done, then write them out. The
+ * statemap format isn't a streaming format, so with 'rotate' the states are
+ * instead written to a new file every so often, each a statemap of its own:
+ * states.0.out, states.1.out, and so on.
+ */
+pub fn state_listener(
+    rx: Receiver<State>,
+    rotate: Option<Duration>,
+    buckets: Vec<u64>,
+) {
+    listen(rx, rotate, &buckets, |statemap, file| {
+        write_statemap(statemap, &statemap_path(file))
+    });
+}
+
+/*
+ * The work of state_listener(), which hands each statemap to 'write' along
+ * with its number, if they're being rotated.
+ *
+ * A state that's waiting is received right away even once the deadline has
+ * passed, so under a steady stream of states the deadline is checked after
+ * each one too. Otherwise the statemap wouldn't be rotated when there's the
+ * most in it.
+ */
+fn listen<F: FnMut(Statemap, Option<u32>)>(
+    rx: Receiver<State>,
+    rotate: Option<Duration>,
+    buckets: &[u64],
+    mut write: F,
+) {
+    let mut statemap = new_statemap();
+    let mut file = 0;
+    let mut deadline = rotate.map(|r| Instant::now() + r);
+
+    loop {
+        let res = match deadline {
+            Some(d) => {
+                rx.recv_timeout(d.saturating_duration_since(Instant::now()))
+            }
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match res {
+            Ok(v) => {
+                let name = v.name(buckets);
+                statemap.set_state(&v.host, &name, None, v.start_time);
+                statemap.set_state(&v.host, "waiting", None, v.end_time);
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if let (Some(d), Some(r)) = (deadline, rotate) {
+            let now = Instant::now();
+            if now >= d {
+                let done = std::mem::replace(&mut statemap, new_statemap());
+                write(done, Some(file));
+                file += 1;
+
+                /* If we've fallen behind, start again from now. */
+                deadline = Some(if d + r > now { d + r } else { now + r });
+            }
+        }
+    }
+
+    let last = rotate.map(|_| file);
+    write(statemap, last);
+}
+
+fn new_statemap() -> Statemap {
     let mut statemap =
         Statemap::new("manta-chum", Some("myhost".to_string()), None);
     statemap.set_state_color("waiting", "white");
+    statemap
+}
 
-    while let Ok(v) = rx.recv() {
-        statemap.set_state(&v.host, &v.state, None, v.start_time);
-        statemap.set_state(&v.host, "waiting", None, v.end_time);
-    }
-
-    let mut f = File::create("states.out").unwrap();
+fn write_statemap(statemap: Statemap, path: &str) {
+    let mut f = File::create(path).unwrap();
     for state in statemap {
         f.write_all(&format!("{}\n", state).as_bytes()).unwrap();
     }
 }
+
+/* Where a statemap goes, given its number if they're being rotated. */
+fn statemap_path(file: Option<u32>) -> String {
+    match file {
+        Some(n) => format!("states.{}.out", n),
+        None => String::from("states.out"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(op: Operation, state: &str, size: Option<u64>) -> State {
+        State {
+            host: String::from("worker"),
+            op,
+            state: state.to_string(),
+            size,
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_state_name() {
+        let buckets = [4096, 1048576];
+        assert_eq!(
+            state(Operation::Write, "fsync", Some(100)).name(&buckets),
+            "write::fsync 4k"
+        );
+        assert_eq!(
+            state(Operation::Read, "read", Some(4097)).name(&buckets),
+            "read::read 1m"
+        );
+        /* Bigger than any bucket is counted in the biggest. */
+        assert_eq!(
+            state(Operation::Read, "read", Some(1 << 30)).name(&buckets),
+            "read::read 1m"
+        );
+        assert_eq!(
+            state(Operation::Delete, "rm", None).name(&buckets),
+            "delete::rm"
+        );
+        /* With only one size there's nothing to tell apart. */
+        assert_eq!(
+            state(Operation::Write, "write", Some(4096)).name(&[]),
+            "write::write"
+        );
+    }
+
+    /*
+     * Statemaps are rotated even if states never stop coming. The channel is
+     * kept full, so there's always a state waiting to be received.
+     */
+    #[test]
+    fn test_rotate_busy() {
+        let (tx, rx) = std::sync::mpsc::sync_channel(1000);
+        let sender = std::thread::spawn(move || {
+            let end = Instant::now() + Duration::from_millis(500);
+            while Instant::now() < end {
+                tx.send(state(Operation::Write, "write", None)).unwrap();
+            }
+        });
+
+        let mut files = vec![];
+        listen(rx, Some(Duration::from_millis(100)), &[], |_, file| {
+            files.push(file)
+        });
+        sender.join().unwrap();
+
+        assert!(files.len() >= 4, "{:?}", files);
+        let numbered: Vec<_> = (0..files.len() as u32).map(Some).collect();
+        assert_eq!(files, numbered);
+    }
+
+    #[test]
+    fn test_statemap_path() {
+        assert_eq!(statemap_path(None), "states.out");
+        assert_eq!(statemap_path(Some(0)), "states.0.out");
+        assert_eq!(statemap_path(Some(12)), "states.12.out");
+    }
+}
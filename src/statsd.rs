@@ -0,0 +1,258 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/.
+ *
+ * Copyright 2020 Joyent, Inc.
+ */
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use log::debug;
+
+use crate::utils::{size_bucket, size_label, ChumError};
+use crate::worker::{Operation, WorkerInfo};
+
+/*
+ * Metrics are batched into packets of about this size, which fits in the MTU
+ * of most networks.
+ */
+const MAX_PACKET: usize = 1432;
+
+/*
+ * Sends a metric for every operation to a statsd server, for --statsd. This is
+ * used from the stat thread, so the worker threads never wait on it.
+ *
+ * Plain statsd has no tags, so the operation is part of each metric's name,
+ * e.g. 'chum.write.rtt'. With --statsd-tags the DogStatsD dialect is used
+ * instead, and metrics are tagged with the operation and, for reads and
+ * writes, the size bucket (see SizeStats), e.g. 'chum.rtt' tagged
+ * 'op:write,size:512k'.
+ */
+pub struct Statsd {
+    socket: UdpSocket,
+    tags: bool,
+    buckets: Vec<u64>,
+    packet: String,
+}
+
+impl Statsd {
+    pub fn new(
+        addr: &str,
+        tags: bool,
+        buckets: Vec<u64>,
+    ) -> Result<Statsd, ChumError> {
+        let err = |e: std::io::Error| {
+            ChumError::new(&format!("statsd server {}: {}", addr, e))
+        };
+
+        let target: SocketAddr =
+            addr.to_socket_addrs().map_err(err)?.next().ok_or_else(|| {
+                ChumError::new(&format!("statsd server {} not found", addr))
+            })?;
+        let local = if target.is_ipv6() {
+            "[::]:0"
+        } else {
+            "0.0.0.0:0"
+        };
+        let socket = UdpSocket::bind(local).map_err(err)?;
+        socket.connect(target).map_err(err)?;
+
+        Ok(Statsd {
+            socket,
+            tags,
+            buckets,
+            packet: String::with_capacity(MAX_PACKET),
+        })
+    }
+
+    /* Queue up the metrics for one result, sending them when a packet fills. */
+    pub fn record(&mut self, wi: &WorkerInfo) {
+        match wi.op {
+            Operation::Error(kind) => {
+                let kind = kind.to_string();
+                self.metric("errors", Some(("kind", &kind)), "1|c", None)
+            }
+            Operation::Retry => self.metric("retries", None, "1|c", None),
+            Operation::Exists => self.metric("exists", None, "1|c", None),
+            Operation::Substituted => {
+                self.metric("substituted", None, "1|c", None)
+            }
+            op => {
+                let name = op.to_string();
+                let tag = Some(("op", name.as_str()));
+                let size = match op {
+                    Operation::Read | Operation::Write => {
+                        size_bucket(&self.buckets, wi.size).map(size_label)
+                    }
+                    _ => None,
+                };
+                let size = size.as_deref();
+                self.metric("count", tag, "1|c", size);
+                self.metric("bytes", tag, &format!("{}|c", wi.size), size);
+                self.metric("ttfb", tag, &format!("{}|ms", wi.ttfb), size);
+                self.metric("rtt", tag, &format!("{}|ms", wi.rtt), size);
+            }
+        }
+    }
+
+    /* Send whatever's been queued up. */
+    pub fn flush(&mut self) {
+        if self.packet.is_empty() {
+            return;
+        }
+
+        /* statsd is fire and forget, a lost packet is no reason to stop. */
+        if let Err(e) = self.socket.send(self.packet.as_bytes()) {
+            debug!("sending to statsd failed: {}", e);
+        }
+        self.packet.clear();
+    }
+
+    /*
+     * Add a metric to the packet. 'tag' is what the metric is about (the
+     * operation, or the cause of an error), which goes in a tag or in the
+     * name depending on the dialect: 'chum.write.rtt', or 'chum.errors.5xx'.
+     */
+    fn metric(
+        &mut self,
+        name: &str,
+        tag: Option<(&str, &str)>,
+        value: &str,
+        size: Option<&str>,
+    ) {
+        let line = if self.tags {
+            let mut tags = vec![];
+            if let Some((key, val)) = tag {
+                tags.push(format!("{}:{}", key, val));
+            }
+            if let Some(size) = size {
+                tags.push(format!("size:{}", size));
+            }
+            if tags.is_empty() {
+                format!("chum.{}:{}", name, value)
+            } else {
+                format!("chum.{}:{}|#{}", name, value, tags.join(","))
+            }
+        } else {
+            match tag {
+                Some(("op", op)) => format!("chum.{}.{}:{}", op, name, value),
+                Some((_, val)) => format!("chum.{}.{}:{}", name, val, value),
+                None => format!("chum.{}:{}", name, value),
+            }
+        };
+
+        if !self.packet.is_empty()
+            && self.packet.len() + 1 + line.len() > MAX_PACKET
+        {
+            self.flush();
+        }
+        if !self.packet.is_empty() {
+            self.packet.push('\n');
+        }
+        self.packet.push_str(&line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::ErrorKind;
+    use std::thread;
+    use std::time::Duration;
+
+    fn info(op: Operation, size: u64) -> WorkerInfo {
+        WorkerInfo {
+            id: thread::current().id(),
+            op,
+            size,
+            ttfb: 2,
+            rtt: 5,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        }
+    }
+
+    fn received(server: &UdpSocket) -> Vec<String> {
+        let mut buf = [0u8; MAX_PACKET];
+        let n = server.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..n])
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_statsd() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+
+        let mut plain = Statsd::new(&addr, false, vec![1024, 4096]).unwrap();
+        plain.record(&info(Operation::Write, 4096));
+        plain.record(&info(Operation::Error(ErrorKind::Timeout), 0));
+        plain.flush();
+        assert_eq!(
+            received(&server),
+            vec![
+                "chum.write.count:1|c",
+                "chum.write.bytes:4096|c",
+                "chum.write.ttfb:2|ms",
+                "chum.write.rtt:5|ms",
+                "chum.errors.timeout:1|c",
+            ]
+        );
+
+        let mut tagged = Statsd::new(&addr, true, vec![1024, 4096]).unwrap();
+        tagged.record(&info(Operation::Read, 1000));
+        tagged.record(&info(Operation::Delete, 0));
+        tagged.record(&info(Operation::Retry, 0));
+        tagged.flush();
+        assert_eq!(
+            received(&server),
+            vec![
+                "chum.count:1|c|#op:read,size:1k",
+                "chum.bytes:1000|c|#op:read,size:1k",
+                "chum.ttfb:2|ms|#op:read,size:1k",
+                "chum.rtt:5|ms|#op:read,size:1k",
+                "chum.count:1|c|#op:delete",
+                "chum.bytes:0|c|#op:delete",
+                "chum.ttfb:2|ms|#op:delete",
+                "chum.rtt:5|ms|#op:delete",
+                "chum.retries:1|c",
+            ]
+        );
+
+        /* Nothing is sent when there's nothing to send. */
+        tagged.flush();
+    }
+
+    #[test]
+    fn test_statsd_batching() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = server.local_addr().unwrap().to_string();
+
+        let mut statsd = Statsd::new(&addr, false, vec![]).unwrap();
+        for _ in 0..100 {
+            statsd.record(&info(Operation::Read, 1024));
+        }
+        statsd.flush();
+
+        let mut lines = 0;
+        while lines < 400 {
+            let packet = received(&server);
+            assert!(
+                packet.iter().map(|l| l.len() + 1).sum::<usize>()
+                    <= MAX_PACKET + 1
+            );
+            lines += packet.len();
+        }
+        assert_eq!(lines, 400);
+    }
+}
@@ -8,19 +8,39 @@
 
 extern crate fs3;
 
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{error, info};
+use rand::distributions::{Distribution, Exp, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{FromEntropy, Rng, SeedableRng};
 use regex::Regex;
+use serde_json::json;
+use uuid::Uuid;
 
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::sync::{mpsc::Receiver, Arc, Mutex};
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::net::Ipv6Addr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc::Receiver, mpsc::RecvTimeoutError, Arc, Mutex};
 use std::vec::Vec;
 use std::{thread, thread::ThreadId};
-use std::{time, time::SystemTime, time::UNIX_EPOCH};
+use std::{time, time::Instant, time::SystemTime, time::UNIX_EPOCH};
 
-use crate::queue::Queue;
-use crate::worker::{Operation, WorkerInfo, WorkerStat};
+use crate::auto::AutoConcurrency;
+use crate::dashboard::Dashboard;
+use crate::histogram::Histogram;
+use crate::pause::PauseSwitch;
+use crate::prometheus::write_metrics;
+use crate::queue::SharedQueue;
+use crate::statsd::Statsd;
+use crate::worker::{
+    bytes_to_human, Operation, WorkerInfo, WorkerResult, WorkerStat,
+};
 
 /*
  * In the future we should use multiple '-v' flags for this:
@@ -30,11 +50,13 @@ use crate::worker::{Operation, WorkerInfo, WorkerStat};
  *
  * But today the user specifies the exact format they want.
  */
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum OutputFormat {
     Human, /* prose, for humans watching the console. */
     HumanVerbose,
-    Tabular, /* tab-separated, for throwing into something like gnuplot. */
+    Tabular, /* columns of numbers, for gnuplot, awk, or a spreadsheet. */
+    Json,    /* newline-delimited JSON, for jq or a time-series database. */
+    Dashboard, /* --tui, redrawn in place on a terminal. */
 }
 
 impl std::str::FromStr for OutputFormat {
@@ -45,16 +67,539 @@ impl std::str::FromStr for OutputFormat {
             "h" => Ok(OutputFormat::Human),
             "v" => Ok(OutputFormat::HumanVerbose),
             "t" => Ok(OutputFormat::Tabular),
+            "j" => Ok(OutputFormat::Json),
             _ => Err(ChumError::new("invalid operation requested")),
         }
     }
 }
 
+/*
+ * How the interval stats are timestamped, see --timestamp. Epoch seconds are
+ * easy to plot, while RFC 3339 (e.g. 2020-06-01T12:00:00Z) is easier to match
+ * against server logs.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimestampFormat {
+    Epoch,
+    Rfc3339,
+}
+
+impl std::str::FromStr for TimestampFormat {
+    type Err = ChumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "epoch" => Ok(TimestampFormat::Epoch),
+            "rfc3339" => Ok(TimestampFormat::Rfc3339),
+            _ => Err(ChumError::new(&format!(
+                "invalid timestamp format '{}', expected epoch or rfc3339",
+                s
+            ))),
+        }
+    }
+}
+
+impl TimestampFormat {
+    pub fn format(self, time: SystemTime) -> String {
+        match self {
+            TimestampFormat::Epoch => epoch_secs(time).to_string(),
+            TimestampFormat::Rfc3339 => DateTime::<Utc>::from(time)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+
+    /* Epoch seconds stay a number in JSON. */
+    fn json(self, time: SystemTime) -> serde_json::Value {
+        match self {
+            TimestampFormat::Epoch => json!(epoch_secs(time)),
+            TimestampFormat::Rfc3339 => json!(self.format(time)),
+        }
+    }
+}
+
+fn epoch_secs(time: SystemTime) -> u64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(time) => time.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+/*
+ * How new objects are named, see --name-scheme. Some targets spread objects
+ * over partitions by the start of their names, so how names begin decides
+ * whether the load is spread evenly or lands on one hot partition.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NameScheme {
+    Uuid,      /* random, the way muskie names objects */
+    Seq,       /* a counter, so every name starts the same way */
+    Timestamp, /* the time written, then a uuid, so names start in order */
+    Hash,      /* a hash of a counter, then the counter */
+}
+
+impl std::str::FromStr for NameScheme {
+    type Err = ChumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uuid" => Ok(NameScheme::Uuid),
+            "seq" => Ok(NameScheme::Seq),
+            "timestamp" => Ok(NameScheme::Timestamp),
+            "hash" => Ok(NameScheme::Hash),
+            _ => Err(ChumError::new(&format!(
+                "invalid name scheme '{}', expected uuid, seq, timestamp, or \
+                 hash",
+                s
+            ))),
+        }
+    }
+}
+
+/*
+ * How long a worker waits after each operation, see --think-time. A fixed
+ * pause (--sleep) has every worker tick like a clock, whereas real clients
+ * are less regular. Exponential think times in particular make the arrivals
+ * from many workers look like those of many independent clients.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ThinkTime {
+    Constant(time::Duration),
+    Uniform(time::Duration, time::Duration), /* lowest, highest */
+    Exponential(time::Duration),             /* the mean */
+}
+
+impl ThinkTime {
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> time::Duration {
+        match *self {
+            ThinkTime::Constant(d) => d,
+            ThinkTime::Uniform(lo, hi) => {
+                let lo = lo.as_micros() as u64;
+                let hi = hi.as_micros() as u64;
+                time::Duration::from_micros(rng.gen_range(lo, hi + 1))
+            }
+            ThinkTime::Exponential(mean) => {
+                let x: f64 = Exp::new(1.0).sample(rng);
+                time::Duration::from_secs_f64(x * mean.as_secs_f64())
+            }
+        }
+    }
+}
+
+/*
+ * Parse a --think-time like '100ms', 'constant:100ms', 'uniform:50ms-200ms',
+ * or 'exp:100ms', where the exponential one is given its mean.
+ */
+impl std::str::FromStr for ThinkTime {
+    type Err = ChumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, val) = match s.split_once(':') {
+            Some((kind, val)) => (kind, val),
+            None => ("constant", s),
+        };
+        match kind {
+            "constant" => Ok(ThinkTime::Constant(parse_duration(val)?)),
+            "uniform" => {
+                let (lo, hi) = val.split_once('-').ok_or_else(|| {
+                    ChumError::new(&format!(
+                        "uniform think time '{}' needs a range, e.g. \
+                         50ms-200ms",
+                        val
+                    ))
+                })?;
+                let (lo, hi) = (parse_duration(lo)?, parse_duration(hi)?);
+                if lo > hi {
+                    return Err(ChumError::new(&format!(
+                        "uniform think time '{}' ends before it starts",
+                        val
+                    )));
+                }
+                Ok(ThinkTime::Uniform(lo, hi))
+            }
+            "exp" => Ok(ThinkTime::Exponential(parse_duration(val)?)),
+            _ => Err(ChumError::new(&format!(
+                "invalid think time '{}', expected constant, uniform, or exp",
+                kind
+            ))),
+        }
+    }
+}
+
+/*
+ * Makes the names of new objects. Every worker shares the one counter, so no
+ * two objects written in a run are given the same name.
+ */
+#[derive(Clone)]
+pub struct Namer {
+    scheme: NameScheme,
+    seq: Arc<AtomicU64>,
+}
+
+impl Namer {
+    pub fn new(scheme: NameScheme) -> Namer {
+        Namer {
+            scheme,
+            seq: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self.scheme {
+            NameScheme::Uuid => Uuid::new_v4().to_string(),
+            NameScheme::Seq => format!("{:016}", self.next_seq()),
+            NameScheme::Timestamp => {
+                let micros = match SystemTime::now().duration_since(UNIX_EPOCH)
+                {
+                    Ok(time) => time.as_micros(),
+                    Err(_) => 0,
+                };
+                format!("{:016}-{}", micros, Uuid::new_v4())
+            }
+            NameScheme::Hash => {
+                let seq = self.next_seq();
+                let mut hasher = DefaultHasher::new();
+                seq.hash(&mut hasher);
+                format!("{:08x}-{:016}", hasher.finish() as u32, seq)
+            }
+        }
+    }
+
+    fn next_seq(&self) -> u64 {
+        self.seq.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
 pub enum DataCap {
     LogicalData(u64),
     Percentage(u32),
 }
 
+/*
+ * Options that control how the stat thread reports statistics and when it
+ * decides that the run is over.
+ */
+pub struct StatsOptions {
+    pub interval: u64,
+    pub format: OutputFormat,
+    pub data_cap: Option<DataCap>,
+    pub targets: Vec<String>,
+    pub protocol: String,
+    pub max_ops: Option<u64>,
+    pub warmup: Option<time::Duration>,
+    pub max_error_rate: Option<f64>,
+    pub max_errors: Option<u64>,
+    pub out: Box<dyn Write + Send>,
+    pub event_log: Option<BufWriter<File>>,
+    pub scatter: Option<BufWriter<File>>,
+    pub run_info: RunInfo,
+    pub prometheus_file: Option<String>,
+    pub statsd: Option<Statsd>,
+    pub slow_threshold: Option<time::Duration>,
+    pub window: Option<usize>,
+    pub size_buckets: Vec<u64>,
+    pub concurrency: u32,
+    pub auto_concurrency: Option<AutoConcurrency>,
+    pub tabular_header: bool,
+    pub separator: char, /* between the tabular format's columns */
+    pub timestamp: TimestampFormat,
+    pub pause_switch: Arc<PauseSwitch>,
+    pub snapshot: Arc<AtomicBool>, /* set by SIGUSR2 */
+}
+
+/* How the run ended, as far as the stat thread is concerned. */
+pub enum RunEnd {
+    Done,
+    Aborted(String), /* an error threshold was crossed */
+}
+
+/* Achieved vs. requested operation rate, when an ops/s limit is in use. */
+pub struct OpsRate {
+    pub achieved: f64,
+    pub target: u64,
+}
+
+/*
+ * How many workers did anything in an interval, out of how many were asked
+ * for. Workers stuck on slow operations don't show up, so this is the
+ * concurrency that was really achieved.
+ */
+pub struct ActiveWorkers {
+    pub active: usize,
+    pub target: u32,
+}
+
+/*
+ * The last few intervals, for --window. Each entry is how long an interval was
+ * and how many objects and bytes each operation moved in it. Once the window
+ * is full the oldest interval is dropped as each new one comes in.
+ */
+struct Window {
+    len: usize,
+    ticks: VecDeque<WindowTick>,
+}
+
+type WindowTick = (f64, HashMap<Operation, (u64, u64)>);
+
+/*
+ * Read and write stats broken down by object size, for the verbose and JSON
+ * formats. A result goes in the smallest bucket that's at least as big as it,
+ * or the biggest bucket if it's bigger than all of them. With no buckets
+ * nothing is kept.
+ */
+struct SizeStats {
+    buckets: Vec<u64>,
+    stats: HashMap<(Operation, u64), WorkerStat>,
+}
+
+impl SizeStats {
+    fn new(buckets: &[u64]) -> SizeStats {
+        SizeStats {
+            buckets: buckets.to_vec(),
+            stats: HashMap::new(),
+        }
+    }
+
+    fn add_result(&mut self, wi: &WorkerInfo) {
+        if wi.op != Operation::Read && wi.op != Operation::Write {
+            return;
+        }
+        let bucket = match size_bucket(&self.buckets, wi.size) {
+            Some(b) => b,
+            None => return,
+        };
+        self.stats
+            .entry((wi.op, bucket))
+            .or_insert_with(WorkerStat::new)
+            .add_result(wi);
+    }
+
+    /* Sorted by operation, then size. */
+    fn sorted(&mut self) -> Vec<(&(Operation, u64), &mut WorkerStat)> {
+        let mut stats: Vec<_> = self.stats.iter_mut().collect();
+        stats.sort_by_key(|(key, _)| **key);
+        stats
+    }
+
+    fn json(&mut self) -> serde_json::Value {
+        let mut ops = serde_json::Map::new();
+        for ((op, size), stat) in self.sorted() {
+            let sizes = ops
+                .entry(op.to_string())
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+                .unwrap();
+            sizes.insert(size_label(*size), json_stat(stat));
+        }
+        serde_json::Value::Object(ops)
+    }
+}
+
+/*
+ * The buckets for SizeStats: the distinct sizes in the distribution. A single
+ * size wouldn't break anything down, so then there are none.
+ */
+pub fn size_buckets(distr: &[u64]) -> Vec<u64> {
+    let mut buckets = distr.to_vec();
+    buckets.sort();
+    buckets.dedup();
+    if buckets.len() < 2 {
+        buckets.clear();
+    }
+    buckets
+}
+
+/* The bucket that 'size' is counted in, see SizeStats. */
+pub fn size_bucket(buckets: &[u64], size: u64) -> Option<u64> {
+    match buckets.iter().find(|b| size <= **b) {
+        Some(b) => Some(*b),
+        None => buckets.last().cloned(),
+    }
+}
+
+/* A size the way it'd be given on the command line, e.g. '512k'. */
+pub fn size_label(bytes: u64) -> String {
+    for (suffix, unit) in &[
+        ("t", 1 << 40),
+        ("g", 1 << 30),
+        ("m", 1 << 20),
+        ("k", 1 << 10),
+    ] {
+        if bytes >= *unit && bytes & (unit - 1) == 0 {
+            return format!("{}{}", bytes / unit, suffix);
+        }
+    }
+    bytes.to_string()
+}
+
+/* Throughput of one operation averaged over the window. */
+struct WindowRate {
+    op: Operation,
+    objs: f64, /* per second */
+    data: u64, /* bytes per second */
+}
+
+impl Window {
+    fn new(len: usize) -> Window {
+        Window {
+            len,
+            ticks: VecDeque::with_capacity(len),
+        }
+    }
+
+    fn push(&mut self, secs: f64, op_ticks: &HashMap<Operation, WorkerStat>) {
+        if self.ticks.len() == self.len {
+            self.ticks.pop_front();
+        }
+        let counts = op_ticks
+            .iter()
+            .filter(|(op, _)| op.count_label().is_none())
+            .map(|(op, stat)| (*op, (stat.objs, stat.data)))
+            .collect();
+        self.ticks.push_back((secs, counts));
+    }
+
+    /* Each operation seen in the window, in the order they're printed. */
+    fn rates(&self) -> Vec<WindowRate> {
+        let secs: f64 = self.ticks.iter().map(|(secs, _)| secs).sum();
+        let mut totals: HashMap<Operation, (u64, u64)> = HashMap::new();
+        for (_, counts) in &self.ticks {
+            for (op, (objs, data)) in counts {
+                let total = totals.entry(*op).or_insert((0, 0));
+                total.0 += objs;
+                total.1 += data;
+            }
+        }
+
+        let mut rates: Vec<WindowRate> = totals
+            .into_iter()
+            .map(|(op, (objs, data))| WindowRate {
+                op,
+                objs: if secs > 0.0 { objs as f64 / secs } else { 0.0 },
+                data: if secs > 0.0 {
+                    (data as f64 / secs) as u64
+                } else {
+                    0
+                },
+            })
+            .collect();
+        rates.sort_by_key(|rate| rate.op);
+        rates
+    }
+}
+
+/* How many bars the summary's histograms of interval throughput have. */
+const RATE_BINS: usize = 10;
+
+/* The widest a bar of those histograms is drawn, in characters. */
+const RATE_BAR: u64 = 40;
+
+/*
+ * The throughput of every interval in the run, for histograms of it in the
+ * summary. The summary's averages hide a target that stalls now and then,
+ * which shows up here as intervals far below the rest.
+ */
+struct IntervalRates {
+    ops: Vec<f64>,  /* per second */
+    data: Vec<f64>, /* MB per second */
+}
+
+/*
+ * A histogram of interval throughput. There are RATE_BINS bins of equal
+ * width, from zero up to the fastest interval, with 'bounds' holding the
+ * edges of the bins.
+ */
+struct RateHistogram {
+    bounds: Vec<f64>,
+    counts: Vec<u64>,
+}
+
+impl RateHistogram {
+    fn new(vals: &[f64]) -> RateHistogram {
+        let max = vals.iter().cloned().fold(0.0, f64::max);
+        let width = max / RATE_BINS as f64;
+        let mut counts = vec![0; RATE_BINS];
+        for val in vals {
+            let i = if width > 0.0 {
+                ((val / width) as usize).min(RATE_BINS - 1)
+            } else {
+                0
+            };
+            counts[i] += 1;
+        }
+        RateHistogram {
+            bounds: (0..=RATE_BINS).map(|i| width * i as f64).collect(),
+            counts,
+        }
+    }
+
+    fn write(&self, out: &mut dyn Write, unit: &str) -> io::Result<()> {
+        let most = self.counts.iter().cloned().max().unwrap_or(0);
+        for (i, count) in self.counts.iter().enumerate() {
+            let bar = match most {
+                0 => 0,
+                _ => (count * RATE_BAR).div_ceil(most),
+            };
+            writeln!(
+                out,
+                "\t{:>10.1} - {:>10.1} {}\t{:>6} {}",
+                self.bounds[i],
+                self.bounds[i + 1],
+                unit,
+                count,
+                "#".repeat(bar as usize)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn json(&self) -> serde_json::Value {
+        json!({
+            "bounds": self.bounds,
+            "counts": self.counts,
+        })
+    }
+}
+
+impl IntervalRates {
+    fn new() -> IntervalRates {
+        IntervalRates {
+            ops: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, secs: f64, op_ticks: &HashMap<Operation, WorkerStat>) {
+        if secs <= 0.0 {
+            return;
+        }
+        let bytes: u64 = op_ticks
+            .iter()
+            .filter(|(op, _)| op.count_label().is_none())
+            .map(|(_, stat)| stat.data)
+            .sum();
+        self.ops.push(ops_per_sec(op_ticks, secs));
+        self.data.push(bytes as f64 / secs / 1024.0 / 1024.0);
+    }
+
+    fn write_human(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.ops.is_empty() {
+            return Ok(());
+        }
+        writeln!(out, "Ops/s in each of {} intervals", self.ops.len())?;
+        RateHistogram::new(&self.ops).write(out, "ops/s")?;
+        writeln!(out, "MB/s in each of {} intervals", self.data.len())?;
+        RateHistogram::new(&self.data).write(out, "MB/s")
+    }
+
+    fn json(&self) -> serde_json::Value {
+        json!({
+            "intervals": self.ops.len(),
+            "ops_per_sec": RateHistogram::new(&self.ops).json(),
+            "mb_per_sec": RateHistogram::new(&self.data).json(),
+        })
+    }
+}
+
 /*
  * This thread reads results off of the channel. This tracks three sets of
  * stats:
@@ -74,18 +619,119 @@ pub enum DataCap {
  * flag at the CLI.
  *
  * All stats are separated by operation (e.g. read, write, etc.).
+ *
+ * Results that arrive during the warmup period (if any) are thrown away, and
+ * the run's stats start once it's over.
+ *
+ * The thread exits when the data cap is reached or when a message arrives on
+ * the 'stop' channel (e.g. the user hit ctrl-c). Stats for the partial
+ * interval are printed before exiting so the totals account for every
+ * operation that completed. The JSON format also emits a summary object for
+ * the whole run.
+ *
+ * When 'snapshot' is set (by SIGUSR2) the summary of the run so far is printed
+ * as soon as the thread notices, headed as a snapshot. The interval carries on
+ * as if nothing happened.
+ *
+ * If an error threshold is set and an interval crosses it the thread exits
+ * the same way, but says why so that chum can exit non-zero. Exiting drops
+ * the receiver, which is what stops the workers.
+ *
+ * Stats are written to 'out', which is flushed after each interval so that
+ * a stats file can be followed as the run goes. Failing to write the stats
+ * is an error, since there's no point carrying on without them. The same
+ * goes for the event log, which gets a row for every result as it's read off
+ * the channel.
  */
 pub fn collect_stats(
-    rx: Receiver<Result<WorkerInfo, ChumError>>,
-    interval: u64,
-    format: OutputFormat,
-    data_cap: Option<DataCap>,
-    target: String,
-    protocol: String,
-) {
+    rx: Receiver<WorkerResult>,
+    stop: Receiver<()>,
+    sopts: StatsOptions,
+) -> Result<RunEnd, ChumError> {
+    let StatsOptions {
+        interval,
+        format,
+        data_cap,
+        targets,
+        protocol,
+        max_ops,
+        warmup,
+        max_error_rate,
+        max_errors,
+        mut out,
+        mut event_log,
+        mut scatter,
+        run_info,
+        prometheus_file,
+        mut statsd,
+        slow_threshold,
+        window,
+        size_buckets,
+        concurrency,
+        mut auto_concurrency,
+        tabular_header,
+        separator,
+        timestamp,
+        pause_switch,
+        snapshot,
+    } = sopts;
+    let mut window = window.map(Window::new);
+    let mut rates = IntervalRates::new();
+    let mut size_agg = SizeStats::new(&size_buckets);
+    let mut dashboard = Dashboard::new();
+    let slow_ms = slow_threshold.map(|t| t.as_millis());
+    let out: &mut dyn Write = &mut *out;
     let mut total_bytes_written: u64 = 0;
+    let mut total_errors: u64 = 0;
+    let mut aborted = None;
     let mut op_agg = HashMap::new();
+
+    if format == OutputFormat::Tabular && tabular_header {
+        writeln!(out, "{}", tabular_columns().join(&separator.to_string()))?;
+    }
+
+    if let Some(w) = warmup {
+        if format != OutputFormat::Tabular && format != OutputFormat::Json {
+            writeln!(out, "Warming up for {}s", w.as_secs_f64())?;
+            out.flush()?;
+        }
+
+        /*
+         * Keep draining the channel so results don't pile up. Data written
+         * during the warmup still counts toward the data cap, since it's on
+         * the target all the same.
+         */
+        let warmup_end = Instant::now() + w;
+        loop {
+            let now = Instant::now();
+            if now >= warmup_end {
+                break;
+            }
+            let wait =
+                (warmup_end - now).min(time::Duration::from_secs(interval));
+            let stopping = wait_for_stop(&stop, wait);
+
+            for wi in rx.try_iter().filter_map(|wr| wr.res.ok()) {
+                if wi.op == Operation::Write || wi.op == Operation::Append {
+                    total_bytes_written += wi.size;
+                }
+            }
+
+            if stopping {
+                if format != OutputFormat::Tabular {
+                    writeln!(
+                        out,
+                        "Stopped during warmup, no stats were collected"
+                    )?;
+                }
+                out.flush()?;
+                return Ok(RunEnd::Done);
+            }
+        }
+    }
+
     let start_time = SystemTime::now();
+    let paused_before = pause_switch.paused_at(Instant::now());
 
     /*
      * This is copied code, and generally an abstraction leak. We should really
@@ -96,74 +742,234 @@ pub fn collect_stats(
      */
 
     loop {
-        thread::sleep(time::Duration::from_secs(interval));
+        let tick_start = Instant::now();
+        let tick_paused = pause_switch.paused_at(tick_start);
+        let tick_end = tick_start + time::Duration::from_secs(interval);
 
         let mut op_ticks = HashMap::new();
         let mut op_stats = HashMap::new();
+        let mut size_ticks = SizeStats::new(&size_buckets);
+        let mut active = HashSet::new();
 
         /*
-         * Catch up with the results that worker threads sent while this
-         * thread was sleeping.
+         * Wait out the interval a little at a time, so that a snapshot asked
+         * for with SIGUSR2 is printed straight away. Each time, catch up with
+         * the results that worker threads sent while this thread was waiting.
          */
-        for res in rx.try_iter() {
-            let wr: WorkerInfo;
-            match res {
-                Ok(wi) => wr = wi,
-                Err(e) => {
-                    if format == OutputFormat::HumanVerbose {
-                        println!("{}", e.to_string());
-                    }
-                    wr = WorkerInfo {
-                        id: thread::current().id(),
-                        op: Operation::Error,
-                        size: 0,
-                        ttfb: 0,
-                        rtt: 0,
+        let stopping = loop {
+            let now = Instant::now();
+            let stopping = now < tick_end
+                && wait_for_stop(&stop, (tick_end - now).min(SNAPSHOT_POLL));
+
+            for msg in rx.try_iter() {
+                active.insert(msg.id);
+                if let Some(log) = &mut event_log {
+                    write_event(log, &msg)?;
+                }
+                if let Some(points) = &mut scatter {
+                    write_scatter(points, &msg)?;
+                }
+
+                let wr: WorkerInfo;
+                match msg.res {
+                    Ok(wi) => wr = wi,
+                    Err(e) => {
+                        wr = WorkerInfo {
+                            id: msg.id,
+                            op: Operation::Error(e.kind()),
+                            size: 0,
+                            ttfb: 0,
+                            rtt: 0,
+                            continue_ms: None,
+                            new_conn: None,
+                            entries: 0,
+                        }
                     }
                 }
+
+                if wr.op == Operation::Write || wr.op == Operation::Append {
+                    total_bytes_written += wr.size;
+                }
+                if let Some(s) = &mut statsd {
+                    s.record(&wr);
+                }
+
+                /*
+                 * Slow operations still succeeded, so they're counted as usual
+                 * and again as slow.
+                 */
+                let slow = match slow_ms {
+                    Some(ms) => wr.op.count_label().is_none() && wr.rtt > ms,
+                    None => false,
+                };
+                if slow {
+                    info!("slow {}: rtt {}ms", wr.op, wr.rtt);
+                }
+
+                op_stats.entry(wr.op).or_insert_with(HashMap::new);
+
+                let thread_stats = op_stats.get_mut(&wr.op).unwrap();
+                thread_stats.entry(wr.id).or_insert_with(WorkerStat::new);
+                let thread_totals = thread_stats.get_mut(&wr.id).unwrap();
+                thread_totals.add_result(&wr);
+
+                op_ticks.entry(wr.op).or_insert_with(WorkerStat::new);
+                let tick_totals = op_ticks.get_mut(&wr.op).unwrap();
+                tick_totals.add_result(&wr);
+
+                op_agg.entry(wr.op).or_insert_with(WorkerStat::new);
+                let agg_totals = op_agg.get_mut(&wr.op).unwrap();
+                agg_totals.add_result(&wr);
+
+                size_ticks.add_result(&wr);
+                size_agg.add_result(&wr);
+
+                if slow {
+                    thread_totals.slow += 1;
+                    tick_totals.slow += 1;
+                    agg_totals.slow += 1;
+                }
             }
 
-            if wr.op == Operation::Write {
-                total_bytes_written += wr.size;
+            if stopping || Instant::now() >= tick_end {
+                break stopping;
             }
 
-            op_stats.entry(wr.op).or_insert_with(HashMap::new);
+            /*
+             * A snapshot is the summary of the run so far, and doesn't change
+             * when the interval ends.
+             */
+            if snapshot.swap(false, Ordering::Relaxed) {
+                let run_start = start_time
+                    + (pause_switch.paused_at(Instant::now()) - paused_before);
+                let knee =
+                    auto_concurrency.as_ref().and_then(|auto| auto.knee());
+                print_summary(
+                    out,
+                    &format,
+                    "Snapshot",
+                    run_start,
+                    &mut op_agg,
+                    warmup,
+                    knee,
+                    &rates,
+                    &mut size_agg,
+                    None,
+                    timestamp,
+                )?;
+                out.flush()?;
+            }
+        };
+
+        /*
+         * Time spent paused (see PauseSwitch) is left out of the rates, so
+         * that a pause doesn't drag them down. For rates over the whole run,
+         * the run starts that much later instead.
+         */
+        let now = Instant::now();
+        let paused = pause_switch.paused_at(now);
+        let secs = (now - tick_start)
+            .saturating_sub(paused - tick_paused)
+            .as_secs_f64();
+        let run_start = start_time + (paused - paused_before);
 
-            let thread_stats = op_stats.get_mut(&wr.op).unwrap();
-            thread_stats.entry(wr.id).or_insert_with(WorkerStat::new);
-            thread_stats.get_mut(&wr.id).unwrap().add_result(&wr);
+        let achieved = ops_per_sec(&op_ticks, secs);
+        let ops_rate = max_ops.map(|target| OpsRate { achieved, target });
 
-            op_ticks.entry(wr.op).or_insert_with(WorkerStat::new);
-            let tick_totals = op_ticks.get_mut(&wr.op).unwrap();
-            tick_totals.add_result(&wr);
+        let workers = ActiveWorkers {
+            active: active.len(),
+            target: match &auto_concurrency {
+                Some(auto) => auto.workers(),
+                None => concurrency,
+            },
+        };
 
-            op_agg.entry(wr.op).or_insert_with(WorkerStat::new);
-            let agg_totals = op_agg.get_mut(&wr.op).unwrap();
-            agg_totals.add_result(&wr);
+        if let Some(w) = &mut window {
+            w.push(secs, &op_ticks);
         }
+        rates.push(secs, &op_ticks);
+
+        total_errors += count_errors(&op_ticks);
+        let abort =
+            check_errors(&op_ticks, total_errors, max_error_rate, max_errors);
 
         match format {
             OutputFormat::Human | OutputFormat::HumanVerbose => print_human(
-                start_time,
+                out,
+                run_start,
                 &format,
                 op_stats,
                 op_ticks,
                 &mut op_agg,
+                ops_rate,
+                workers,
+                window.as_ref(),
+                &mut size_ticks,
+                timestamp,
             ),
             OutputFormat::Tabular => print_tabular(
-                start_time,
+                out,
+                run_start,
+                &format,
+                op_stats,
+                op_ticks,
+                &mut op_agg,
+                separator,
+                timestamp,
+            ),
+            OutputFormat::Json => print_json(
+                out,
+                run_start,
                 &format,
                 op_stats,
                 op_ticks,
                 &mut op_agg,
+                ops_rate,
+                workers,
+                window.as_ref(),
+                &mut size_ticks,
+                timestamp,
             ),
+            OutputFormat::Dashboard => dashboard.draw(
+                out, run_start, secs, &op_ticks, &op_agg, ops_rate, workers,
+                timestamp,
+            ),
+        }?;
+        out.flush()?;
+        if let Some(log) = &mut event_log {
+            log.flush()?;
+        }
+        if let Some(points) = &mut scatter {
+            points.flush()?;
+        }
+        if let Some(path) = &prometheus_file {
+            write_metrics(path, &op_agg)?;
+        }
+        if let Some(s) = &mut statsd {
+            s.flush();
+        }
+
+        if stopping {
+            break;
+        }
+
+        /* A paused interval says nothing about the concurrency. */
+        if let Some(auto) = &mut auto_concurrency {
+            if paused == tick_paused {
+                auto.observe(achieved);
+            }
+        }
+
+        if abort.is_some() {
+            aborted = abort;
+            break;
         }
 
         match data_cap {
             Some(DataCap::LogicalData(cap)) => {
                 if total_bytes_written >= cap {
                     /* Exit the thread, signalling and end of the program. */
-                    return;
+                    break;
                 }
             }
             Some(DataCap::Percentage(cap)) => {
@@ -172,53 +978,633 @@ pub fn collect_stats(
                     continue;
                 }
 
-                match fs3::statvfs(&target) {
-                    Ok(stats) => {
-                        let used =
-                            stats.total_space() - stats.available_space();
-                        let perc_used = (used * 100) / stats.total_space();
-
-                        if perc_used >= cap.into() {
-                            return;
-                        }
-                    }
-                    Err(e) => {
-                        println!("statvfs error for {}: {}", &target, e);
-                        return;
-                    }
+                if targets.iter().any(|t| percentage_reached(t, cap)) {
+                    break;
                 }
             }
             None => (),
         }
     }
+
+    /* However the run ended, finish with a rollup of the whole run. */
+    let run_start =
+        start_time + (pause_switch.paused_at(Instant::now()) - paused_before);
+    let knee = auto_concurrency.as_ref().and_then(|auto| auto.knee());
+    print_summary(
+        out,
+        &format,
+        "Summary",
+        run_start,
+        &mut op_agg,
+        warmup,
+        knee,
+        &rates,
+        &mut size_agg,
+        Some(&run_info),
+        timestamp,
+    )?;
+    out.flush()?;
+    if let Some(log) = &mut event_log {
+        log.flush()?;
+    }
+    if let Some(points) = &mut scatter {
+        points.flush()?;
+    }
+
+    Ok(match aborted {
+        Some(msg) => RunEnd::Aborted(msg),
+        None => RunEnd::Done,
+    })
 }
 
-fn print_human(
-    start_time: SystemTime,
+/*
+ * A rollup of the whole run so far, in the output format. 'title' says what
+ * it is, "Summary" at the end of the run or "Snapshot" part way through.
+ */
+#[allow(clippy::too_many_arguments)]
+fn print_summary(
+    out: &mut dyn Write,
     format: &OutputFormat,
-    mut op_stats: HashMap<Operation, HashMap<ThreadId, WorkerStat>>,
-    mut op_ticks: HashMap<Operation, WorkerStat>,
+    title: &str,
+    run_start: SystemTime,
     op_agg: &mut HashMap<Operation, WorkerStat>,
-) {
-    /* Print out the stats we gathered. */
-    println!("---");
-    if *format == OutputFormat::HumanVerbose {
-        let mut i = 0;
-        for (op, op_map) in op_stats.iter_mut() {
-            println!("Thread ({})", op);
-            for (_, worker) in op_map.iter_mut() {
-                if worker.objs == 0 {
-                    /*
-                     * don't want to divide by zero when there's
-                     * no activity
-                     */
-                    continue;
-                }
+    warmup: Option<time::Duration>,
+    knee: Option<u32>,
+    rates: &IntervalRates,
+    size_agg: &mut SizeStats,
+    run: Option<&RunInfo>,
+    timestamp: TimestampFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            let run = run.map(|run| run.json(timestamp));
+            print_json_summary(
+                out, title, run_start, op_agg, warmup, knee, rates, size_agg,
+                run,
+            )
+        }
+        OutputFormat::Human | OutputFormat::Dashboard => {
+            writeln!(out, "=== {} ===", title)?;
+            if let Some(run) = run {
+                run.write_human(out, timestamp)?;
+            }
+            print_human_summary(
+                out, run_start, op_agg, warmup, knee, rates, None,
+            )
+        }
+        OutputFormat::HumanVerbose => {
+            writeln!(out, "=== {} ===", title)?;
+            if let Some(run) = run {
+                run.write_human(out, timestamp)?;
+            }
+            print_human_summary(
+                out,
+                run_start,
+                op_agg,
+                warmup,
+                knee,
+                rates,
+                Some(size_agg),
+            )
+        }
+        OutputFormat::Tabular => {
+            print_tabular_summary(out, title, run_start, op_agg)
+        }
+    }
+}
 
-                if op == &Operation::Error {
-                    println!("\t{}: {} errors", i, worker.objs);
-                } else {
-                    println!("\t{}: {}", i, worker.serialize_relative());
+/*
+ * What was run, for the top of the final summary, so that saved results can
+ * be traced back to the settings that produced them. The command line doesn't
+ * have anything set in a --config file, but it does name the file.
+ */
+pub struct RunInfo {
+    pub command: String, /* secrets left out, see command_line() */
+    pub version: &'static str,
+    pub protocol: String,
+    pub targets: Vec<String>,
+    pub concurrency: u32, /* the most workers, with --auto-concurrency */
+    pub auto_concurrency: bool,
+    pub distribution: String,
+    pub workload: String,
+    pub started: SystemTime,
+}
+
+impl RunInfo {
+    fn write_human(
+        &self,
+        out: &mut dyn Write,
+        timestamp: TimestampFormat,
+    ) -> io::Result<()> {
+        writeln!(out, "Command\t\t{}", self.command)?;
+        writeln!(out, "Version\t\tchum {}", self.version)?;
+        writeln!(
+            out,
+            "Target\t\t{} {}",
+            self.protocol,
+            self.targets.join(", ")
+        )?;
+        if self.auto_concurrency {
+            writeln!(
+                out,
+                "Workers\t\tup to {}, with --auto-concurrency",
+                self.concurrency
+            )?;
+        } else {
+            writeln!(out, "Workers\t\t{}", self.concurrency)?;
+        }
+        writeln!(out, "Distribution\t{}", self.distribution)?;
+        writeln!(out, "Workload\t{}", self.workload)?;
+        writeln!(out, "Started\t\t{}", timestamp.format(self.started))?;
+        writeln!(out, "Ended\t\t{}", timestamp.format(SystemTime::now()))
+    }
+
+    fn json(&self, timestamp: TimestampFormat) -> serde_json::Value {
+        json!({
+            "command": self.command,
+            "version": self.version,
+            "protocol": self.protocol,
+            "targets": self.targets,
+            "concurrency": self.concurrency,
+            "auto_concurrency": self.auto_concurrency,
+            "distribution": self.distribution,
+            "workload": self.workload,
+            "started": timestamp.json(self.started),
+            "ended": timestamp.json(SystemTime::now()),
+        })
+    }
+}
+
+/* Arguments whose values may be secret, e.g. an auth header. */
+const SECRET_ARGS: [&str; 4] = ["--secret-key", "--proxy", "--header", "-H"];
+
+/*
+ * The command line as it could be typed into a shell, with the values of
+ * SECRET_ARGS replaced by '...' so that summaries can be shared.
+ */
+pub fn command_line<I: IntoIterator<Item = String>>(args: I) -> String {
+    let mut words = vec![];
+    let mut secret = false;
+    for arg in args {
+        let word = if secret {
+            String::from("...")
+        } else {
+            match arg.split_once('=') {
+                Some((name, _)) if SECRET_ARGS.contains(&name) => {
+                    format!("{}=...", name)
+                }
+                _ => shell_quote(&arg),
+            }
+        };
+        secret = SECRET_ARGS.contains(&arg.as_str());
+        words.push(word);
+    }
+    words.join(" ")
+}
+
+fn shell_quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/*
+ * Report how many objects --cleanup deleted, which happens after the summary
+ * has been printed, in the same format.
+ */
+pub fn print_cleanup(
+    out: &mut dyn Write,
+    format: &OutputFormat,
+    deleted: u64,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Json => {
+            writeln!(out, "{}", json!({"type": "cleanup", "deleted": deleted}))
+        }
+        OutputFormat::Tabular => writeln!(out, "# cleanup {}", deleted),
+        _ => writeln!(out, "Cleaned up\t{} objects", deleted),
+    }
+}
+
+pub const EVENT_LOG_HEADER: &str = "time,thread,op,size,ttfb,rtt,status";
+
+pub const SCATTER_HEADER: &str = "op,size,rtt";
+
+/*
+ * The --scatter-file buffer. Each point is only a few bytes, so this holds
+ * tens of thousands of them and the file is written to rarely.
+ */
+const SCATTER_BUFFER: usize = 1024 * 1024;
+
+/*
+ * Open the event log for appending, starting it with a header if it's new.
+ * Rows are buffered, since at high op rates writing them one at a time would
+ * slow the stat thread down.
+ */
+pub fn open_event_log(path: &str) -> Result<BufWriter<File>, ChumError> {
+    open_csv(path, EVENT_LOG_HEADER, 8 * 1024)
+}
+
+/* Likewise for --scatter-file, with a bigger buffer. */
+pub fn open_scatter_file(path: &str) -> Result<BufWriter<File>, ChumError> {
+    open_csv(path, SCATTER_HEADER, SCATTER_BUFFER)
+}
+
+fn open_csv(
+    path: &str,
+    header: &str,
+    capacity: usize,
+) -> Result<BufWriter<File>, ChumError> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let empty = file.metadata()?.len() == 0;
+    let mut log = BufWriter::with_capacity(capacity, file);
+    if empty {
+        writeln!(log, "{}", header)?;
+    }
+    Ok(log)
+}
+
+/*
+ * One point of the scatter file, for a read or write that succeeded. Other
+ * operations don't move data, and failures say nothing about how long a
+ * transfer of that size takes.
+ */
+fn write_scatter(out: &mut dyn Write, msg: &WorkerResult) -> io::Result<()> {
+    match &msg.res {
+        Ok(wi) if wi.op == Operation::Read || wi.op == Operation::Write => {
+            writeln!(out, "{},{},{}", wi.op, wi.size, wi.rtt)
+        }
+        _ => Ok(()),
+    }
+}
+
+/*
+ * One row of the event log. The time is when the attempt finished, in
+ * seconds since the epoch, and the thread is named the way it is in the
+ * statemap. The status is 'ok', 'retry' for an attempt that's being retried,
+ * or the cause of an error.
+ */
+fn write_event(out: &mut dyn Write, msg: &WorkerResult) -> io::Result<()> {
+    let time = match msg.time.duration_since(UNIX_EPOCH) {
+        Ok(time) => time,
+        Err(_) => time::Duration::from_secs(0),
+    };
+    let (size, ttfb, rtt, status) = match &msg.res {
+        Ok(wi) if wi.op == Operation::Retry => (0, 0, 0, "retry".to_string()),
+        Ok(wi) if wi.op == Operation::Substituted => {
+            (0, 0, 0, "substituted".to_string())
+        }
+        Ok(wi) if wi.op == Operation::Exists => {
+            (0, wi.ttfb, wi.rtt, "exists".to_string())
+        }
+        Ok(wi) => (wi.size, wi.ttfb, wi.rtt, "ok".to_string()),
+        Err(e) => (0, 0, 0, e.kind().to_string()),
+    };
+    writeln!(
+        out,
+        "{}.{:03},{:?},{},{},{},{},{}",
+        time.as_secs(),
+        time.subsec_millis(),
+        msg.id,
+        msg.op,
+        size,
+        ttfb,
+        rtt,
+        status
+    )
+}
+
+fn count_errors(op_ticks: &HashMap<Operation, WorkerStat>) -> u64 {
+    op_ticks
+        .iter()
+        .filter(|(op, _)| matches!(op, Operation::Error(_)))
+        .map(|(_, stat)| stat.objs)
+        .sum()
+}
+
+/*
+ * Decide whether the run should be aborted after a tick. The error rate is
+ * the fraction of the tick's operations that failed, so retries and the like
+ * don't count either way. The error count is over the whole run. Returns
+ * the reason for aborting, if any.
+ */
+fn check_errors(
+    op_ticks: &HashMap<Operation, WorkerStat>,
+    total_errors: u64,
+    max_error_rate: Option<f64>,
+    max_errors: Option<u64>,
+) -> Option<String> {
+    if let Some(max) = max_errors {
+        if total_errors > max {
+            return Some(format!(
+                "aborting, {} operations failed (max-errors {})",
+                total_errors, max
+            ));
+        }
+    }
+
+    if let Some(max) = max_error_rate {
+        let errors = count_errors(op_ticks);
+        let ops: u64 = op_ticks
+            .iter()
+            .filter(|(op, _)| op.count_label().is_none())
+            .map(|(_, stat)| stat.objs)
+            .sum();
+        let total = errors + ops;
+        if total > 0 {
+            let rate = errors as f64 * 100.0 / total as f64;
+            if rate > max {
+                return Some(format!(
+                    "aborting, {:.1}% of operations failed in the last \
+                     interval (max-error-rate {}%)",
+                    rate, max
+                ));
+            }
+        }
+    }
+
+    None
+}
+
+/* Operations completed per second, leaving out errors and retries. */
+fn ops_per_sec(op_ticks: &HashMap<Operation, WorkerStat>, secs: f64) -> f64 {
+    let objs: u64 = op_ticks
+        .iter()
+        .filter(|(op, _)| op.count_label().is_none())
+        .map(|(_, stat)| stat.objs)
+        .sum();
+    if secs > 0.0 {
+        objs as f64 / secs
+    } else {
+        0.0
+    }
+}
+
+/* Totals across operations for the end of run summary. */
+struct RunTotals {
+    secs: f64,
+    ops: u64,
+    bytes: u64,
+    errors: u64,
+    retries: u64,
+    exists: u64,
+    substituted: u64,
+}
+
+impl RunTotals {
+    fn new(
+        start_time: SystemTime,
+        op_agg: &HashMap<Operation, WorkerStat>,
+    ) -> Self {
+        let mut totals = RunTotals {
+            secs: match start_time.elapsed() {
+                Ok(elapsed) => elapsed.as_secs_f64(),
+                Err(_) => 0.0,
+            },
+            ops: 0,
+            bytes: 0,
+            errors: 0,
+            retries: 0,
+            exists: 0,
+            substituted: 0,
+        };
+        for (op, stat) in op_agg.iter() {
+            match op {
+                Operation::Error(_) => totals.errors += stat.objs,
+                Operation::Retry => totals.retries += stat.objs,
+                Operation::Exists => totals.exists += stat.objs,
+                Operation::Substituted => totals.substituted += stat.objs,
+                _ => {
+                    totals.ops += stat.objs;
+                    totals.bytes += stat.data;
+                }
+            }
+        }
+        totals
+    }
+
+    fn ops_rate(&self) -> f64 {
+        if self.secs > 0.0 {
+            self.ops as f64 / self.secs
+        } else {
+            0.0
+        }
+    }
+
+    fn bytes_rate(&self) -> f64 {
+        if self.secs > 0.0 {
+            self.bytes as f64 / self.secs
+        } else {
+            0.0
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn print_human_summary(
+    out: &mut dyn Write,
+    start_time: SystemTime,
+    op_agg: &mut HashMap<Operation, WorkerStat>,
+    warmup: Option<time::Duration>,
+    knee: Option<u32>,
+    rates: &IntervalRates,
+    sizes: Option<&mut SizeStats>,
+) -> io::Result<()> {
+    let totals = RunTotals::new(start_time, op_agg);
+    let mb = |bytes: f64| bytes / 1024.0 / 1024.0;
+
+    writeln!(out, "Wall time\t{:.1}s", totals.secs)?;
+    if let Some(w) = warmup {
+        writeln!(
+            out,
+            "Warmup\t\t{}s, not included in the summary",
+            w.as_secs_f64()
+        )?;
+    }
+    writeln!(
+        out,
+        "Operations\t{} ({:.1} ops/s)",
+        totals.ops,
+        totals.ops_rate()
+    )?;
+    writeln!(
+        out,
+        "Data\t\t{:.3}MB ({:.3}MB/s)",
+        mb(totals.bytes as f64),
+        mb(totals.bytes_rate())
+    )?;
+    writeln!(out, "Errors\t\t{}", totals.errors)?;
+    writeln!(out, "Retries\t\t{}", totals.retries)?;
+    if totals.exists > 0 {
+        writeln!(out, "Existed\t\t{}", totals.exists)?;
+    }
+    if totals.substituted > 0 {
+        writeln!(
+            out,
+            "Substituted\t{} reads done as writes",
+            totals.substituted
+        )?;
+    }
+    if let Some(workers) = knee {
+        writeln!(
+            out,
+            "Concurrency\t{} workers, found by --auto-concurrency",
+            workers
+        )?;
+    }
+
+    let elapsed_sec = totals.secs as u64;
+    let mut op_agg: Vec<_> = op_agg
+        .iter_mut()
+        .filter(|(op, _)| op.count_label().is_none())
+        .collect();
+    op_agg.sort_by_key(|(op, _)| **op);
+    for (op, worker) in op_agg {
+        match op {
+            Operation::List => {
+                writeln!(out, "{}\t\t{}", op, worker.serialize_listing())?
+            }
+            _ => writeln!(
+                out,
+                "{}\t\t{}",
+                op,
+                worker.serialize_absolute(elapsed_sec)
+            )?,
+        }
+    }
+
+    if let Some(sizes) = sizes {
+        for ((op, size), worker) in sizes.sorted() {
+            writeln!(
+                out,
+                "{} {}\t{}",
+                op,
+                size_label(*size),
+                worker.serialize_absolute(elapsed_sec)
+            )?;
+        }
+    }
+
+    rates.write_human(out)
+}
+
+/*
+ * The tabular format is made to be plotted, so the summary is a comment that
+ * gnuplot and friends will skip. The fields are the wall time in seconds,
+ * operations, bytes, operations per second, bytes per second, errors, and
+ * retries.
+ */
+fn print_tabular_summary(
+    out: &mut dyn Write,
+    title: &str,
+    start_time: SystemTime,
+    op_agg: &HashMap<Operation, WorkerStat>,
+) -> io::Result<()> {
+    let totals = RunTotals::new(start_time, op_agg);
+    writeln!(
+        out,
+        "# {} {:.1} {} {} {:.1} {:.0} {} {}",
+        title.to_lowercase(),
+        totals.secs,
+        totals.ops,
+        totals.bytes,
+        totals.ops_rate(),
+        totals.bytes_rate(),
+        totals.errors,
+        totals.retries
+    )?;
+
+    Ok(())
+}
+
+/*
+ * Has the filesystem holding 'target' been filled to 'cap' percent? We treat
+ * errors as reaching the cap, since we can't tell how full it is.
+ */
+fn percentage_reached(target: &str, cap: u32) -> bool {
+    match fs3::statvfs(target) {
+        Ok(stats) => {
+            let used = stats.total_space() - stats.available_space();
+            let perc_used = (used * 100) / stats.total_space();
+
+            perc_used >= cap.into()
+        }
+        Err(e) => {
+            error!("statvfs error for {}: {}", target, e);
+            true
+        }
+    }
+}
+
+/* How often the stat thread checks whether a snapshot was asked for. */
+const SNAPSHOT_POLL: time::Duration = time::Duration::from_millis(100);
+
+/*
+ * Wait up to 'timeout' for a message on the stop channel, returning true if
+ * one arrived.
+ */
+fn wait_for_stop(stop: &Receiver<()>, timeout: time::Duration) -> bool {
+    match stop.recv_timeout(timeout) {
+        Ok(_) => true,
+        Err(RecvTimeoutError::Timeout) => false,
+        Err(RecvTimeoutError::Disconnected) => {
+            /* Nobody can ask us to stop, wait out the interval. */
+            thread::sleep(timeout);
+            false
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn print_human(
+    out: &mut dyn Write,
+    start_time: SystemTime,
+    format: &OutputFormat,
+    mut op_stats: HashMap<Operation, HashMap<ThreadId, WorkerStat>>,
+    mut op_ticks: HashMap<Operation, WorkerStat>,
+    op_agg: &mut HashMap<Operation, WorkerStat>,
+    ops_rate: Option<OpsRate>,
+    workers: ActiveWorkers,
+    window: Option<&Window>,
+    sizes: &mut SizeStats,
+    timestamp: TimestampFormat,
+) -> io::Result<()> {
+    /* Print out the stats we gathered, one operation at a time. */
+    writeln!(out, "--- {}", timestamp.format(SystemTime::now()))?;
+    if *format == OutputFormat::HumanVerbose {
+        let mut i = 0;
+        let mut op_stats: Vec<_> = op_stats.iter_mut().collect();
+        op_stats.sort_by_key(|(op, _)| **op);
+        for (op, op_map) in op_stats {
+            writeln!(out, "Thread ({})", op)?;
+            for (_, worker) in op_map.iter_mut() {
+                if worker.objs == 0 {
+                    /*
+                     * don't want to divide by zero when there's
+                     * no activity
+                     */
+                    continue;
+                }
+
+                match op.count_label() {
+                    Some(label) => {
+                        writeln!(out, "\t{}: {} {}", i, worker.objs, label)?
+                    }
+                    None if *op == Operation::List => writeln!(
+                        out,
+                        "\t{}: {}",
+                        i,
+                        worker.serialize_listing()
+                    )?,
+                    None => writeln!(
+                        out,
+                        "\t{}: {}",
+                        i,
+                        worker.serialize_relative()
+                    )?,
                 }
                 worker.clear();
                 i += 1;
@@ -227,47 +1613,162 @@ fn print_human(
         }
     }
 
-    for (op, worker) in op_ticks.iter_mut() {
-        print!("Tick ({})", op);
+    let mut op_ticks: Vec<_> = op_ticks.iter_mut().collect();
+    op_ticks.sort_by_key(|(op, _)| **op);
+    for (op, worker) in op_ticks {
+        write!(out, "Tick ({})", op)?;
         if worker.objs == 0 {
-            println!("No activity this tick");
+            writeln!(out, "No activity this tick")?;
             continue;
         }
-        if op == &Operation::Error {
-            println!("\t{} errors", worker.objs);
-        } else {
-            println!("\t{}", worker.serialize_relative());
+        match op.count_label() {
+            Some(label) => writeln!(out, "\t{} {}", worker.objs, label)?,
+            None if *op == Operation::List => {
+                writeln!(out, "\t{}", worker.serialize_listing())?
+            }
+            None => writeln!(out, "\t{}", worker.serialize_relative())?,
+        }
+    }
+
+    if *format == OutputFormat::HumanVerbose {
+        for ((op, size), worker) in sizes.sorted() {
+            writeln!(
+                out,
+                "Size ({} {})\t{}",
+                op,
+                size_label(*size),
+                worker.serialize_relative()
+            )?;
+        }
+    }
+
+    if let Some(w) = window {
+        for rate in w.rates() {
+            writeln!(
+                out,
+                "Window ({})\t{:.1} objs/s, {}/s over the last {} intervals",
+                rate.op,
+                rate.objs,
+                bytes_to_human(rate.data),
+                w.ticks.len()
+            )?;
         }
     }
 
-    for (op, worker) in op_agg.iter_mut() {
-        print!("Total ({})", op);
+    let mut op_agg: Vec<_> = op_agg.iter_mut().collect();
+    op_agg.sort_by_key(|(op, _)| **op);
+    for (op, worker) in op_agg {
+        write!(out, "Total ({})", op)?;
         if worker.objs == 0 {
-            println!("No activity this tick");
+            writeln!(out, "No activity this tick")?;
             continue;
         }
         let elapsed_sec = start_time.elapsed().unwrap().as_secs();
-        if op == &Operation::Error {
-            println!("\t{} errors", worker.objs);
-        } else {
-            println!("\t{}", worker.serialize_absolute(elapsed_sec));
+        match op.count_label() {
+            Some(label) => writeln!(out, "\t{} {}", worker.objs, label)?,
+            None if *op == Operation::List => {
+                writeln!(out, "\t{}", worker.serialize_listing())?
+            }
+            None => {
+                writeln!(out, "\t{}", worker.serialize_absolute(elapsed_sec))?
+            }
         }
     }
+
+    if let Some(rate) = ops_rate {
+        writeln!(
+            out,
+            "Rate\t{:.1} ops/s achieved, {} ops/s target",
+            rate.achieved, rate.target
+        )?;
+    }
+    writeln!(
+        out,
+        "Workers\t{} of {} active",
+        workers.active, workers.target
+    )?;
+
+    Ok(())
 }
 
+/* The rtt percentiles in the tabular format: p50, p90, p99, and max. */
+fn percentiles(stat: &WorkerStat) -> [u128; 4] {
+    [
+        stat.rtt_hist.percentile(50.0).into(),
+        stat.rtt_hist.percentile(90.0).into(),
+        stat.rtt_hist.percentile(99.0).into(),
+        stat.rtt_hist.max().into(),
+    ]
+}
+
+/*
+ * The names of the tabular format's columns, in order, for --tabular-header.
+ * Summed latencies are in millis, and the totals of bytes read and written
+ * cover the whole run rather than the interval.
+ */
+pub fn tabular_columns() -> Vec<String> {
+    let names = |names: &[&str]| -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    };
+    let pcts = |op: &str| -> Vec<String> {
+        ["p50", "p90", "p99", "max"]
+            .iter()
+            .map(|p| format!("{}_rtt_{}", op, p))
+            .collect()
+    };
+
+    let mut cols = names(&[
+        "time",
+        "reads",
+        "writes",
+        "read_bytes",
+        "write_bytes",
+        "read_ttfb",
+        "write_ttfb",
+        "read_rtt",
+        "write_rtt",
+        "errors",
+        "total_read_bytes",
+        "total_write_bytes",
+    ]);
+    cols.extend(pcts("read"));
+    cols.extend(pcts("write"));
+    cols.extend(names(&["retries", "deletes", "delete_ttfb", "delete_rtt"]));
+    cols.extend(pcts("delete"));
+    cols.extend(
+        ErrorKind::ALL[..ErrorKind::ALL.len() - 2]
+            .iter()
+            .map(|kind| format!("{}_errors", kind)),
+    );
+    cols.extend(names(&["lists", "list_entries", "list_ttfb", "list_rtt"]));
+    cols.extend(pcts("list"));
+    cols.extend(names(&["stats", "stat_ttfb", "stat_rtt"]));
+    cols.extend(pcts("stat"));
+    cols.extend(names(&["slow", "undeleted"]));
+    cols.extend(names(&["copies", "copy_ttfb", "copy_rtt"]));
+    cols.extend(pcts("copy"));
+    cols.extend(names(&["appends", "append_bytes", "append_rtt"]));
+    cols.extend(pcts("append"));
+    cols.extend(names(&["expires", "expire_rtt"]));
+    cols.extend(pcts("expire"));
+    cols.extend(names(&["size_errors"]));
+    cols
+}
+
+#[allow(clippy::too_many_arguments)]
 fn print_tabular(
+    out: &mut dyn Write,
     _: SystemTime,
     _: &OutputFormat,
     _: HashMap<Operation, HashMap<ThreadId, WorkerStat>>,
     op_ticks: HashMap<Operation, WorkerStat>,
     op_agg: &mut HashMap<Operation, WorkerStat>,
-) {
+    separator: char,
+    timestamp: TimestampFormat,
+) -> io::Result<()> {
     let zero_stat = WorkerStat::new();
 
-    let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(time) => format!("{}", time.as_secs()),
-        Err(_) => String::from("0"),
-    };
+    let time = timestamp.format(SystemTime::now());
 
     /*
      * Per-tick (-i interval flag) stats.
@@ -282,7 +1783,52 @@ fn print_tabular(
         None => &zero_stat,
     };
 
-    let error_stats = match op_ticks.get(&Operation::Error) {
+    /*
+     * Errors are tracked by cause. Corrupt objects are counted along with the
+     * other errors in the total.
+     */
+    let mut error_kinds: Vec<u64> = ErrorKind::ALL
+        .iter()
+        .map(|kind| match op_ticks.get(&Operation::Error(*kind)) {
+            Some(stats) => stats.objs,
+            None => 0,
+        })
+        .collect();
+    let errors: u64 = error_kinds.iter().sum();
+    let size_errors = error_kinds.pop().unwrap_or(0);
+    let undeleted = error_kinds.pop().unwrap_or(0);
+
+    let delete_stats = match op_ticks.get(&Operation::Delete) {
+        Some(stats) => stats,
+        None => &zero_stat,
+    };
+
+    let retry_stats = match op_ticks.get(&Operation::Retry) {
+        Some(stats) => stats,
+        None => &zero_stat,
+    };
+
+    let list_stats = match op_ticks.get(&Operation::List) {
+        Some(stats) => stats,
+        None => &zero_stat,
+    };
+
+    let stat_stats = match op_ticks.get(&Operation::Stat) {
+        Some(stats) => stats,
+        None => &zero_stat,
+    };
+
+    let copy_stats = match op_ticks.get(&Operation::Copy) {
+        Some(stats) => stats,
+        None => &zero_stat,
+    };
+
+    let append_stats = match op_ticks.get(&Operation::Append) {
+        Some(stats) => stats,
+        None => &zero_stat,
+    };
+
+    let expire_stats = match op_ticks.get(&Operation::Expire) {
         Some(stats) => stats,
         None => &zero_stat,
     };
@@ -300,37 +1846,367 @@ fn print_tabular(
         None => &zero_stat,
     };
 
-    println!(
-        "{} {} {} {} {} {} {} {} {} {} {} {}",
-        time,
-        reader_stats.objs,
-        writer_stats.objs,
-        reader_stats.data,
-        writer_stats.data,
+    /* Slow operations of every kind. */
+    let slow: u64 = op_ticks.values().map(|stats| stats.slow).sum();
+
+    /*
+     * Latency percentiles, retries, deletes, errors by cause, listings,
+     * stats, slow operations, undeleted objects, copies, appends, expires, and
+     * size mismatches are appended to the end so existing consumers of this format (e.g.
+     * plots/throughput.plot) keep working. The columns are named in
+     * tabular_columns(), which has to be kept in step with this.
+     */
+    let mut row = vec![time];
+    let mut push = |vals: &[u128]| row.extend(vals.iter().map(u128::to_string));
+    push(&[
+        reader_stats.objs.into(),
+        writer_stats.objs.into(),
+        reader_stats.data.into(),
+        writer_stats.data.into(),
         reader_stats.ttfb,
         writer_stats.ttfb,
         reader_stats.rtt,
         writer_stats.rtt,
-        error_stats.objs,
-        agg_read.data,
-        agg_write.data,
-    );
+        errors.into(),
+        agg_read.data.into(),
+        agg_write.data.into(),
+    ]);
+    push(&percentiles(reader_stats));
+    push(&percentiles(writer_stats));
+    push(&[
+        retry_stats.objs.into(),
+        delete_stats.objs.into(),
+        delete_stats.ttfb,
+        delete_stats.rtt,
+    ]);
+    push(&percentiles(delete_stats));
+    for objs in &error_kinds {
+        push(&[(*objs).into()]);
+    }
+    push(&[
+        list_stats.objs.into(),
+        list_stats.entries.into(),
+        list_stats.ttfb,
+        list_stats.rtt,
+    ]);
+    push(&percentiles(list_stats));
+    push(&[stat_stats.objs.into(), stat_stats.ttfb, stat_stats.rtt]);
+    push(&percentiles(stat_stats));
+    push(&[slow.into(), undeleted.into()]);
+    push(&[copy_stats.objs.into(), copy_stats.ttfb, copy_stats.rtt]);
+    push(&percentiles(copy_stats));
+    push(&[
+        append_stats.objs.into(),
+        append_stats.data.into(),
+        append_stats.rtt,
+    ]);
+    push(&percentiles(append_stats));
+    push(&[expire_stats.objs.into(), expire_stats.rtt]);
+    push(&percentiles(expire_stats));
+    push(&[size_errors.into()]);
+
+    writeln!(out, "{}", row.join(&separator.to_string()))?;
+
+    Ok(())
 }
 
-#[derive(Debug, PartialEq)]
-pub struct ChumError {
-    msg: String,
+fn json_percentiles(hist: &Histogram) -> serde_json::Value {
+    json!({
+        "p50": hist.percentile(50.0),
+        "p90": hist.percentile(90.0),
+        "p99": hist.percentile(99.0),
+        "max": hist.max(),
+    })
 }
-impl ChumError {
-    pub fn new(msg: &str) -> Self {
-        ChumError {
-            msg: msg.to_string(),
+
+fn json_stat(stat: &WorkerStat) -> serde_json::Value {
+    json!({
+        "objs": stat.objs,
+        "bytes": stat.data,
+        "avg_ttfb_ms": stat.avg_ttfb(),
+        "avg_rtt_ms": stat.avg_rtt(),
+        "ttfb_ms": json_percentiles(&stat.ttfb_hist),
+        "rtt_ms": json_percentiles(&stat.rtt_hist),
+        "slow": stat.slow,
+        "avg_continue_ms": stat.avg_continue(),
+        "new_connections": stat.connects,
+        "reused_connections": stat.reused,
+    })
+}
+
+/*
+ * Roll up a set of per-operation stats into an overall object, along with a
+ * per-operation breakdown. Errors (and corrupt objects) and retries are
+ * counted separately since they don't move any data and would skew the
+ * latency averages. Errors are also broken down by cause.
+ */
+fn json_stats(op_stats: &HashMap<Operation, WorkerStat>) -> serde_json::Value {
+    let mut total = WorkerStat::new();
+    let mut errors = 0;
+    let mut corruption = 0;
+    let mut retries = 0;
+    let mut exists = 0;
+    let mut substituted = 0;
+    let mut ops = serde_json::Map::new();
+    let mut error_kinds = serde_json::Map::new();
+
+    for (op, stat) in op_stats.iter() {
+        match op {
+            Operation::Error(ErrorKind::Corruption) => corruption += stat.objs,
+            Operation::Error(_) => errors += stat.objs,
+            Operation::Retry => retries += stat.objs,
+            Operation::Exists => exists += stat.objs,
+            Operation::Substituted => substituted += stat.objs,
+            Operation::List => {
+                total.merge(stat);
+                let mut list = json_stat(stat);
+                if let Some(map) = list.as_object_mut() {
+                    map.insert("entries".to_string(), json!(stat.entries));
+                }
+                ops.insert(op.to_string(), list);
+            }
+            _ => {
+                total.merge(stat);
+                ops.insert(op.to_string(), json_stat(stat));
+            }
+        }
+        if let Operation::Error(kind) = op {
+            error_kinds.insert(kind.to_string(), json!(stat.objs));
         }
     }
+
+    let mut obj = json_stat(&total);
+    if let Some(map) = obj.as_object_mut() {
+        map.insert("errors".to_string(), json!(errors));
+        map.insert("corruption".to_string(), json!(corruption));
+        map.insert("retries".to_string(), json!(retries));
+        map.insert("exists".to_string(), json!(exists));
+        map.insert("substituted".to_string(), json!(substituted));
+        map.insert("error_kinds".to_string(), json!(error_kinds));
+        map.insert("ops".to_string(), json!(ops));
+    }
+    obj
 }
-impl Error for ChumError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        None
+
+#[allow(clippy::too_many_arguments)]
+fn print_json(
+    out: &mut dyn Write,
+    _: SystemTime,
+    _: &OutputFormat,
+    _: HashMap<Operation, HashMap<ThreadId, WorkerStat>>,
+    op_ticks: HashMap<Operation, WorkerStat>,
+    _: &mut HashMap<Operation, WorkerStat>,
+    ops_rate: Option<OpsRate>,
+    workers: ActiveWorkers,
+    window: Option<&Window>,
+    sizes: &mut SizeStats,
+    timestamp: TimestampFormat,
+) -> io::Result<()> {
+    let mut obj = json_stats(&op_ticks);
+    if let Some(map) = obj.as_object_mut() {
+        map.insert("type".to_string(), json!("interval"));
+        map.insert("time".to_string(), timestamp.json(SystemTime::now()));
+        if let Some(rate) = ops_rate {
+            map.insert("ops_per_sec".to_string(), json!(rate.achieved));
+            map.insert("target_ops_per_sec".to_string(), json!(rate.target));
+        }
+        map.insert("active_workers".to_string(), json!(workers.active));
+        map.insert("target_workers".to_string(), json!(workers.target));
+        if let Some(w) = window {
+            let mut ops = serde_json::Map::new();
+            for rate in w.rates() {
+                ops.insert(
+                    rate.op.to_string(),
+                    json!({
+                        "objs_per_sec": rate.objs,
+                        "bytes_per_sec": rate.data,
+                    }),
+                );
+            }
+            map.insert(
+                "window".to_string(),
+                json!({ "intervals": w.ticks.len(), "ops": ops }),
+            );
+        }
+        if !sizes.stats.is_empty() {
+            map.insert("sizes".to_string(), sizes.json());
+        }
+    }
+    writeln!(out, "{}", obj)?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn print_json_summary(
+    out: &mut dyn Write,
+    title: &str,
+    start_time: SystemTime,
+    op_agg: &HashMap<Operation, WorkerStat>,
+    warmup: Option<time::Duration>,
+    knee: Option<u32>,
+    rates: &IntervalRates,
+    sizes: &mut SizeStats,
+    run: Option<serde_json::Value>,
+) -> io::Result<()> {
+    let elapsed_sec = match start_time.elapsed() {
+        Ok(elapsed) => elapsed.as_secs(),
+        Err(_) => 0,
+    };
+    let totals = RunTotals::new(start_time, op_agg);
+
+    let mut obj = json_stats(op_agg);
+    if let Some(map) = obj.as_object_mut() {
+        map.insert("type".to_string(), json!(title.to_lowercase()));
+        map.insert("elapsed_sec".to_string(), json!(elapsed_sec));
+        map.insert("ops_per_sec".to_string(), json!(totals.ops_rate()));
+        map.insert("bytes_per_sec".to_string(), json!(totals.bytes_rate()));
+        if let Some(w) = warmup {
+            /* Not included in the stats or elapsed time above. */
+            map.insert("warmup_sec".to_string(), json!(w.as_secs_f64()));
+        }
+        if let Some(workers) = knee {
+            map.insert("auto_concurrency".to_string(), json!(workers));
+        }
+        if !sizes.stats.is_empty() {
+            map.insert("sizes".to_string(), sizes.json());
+        }
+        map.insert("interval_rates".to_string(), rates.json());
+        if let Some(run) = run {
+            map.insert("run".to_string(), run);
+        }
+    }
+    writeln!(out, "{}", obj)?;
+
+    Ok(())
+}
+
+/*
+ * What caused an error, so that errors can be counted by cause. This makes it
+ * easier to tell a flaky network from a broken server.
+ */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorKind {
+    Connection, /* refused, reset, or the target couldn't be resolved */
+    Timeout,
+    Throttled,  /* 429 */
+    Client,     /* any other 4xx */
+    Server,     /* 5xx */
+    Corruption, /* a read didn't match what was written */
+    Other,
+    Undeleted, /* --verify-delete found an object after deleting it */
+    Size,      /* --verify-size read a different size than the read list has */
+}
+
+impl ErrorKind {
+    /*
+     * In the order they're reported in the tabular output. Undeleted objects
+     * and size mismatches came later, so they're in columns further along
+     * the row instead.
+     */
+    pub const ALL: [ErrorKind; 9] = [
+        ErrorKind::Connection,
+        ErrorKind::Timeout,
+        ErrorKind::Throttled,
+        ErrorKind::Client,
+        ErrorKind::Server,
+        ErrorKind::Corruption,
+        ErrorKind::Other,
+        ErrorKind::Undeleted,
+        ErrorKind::Size,
+    ];
+
+    /* The cause of an unexpected HTTP status code. */
+    pub fn from_status(code: u32) -> ErrorKind {
+        match code {
+            429 => ErrorKind::Throttled,
+            400..=499 => ErrorKind::Client,
+            500..=599 => ErrorKind::Server,
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /*
+     * Failures that might not happen if the operation is tried again, e.g.
+     * the connection was dropped or the server returned a 5xx.
+     */
+    pub fn is_transient(self) -> bool {
+        matches!(
+            self,
+            ErrorKind::Connection
+                | ErrorKind::Timeout
+                | ErrorKind::Throttled
+                | ErrorKind::Server
+        )
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let str = match self {
+            ErrorKind::Connection => "connection",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Throttled => "throttled",
+            ErrorKind::Client => "4xx",
+            ErrorKind::Server => "5xx",
+            ErrorKind::Corruption => "corruption",
+            ErrorKind::Other => "other",
+            ErrorKind::Undeleted => "undeleted",
+            ErrorKind::Size => "size",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ChumError {
+    msg: String,
+    kind: ErrorKind,
+}
+impl ChumError {
+    pub fn new(msg: &str) -> Self {
+        ChumError::with_kind(ErrorKind::Other, msg)
+    }
+
+    pub fn with_kind(kind: ErrorKind, msg: &str) -> Self {
+        ChumError {
+            msg: msg.to_string(),
+            kind,
+        }
+    }
+
+    /* The data read back didn't match what was written. */
+    pub fn corruption(msg: &str) -> Self {
+        ChumError::with_kind(ErrorKind::Corruption, msg)
+    }
+
+    /* An object was still there after it was deleted. */
+    pub fn undeleted(msg: &str) -> Self {
+        ChumError::with_kind(ErrorKind::Undeleted, msg)
+    }
+
+    /* A read returned a different number of bytes than expected. */
+    pub fn size_mismatch(msg: &str) -> Self {
+        ChumError::with_kind(ErrorKind::Size, msg)
+    }
+
+    /* An unexpected HTTP status code. */
+    pub fn http_status(msg: &str, code: u32) -> Self {
+        let kind = ErrorKind::from_status(code);
+        ChumError::with_kind(kind, &format!("{}: {}", msg, code))
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.kind.is_transient()
+    }
+}
+impl Error for ChumError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
     }
 }
 impl std::fmt::Display for ChumError {
@@ -341,7 +2217,19 @@ impl std::fmt::Display for ChumError {
 /* Wrap errors from libcurl. */
 impl From<curl::Error> for ChumError {
     fn from(err: curl::Error) -> Self {
-        ChumError::new(&format!("{}", err))
+        let kind = if err.is_operation_timedout() {
+            ErrorKind::Timeout
+        } else if err.is_couldnt_connect()
+            || err.is_couldnt_resolve_host()
+            || err.is_send_error()
+            || err.is_recv_error()
+            || err.is_got_nothing()
+        {
+            ErrorKind::Connection
+        } else {
+            ErrorKind::Other
+        };
+        ChumError::with_kind(kind, &format!("{}", err))
     }
 }
 impl From<std::io::Error> for ChumError {
@@ -350,7 +2238,11 @@ impl From<std::io::Error> for ChumError {
     }
 }
 
-/* Convert a human-readable string (e.g. '4k') to bytes (e.g. '4096'). */
+/*
+ * Convert a human-readable string (e.g. '4k') to bytes (e.g. '4096'). This is
+ * how every size on the command line is given. A size may have a fractional
+ * part, e.g. '1.5m', which is rounded to the nearest byte.
+ */
 pub fn parse_human(val: &str) -> Result<u64, ChumError> {
     let k = 1024;
     let m = k * 1024;
@@ -360,197 +2252,1807 @@ pub fn parse_human(val: &str) -> Result<u64, ChumError> {
     if val == "0" {
         return Ok(0);
     }
-    let mix_re = Regex::new(r"^\d+[KMGTkmgt]$").unwrap();
-    if mix_re.is_match(val) {
-        let (first, last) = val.split_at(val.len() - 1);
-        let val_as_bytes: u64 = u64::from_str_radix(first, 10)
-            .map_err(|err| ChumError::new(&err.to_string()))?;
-
-        match last.to_ascii_lowercase().as_ref() {
-            "k" => Ok(val_as_bytes * k),
-            "m" => Ok(val_as_bytes * m),
-            "g" => Ok(val_as_bytes * g),
-            "t" => Ok(val_as_bytes * t),
-            _ => Err(ChumError::new("unrecognized unit suffix")),
-        }
-    } else {
-        Err(ChumError::new(
+    let mix_re = Regex::new(r"^(\d+)(?:\.(\d+))?([KMGTkmgt])$").unwrap();
+    let caps = mix_re.captures(val).ok_or_else(|| {
+        ChumError::new(
             "provided value must be a positive number with a unit suffix",
-        ))
+        )
+    })?;
+
+    let unit: u128 = match caps[3].to_ascii_lowercase().as_ref() {
+        "k" => k,
+        "m" => m,
+        "g" => g,
+        "t" => t,
+        _ => return Err(ChumError::new("unrecognized unit suffix")),
+    };
+
+    /*
+     * Work in units of 10^-digits of the suffix, e.g. 1.5m is 15 tenths of a
+     * megabyte, so that the fraction is exact.
+     */
+    let too_large = || ChumError::new(&format!("'{}' is too large", val));
+    let frac = caps.get(2).map_or("", |f| f.as_str());
+    let scale = 10u128
+        .checked_pow(frac.len() as u32)
+        .ok_or_else(too_large)?;
+    let digits: u128 = format!("{}{}", &caps[1], frac)
+        .parse()
+        .map_err(|_| too_large())?;
+    let bytes = digits
+        .checked_mul(unit)
+        .and_then(|b| b.checked_add(scale / 2))
+        .ok_or_else(too_large)?
+        / scale;
+
+    if bytes > u128::from(u64::MAX) {
+        return Err(too_large());
     }
+    Ok(bytes as u64)
 }
 
 /*
- * Expand an input string like:
- *   1,2,3
- * into a slice like:
- *   [ 1, 2, 3 ]
- *
- * This allows for a single operator to expand a given entry. For example,
- *   1:3,2,3
- * turns into
- *   [ 1, 1, 1, 2, 3 ]
- *
- * That syntax allows the left-operand to be expanded into right-operand copies.
- * This also works with string prefixes:
- *   r:2,w:2
- * turns into
- *   [ r, r, w, w ]
+ * Create a random number generator. With --seed, each worker is given its own
+ * seed derived from the one the user gave, so that a run can be repeated.
  */
-pub fn expand_distribution(dstr: &str) -> Result<Vec<String>, ChumError> {
-    let mut gen_distr = Vec::new();
+pub fn new_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(s) => StdRng::seed_from_u64(s),
+        None => StdRng::from_entropy(),
+    }
+}
 
-    for s in dstr.split(',') {
-        let tok: Vec<&str> = s.split(':').collect();
-        match tok.len() {
-            1 => gen_distr.push(tok[0].to_string()),
-            2 => {
-                for _ in 0..tok[1].parse::<u32>().map_err(|_| {
-                    ChumError::new(&format!(
-                        "failed to parse '{}' as a number",
-                        tok[1]
-                    ))
-                })? {
-                    gen_distr.push(tok[0].to_string());
+/*
+ * Start a thread with a name (e.g. 'chum-worker-3'), so that it can be picked
+ * out in a debugger, a core dump, or 'prstat -L', and in the message if it
+ * panics. Worker threads are also given --thread-stack-size, if there is one.
+ */
+pub fn spawn_named<F, T>(
+    name: &str,
+    stack_size: Option<usize>,
+    f: F,
+) -> thread::JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let mut builder = thread::Builder::new().name(name.to_string());
+    if let Some(size) = stack_size {
+        builder = builder.stack_size(size);
+    }
+    builder
+        .spawn(f)
+        .unwrap_or_else(|e| panic!("failed to start thread {}: {}", name, e))
+}
+
+/*
+ * A weighted choice of sizes, like '128k:3,256k' for --distribution. Each size
+ * is kept once along with its weight, rather than repeated that many times, so
+ * weights can be as big (and ratios as fine) as anyone likes. It's empty when
+ * an option wasn't given.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct SizeDistribution {
+    sizes: Vec<u64>,
+    index: Option<WeightedIndex<u64>>,
+}
+
+impl SizeDistribution {
+    /*
+     * A size given more than once, as in the long form '128k,128k,512k', has
+     * the weights added up. Sizes with a weight of zero are never chosen, so
+     * they're dropped.
+     */
+    pub fn new(
+        weights: Vec<(u64, u64)>,
+    ) -> Result<SizeDistribution, ChumError> {
+        let mut sizes: Vec<u64> = Vec::new();
+        let mut totals: Vec<u64> = Vec::new();
+        for (size, weight) in weights.into_iter().filter(|(_, w)| *w > 0) {
+            match sizes.iter().position(|s| *s == size) {
+                Some(i) => totals[i] += weight,
+                None => {
+                    sizes.push(size);
+                    totals.push(weight);
                 }
             }
-            _ => {
-                return Err(ChumError::new(&format!(
-                    "too many multiples in \
-                     token '{}'",
-                    tok.join(":")
-                )))
+        }
+        if sizes.is_empty() {
+            return Err(ChumError::new(
+                "a distribution needs a non-zero weight",
+            ));
+        }
+        let index = WeightedIndex::new(&totals).map_err(|e| {
+            ChumError::new(&format!("invalid distribution: {}", e))
+        })?;
+        Ok(SizeDistribution {
+            sizes,
+            index: Some(index),
+        })
+    }
+
+    /* The distinct sizes that can be chosen. */
+    pub fn sizes(&self) -> &[u64] {
+        &self.sizes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sizes.is_empty()
+    }
+
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> Option<u64> {
+        let index = self.index.as_ref()?;
+        Some(self.sizes[index.sample(rng)])
+    }
+}
+
+/* Randomly choose an object size from the --distribution list. */
+pub fn choose_size<R: Rng>(dist: &SizeDistribution, rng: &mut R) -> u64 {
+    dist.choose(rng).expect("choosing file size failed")
+}
+
+/*
+ * Choose which part of an object to read. 'dist' is the --read-range
+ * distribution of range lengths, where 0 means the whole object. Returns the
+ * offset and length of a random range within an object of 'size' bytes, or
+ * None if the whole object should be read.
+ */
+pub fn choose_read_range<R: Rng>(
+    dist: &SizeDistribution,
+    size: u64,
+    rng: &mut R,
+) -> Option<(u64, u64)> {
+    let len = dist.choose(rng)?;
+    if len == 0 || len >= size {
+        return None;
+    }
+    Some((rng.gen_range(0, size - len + 1), len))
+}
+
+/*
+ * Choose how much of an object to read. 'dist' is the --read-distribution of
+ * read sizes. Reads start at the beginning of the object, and an object
+ * smaller than the chosen size is read whole, so the sizes actually read are
+ * limited by what was written.
+ */
+pub fn choose_read_size<R: Rng>(
+    dist: &SizeDistribution,
+    size: u64,
+    rng: &mut R,
+) -> Option<(u64, u64)> {
+    choose_read_range(dist, size, rng).map(|(_, len)| (0, len))
+}
+
+/* Format an HTTP Range header value, e.g. 'bytes=0-1023'. */
+pub fn range_header(offset: u64, len: u64) -> String {
+    format!("bytes={}-{}", offset, offset + len - 1)
+}
+
+/*
+ * Checksums of the objects written during this run, keyed by the name that is
+ * put in the read queue. Only kept when the user asks for --verify.
+ */
+pub type Checksums = Arc<Mutex<HashMap<String, u32>>>;
+
+/*
+ * The sizes of the objects in the read list, keyed by name, for
+ * --verify-size. These are read once at the start and never change.
+ */
+pub type ExpectedSizes = Arc<HashMap<String, u64>>;
+
+/*
+ * CRC32 of a 'size' byte payload made up of 'src' repeated (see
+ * fill_payload), computed without building the payload in memory.
+ */
+pub fn payload_checksum(src: &[u8], size: u64) -> u32 {
+    append_checksum(0, src, size)
+}
+
+/*
+ * The checksum of an object after 'size' bytes of payload were appended to
+ * it, given its checksum before. CRC32 carries on from where it left off, so
+ * the object doesn't need to be read back.
+ */
+pub fn append_checksum(sum: u32, src: &[u8], size: u64) -> u32 {
+    let mut hasher = crc32fast::Hasher::new_with_initial(sum);
+    let mut left = size;
+    while left > 0 {
+        let len = std::cmp::min(left, src.len() as u64);
+        hasher.update(&src[..len as usize]);
+        left -= len;
+    }
+    hasher.finalize()
+}
+
+/*
+ * The base64 MD5 of the same payload, as sent in a Content-MD5 header for
+ * --content-md5.
+ */
+pub fn payload_md5(src: &[u8], size: u64) -> String {
+    let mut ctx = md5::Context::new();
+    let mut left = size;
+    while left > 0 {
+        let len = std::cmp::min(left, src.len() as u64);
+        ctx.consume(&src[..len as usize]);
+        left -= len;
+    }
+    base64::encode(&ctx.compute().0)
+}
+
+/*
+ * Compare the checksum of an object that was just read with the one recorded
+ * when it was written. Objects we didn't write (e.g. from --read-list) can't
+ * be checked.
+ */
+pub fn verify_checksum(
+    sums: &Checksums,
+    name: &str,
+    got: u32,
+) -> Result<(), ChumError> {
+    match sums.lock().unwrap().get(name) {
+        Some(want) if *want != got => Err(ChumError::corruption(&format!(
+            "Checksum mismatch for {}: expected {:08x}, got {:08x}",
+            name, want, got
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/*
+ * Compare the number of bytes a whole-object read returned with the size the
+ * read list has for it. Objects that aren't in the read list aren't checked.
+ */
+pub fn verify_size(
+    sizes: &ExpectedSizes,
+    name: &str,
+    got: u64,
+) -> Result<(), ChumError> {
+    match sizes.get(name) {
+        Some(want) if *want != got => Err(ChumError::size_mismatch(&format!(
+            "Size mismatch for {}: expected {} bytes, got {}",
+            name, want, got
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/*
+ * Copy the next chunk of an object's payload into 'into'. The payload is
+ * 'size' bytes long and is made up of 'src' repeated as many times as needed.
+ *
+ * 'offset' is the number of payload bytes that have already been copied and
+ * is advanced by the number of bytes copied this time around. This returns the
+ * number of bytes copied, which is 0 once the whole payload has been produced.
+ * This fits the shape of libcurl's read callback, which may hand us a buffer of
+ * any length.
+ */
+pub fn fill_payload(
+    into: &mut [u8],
+    src: &[u8],
+    offset: &mut u64,
+    size: u64,
+) -> usize {
+    if *offset >= size || src.is_empty() {
+        return 0;
+    }
+
+    let start = (*offset % src.len() as u64) as usize;
+    let n = std::cmp::min(
+        std::cmp::min(into.len() as u64, size - *offset),
+        (src.len() - start) as u64,
+    ) as usize;
+
+    into[..n].copy_from_slice(&src[start..start + n]);
+    *offset += n as u64;
+    n
+}
+
+/* Build an entire 'size' byte payload in memory. */
+pub fn build_payload(src: &[u8], size: u64) -> Vec<u8> {
+    build_payload_range(src, 0, size)
+}
+
+/*
+ * Build 'len' bytes of a payload in memory, starting 'offset' bytes into the
+ * payload. This is handy for sending an object in pieces (e.g. S3 multipart
+ * uploads).
+ */
+pub fn build_payload_range(src: &[u8], offset: u64, len: u64) -> Vec<u8> {
+    let mut buf = vec![0u8; len as usize];
+    let mut pos = offset;
+    loop {
+        let idx = (pos - offset) as usize;
+        if fill_payload(&mut buf[idx..], src, &mut pos, offset + len) == 0 {
+            break;
+        }
+    }
+    buf
+}
+
+/*
+ * A payload that's produced as it's read, so that objects of any size can be
+ * written without holding the whole object in memory.
+ */
+pub struct PayloadReader<'a> {
+    src: &'a [u8],
+    offset: u64,
+    size: u64,
+}
+
+impl<'a> PayloadReader<'a> {
+    pub fn new(src: &'a [u8], size: u64) -> Self {
+        PayloadReader {
+            src,
+            offset: 0,
+            size,
+        }
+    }
+}
+
+impl<'a> std::io::Read for PayloadReader<'a> {
+    fn read(&mut self, into: &mut [u8]) -> std::io::Result<usize> {
+        Ok(fill_payload(into, self.src, &mut self.offset, self.size))
+    }
+}
+
+/*
+ * Limits on --io-buffer, the size of the buffer that object payloads are built
+ * from and that reads are done in.
+ */
+const MIN_IO_BUFFER: u64 = 1024;
+const MAX_IO_BUFFER: u64 = 64 * 1024 * 1024;
+
+const MIN_THREAD_STACK_SIZE: u64 = 64 * 1024;
+
+/*
+ * Compressibility is applied to each block of the buffer, rather than the
+ * buffer as a whole, so that it holds for small objects and small compression
+ * windows.
+ */
+const COMPRESS_BLOCK_SIZE: usize = 4096;
+
+/*
+ * Create the 'size' byte buffer that object payloads are built from. The
+ * leading 'compressibility' fraction of each block is zero-filled and the rest
+ * is random, so 0.0 gives incompressible data and 1.0 gives all zeros.
+ */
+pub fn payload_buffer<R: Rng>(
+    rng: &mut R,
+    compressibility: f64,
+    size: usize,
+) -> Vec<u8> {
+    let mut buf = vec![0u8; size];
+    for block in buf.chunks_mut(COMPRESS_BLOCK_SIZE) {
+        let zeros = (block.len() as f64 * compressibility).round() as usize;
+        rng.fill(&mut block[zeros..]);
+    }
+    buf
+}
+
+/*
+ * Parse --thread-stack-size, e.g. '8m'. Workers need a fair bit of stack for
+ * curl and rusoto, so anything smaller than 64k is surely a mistake.
+ */
+pub fn parse_thread_stack_size(val: &str) -> Result<usize, ChumError> {
+    match parse_human(val) {
+        Ok(size) if size >= MIN_THREAD_STACK_SIZE => Ok(size as usize),
+        _ => Err(ChumError::new(&format!(
+            "thread-stack-size must be at least 64k, got '{}'",
+            val
+        ))),
+    }
+}
+
+/* Parse --io-buffer, e.g. '4k'. */
+pub fn parse_io_buffer(val: &str) -> Result<usize, ChumError> {
+    match parse_human(val) {
+        Ok(size) if (MIN_IO_BUFFER..=MAX_IO_BUFFER).contains(&size) => {
+            Ok(size as usize)
+        }
+        _ => Err(ChumError::new(&format!(
+            "io-buffer must be between 1k and 64m, got '{}'",
+            val
+        ))),
+    }
+}
+
+/* Parse --compressibility, a fraction between 0.0 and 1.0. */
+pub fn parse_compressibility(val: &str) -> Result<f64, ChumError> {
+    match val.parse::<f64>() {
+        Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+        _ => Err(ChumError::new(&format!(
+            "compressibility must be between 0.0 and 1.0, got '{}'",
+            val
+        ))),
+    }
+}
+
+/*
+ * Targets may be given more than once, and each may be a comma-separated list.
+ */
+pub fn parse_targets(vals: &[&str]) -> Vec<String> {
+    vals.iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/*
+ * Turn a webdav or S3 target into the base URL that requests are sent to.
+ * The target is a host with an optional port, and may include a scheme (plain
+ * http is assumed if it doesn't) or a path. The host may be a name, an IPv4
+ * address, or an IPv6 address. An IPv6 address must be in brackets if a port
+ * is given, e.g. '[2001:db8::1]:8080', and gets them if it isn't.
+ */
+pub fn parse_target_url(target: &str) -> Result<String, ChumError> {
+    let bad = |why: &str| {
+        ChumError::new(&format!("invalid target '{}': {}", target, why))
+    };
+
+    let (scheme, rest) = match target.find("://") {
+        Some(i) => match &target[..i] {
+            "http" | "https" => (&target[..i], &target[i + 3..]),
+            _ => return Err(bad("the scheme must be http or https")),
+        },
+        None => ("http", target),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    let path = path.trim_end_matches('/');
+
+    let (host, port) = if authority.starts_with('[') {
+        let end = match authority.find(']') {
+            Some(end) => end,
+            None => return Err(bad("missing ']' after IPv6 address")),
+        };
+        if authority[1..end].parse::<Ipv6Addr>().is_err() {
+            return Err(bad("not a valid IPv6 address"));
+        }
+        match &authority[end + 1..] {
+            "" => (&authority[..=end], None),
+            p if p.starts_with(':') => (&authority[..=end], Some(&p[1..])),
+            _ => return Err(bad("unexpected text after IPv6 address")),
+        }
+    } else if authority.parse::<Ipv6Addr>().is_ok() {
+        return Ok(format!("{}://[{}]{}", scheme, authority, path));
+    } else {
+        let (host, port) = match authority.rfind(':') {
+            Some(i) => (&authority[..i], Some(&authority[i + 1..])),
+            None => (authority, None),
+        };
+        if host.contains(':') {
+            return Err(bad("IPv6 addresses with a port must be in brackets"));
+        }
+        (host, port)
+    };
+
+    if host.is_empty() {
+        return Err(bad("no host"));
+    }
+
+    match port {
+        Some(p) => match p.parse::<u16>() {
+            Ok(port) if port > 0 => {
+                Ok(format!("{}://{}:{}{}", scheme, host, port, path))
             }
+            _ => Err(bad("the port must be a number from 1 to 65535")),
+        },
+        None => Ok(format!("{}://{}{}", scheme, host, path)),
+    }
+}
+
+/*
+ * The prefix a worker writes under with --shard-by-thread: its own directory
+ * within the usual prefix, if there is one.
+ */
+pub fn shard_prefix(prefix: Option<&str>, worker: u32) -> String {
+    match prefix {
+        Some(prefix) => format!("{}/worker-{}", prefix, worker),
+        None => format!("worker-{}", worker),
+    }
+}
+
+/* The worker backends, named as they are on the command line. */
+pub const PROTOCOLS: &[&str] = &["webdav", "s3", "http", "azblob", "fs"];
+
+/* How many workers there are, from --concurrency. */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Concurrency {
+    /* Workers that share all of the targets. */
+    Workers(u32),
+    /* Workers for each target, in the order the targets were given. */
+    PerTarget(Vec<u32>),
+}
+
+/*
+ * Parse --concurrency, which is either a number of workers or a list of them
+ * by target with an optional default for the rest, e.g.
+ * 'host1:80=8,host2:80=4,2'. The list lets targets that can take different
+ * loads be driven together. Target names are as given to --target.
+ */
+pub fn parse_concurrency(
+    spec: &str,
+    targets: &[String],
+) -> Result<Concurrency, ChumError> {
+    let mut default = None;
+    let mut counts = vec![None; targets.len()];
+    let mut listed = false;
+    for tok in spec.split(',') {
+        let (name, num) = match tok.rfind('=') {
+            Some(i) => (Some(tok[..i].trim()), &tok[i + 1..]),
+            None => (None, tok),
+        };
+        let num = match num.trim().parse::<u32>() {
+            Ok(num) if num > 0 => num,
+            _ => {
+                return Err(ChumError::new(&format!(
+                    "'{}' is not a valid concurrency",
+                    tok
+                )))
+            }
+        };
+        match name {
+            Some(name) => match targets.iter().position(|t| t == name) {
+                Some(i) => {
+                    counts[i] = Some(num);
+                    listed = true;
+                }
+                None => {
+                    return Err(ChumError::new(&format!(
+                        "unknown target '{}' in concurrency",
+                        name
+                    )))
+                }
+            },
+            None => default = Some(num),
+        }
+    }
+
+    match default {
+        Some(num) if !listed => return Ok(Concurrency::Workers(num)),
+        _ => (),
+    }
+    counts
+        .iter()
+        .zip(targets)
+        .map(|(count, target)| {
+            count.or(default).ok_or_else(|| {
+                ChumError::new(&format!(
+                    "no concurrency given for target '{}'",
+                    target
+                ))
+            })
+        })
+        .collect::<Result<Vec<u32>, ChumError>>()
+        .map(Concurrency::PerTarget)
+}
+
+/*
+ * Deal out workers to targets, given how many each target gets. Targets take
+ * turns so that each sees its share of the load while workers are ramped up.
+ * Returns the target of each worker, in the order they're started.
+ */
+pub fn pin_workers(counts: &[u32]) -> Vec<usize> {
+    let mut pinned = Vec::new();
+    let most = counts.iter().copied().max().unwrap_or(0);
+    for round in 0..most {
+        for (i, count) in counts.iter().enumerate() {
+            if round < *count {
+                pinned.push(i);
+            }
+        }
+    }
+    pinned
+}
+
+/* Parse --target-weights, which needs a weight for each target. */
+pub fn parse_target_weights(
+    list: &str,
+    ntargets: usize,
+) -> Result<Vec<u32>, ChumError> {
+    let weights = list
+        .split(',')
+        .map(|w| match w.trim().parse::<u32>() {
+            Ok(weight) if weight > 0 => Ok(weight),
+            _ => Err(ChumError::new(&format!(
+                "'{}' is not a valid target weight",
+                w
+            ))),
+        })
+        .collect::<Result<Vec<u32>, ChumError>>()?;
+
+    if weights.len() != ntargets {
+        return Err(ChumError::new(&format!(
+            "{} target weights given for {} targets",
+            weights.len(),
+            ntargets
+        )));
+    }
+    Ok(weights)
+}
+
+/*
+ * Split a --header value (e.g. 'X-Auth-Token: abc') into its name and value.
+ */
+pub fn parse_header(val: &str) -> Result<(String, String), ChumError> {
+    let mut parts = val.splitn(2, ':');
+    let name = parts.next().unwrap_or("").trim();
+    match parts.next() {
+        Some(value)
+            if !name.is_empty() && !name.contains(char::is_whitespace) =>
+        {
+            Ok((name.to_string(), value.trim().to_string()))
+        }
+        _ => Err(ChumError::new(&format!(
+            "invalid header '{}', expected 'Name: value'",
+            val
+        ))),
+    }
+}
+
+/* The separator named by --tabular-separator. */
+pub fn parse_separator(name: &str) -> Result<char, ChumError> {
+    match name {
+        "space" => Ok(' '),
+        "tab" => Ok('\t'),
+        "comma" => Ok(','),
+        _ => Err(ChumError::new(&format!(
+            "invalid tabular separator '{}', expected space, tab, or comma",
+            name
+        ))),
+    }
+}
+
+/*
+ * Convert a human-readable duration (e.g. '5m') to a Duration (e.g. 300
+ * seconds).
+ */
+pub fn parse_duration(val: &str) -> Result<time::Duration, ChumError> {
+    if val == "0" {
+        return Ok(time::Duration::from_secs(0));
+    }
+    let bad = || {
+        ChumError::new(
+            "provided duration must be a positive number with a unit \
+             suffix (ms, s, m, h)",
+        )
+    };
+    let split = val.find(|c: char| !c.is_ascii_digit()).unwrap_or(0);
+    if split == 0 {
+        return Err(bad());
+    }
+    let (first, last) = val.split_at(split);
+    let num: u64 = first.parse().map_err(|err: std::num::ParseIntError| {
+        ChumError::new(&err.to_string())
+    })?;
+
+    let secs = |unit: u64| {
+        num.checked_mul(unit)
+            .map(time::Duration::from_secs)
+            .ok_or_else(|| ChumError::new(&format!("'{}' is too large", val)))
+    };
+    match last {
+        "ms" => Ok(time::Duration::from_millis(num)),
+        "s" => Ok(time::Duration::from_secs(num)),
+        "m" => secs(60),
+        "h" => secs(60 * 60),
+        _ => Err(bad()),
+    }
+}
+
+/*
+ * Parse a duration for an option that's off when it isn't given or is 0, e.g.
+ * --duration or --ttl. Anything else is used however short it is.
+ */
+pub fn parse_optional_duration(
+    val: Option<&str>,
+) -> Result<Option<time::Duration>, ChumError> {
+    match val.map(parse_duration).transpose()? {
+        Some(d) if d == time::Duration::from_secs(0) => Ok(None),
+        d => Ok(d),
+    }
+}
+
+/*
+ * Expand an input string like:
+ *   1,2,3
+ * into a slice like:
+ *   [ 1, 2, 3 ]
+ *
+ * This allows for a single operator to expand a given entry. For example,
+ *   1:3,2,3
+ * turns into
+ *   [ 1, 1, 1, 2, 3 ]
+ *
+ * That syntax allows the left-operand to be expanded into right-operand copies.
+ * This also works with string prefixes:
+ *   r:2,w:2
+ * turns into
+ *   [ r, r, w, w ]
+ */
+pub fn expand_distribution(dstr: &str) -> Result<Vec<String>, ChumError> {
+    let mut gen_distr = Vec::new();
+
+    for (tok, weight) in parse_weights(dstr)? {
+        for _ in 0..weight {
+            gen_distr.push(tok.clone());
+        }
+    }
+
+    Ok(gen_distr)
+}
+
+/*
+ * Split a distribution like 'r:2,w' into its entries and their weights. An
+ * entry without a weight has a weight of one.
+ */
+fn parse_weights(dstr: &str) -> Result<Vec<(String, u32)>, ChumError> {
+    let mut weights = Vec::new();
+
+    for s in dstr.split(',') {
+        let tok: Vec<&str> = s.split(':').collect();
+        match tok.len() {
+            1 => weights.push((tok[0].to_string(), 1)),
+            2 => {
+                let weight = tok[1].parse::<u32>().map_err(|_| {
+                    ChumError::new(&format!(
+                        "failed to parse '{}' as a number",
+                        tok[1]
+                    ))
+                })?;
+                weights.push((tok[0].to_string(), weight));
+            }
+            _ => {
+                return Err(ChumError::new(&format!(
+                    "too many multiples in \
+                     token '{}'",
+                    tok.join(":")
+                )))
+            }
+        };
+    }
+
+    Ok(weights)
+}
+
+/*
+ * Parse a distribution of sizes, e.g. '128k:8,512k,1m', as given to
+ * --distribution, --read-range, and --read-distribution.
+ */
+pub fn parse_distribution(dstr: &str) -> Result<SizeDistribution, ChumError> {
+    let mut weights = Vec::new();
+
+    for (size, weight) in parse_weights(dstr)? {
+        weights.push((parse_human(&size)?, u64::from(weight)));
+    }
+
+    SizeDistribution::new(weights)
+}
+
+pub fn convert_operation_distribution(
+    dstr: Vec<String>,
+) -> Result<Vec<Operation>, ChumError> {
+    let mut gen_distr = Vec::new();
+
+    for s in dstr {
+        gen_distr.push(s.parse()?);
+    }
+
+    Ok(gen_distr)
+}
+
+/*
+ * Parse --storage-class, a distribution of S3 storage classes like
+ * 'STANDARD:3,STANDARD_IA'. Targets may have classes of their own, so any
+ * name is accepted as long as it looks like one.
+ */
+pub fn parse_storage_classes(dstr: &str) -> Result<Vec<String>, ChumError> {
+    let classes = expand_distribution(dstr)?;
+    for class in &classes {
+        if class.is_empty()
+            || !class.chars().all(|c| {
+                c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'
+            })
+        {
+            return Err(ChumError::new(&format!(
+                "'{}' is not a valid storage class",
+                class
+            )));
+        }
+    }
+    Ok(classes)
+}
+
+/*
+ * The user provided the path to a file. This file contains a listing of objects
+ * in the 'chum' namespace that chum should read back.
+ *
+ * This function pulls each of these file names from the listing file and
+ * inserts them into the chum read queue. The read worker will then pull them
+ * off the queue as it normally would (using whatever algorithm the user
+ * specified). A line may end with a weight, after a space, for objects that
+ * should be read more often than others, see parse_read_list_line().
+ *
+ * With 'sized' (--verify-size) each name is followed by the object's size,
+ * and the sizes are returned so that reads can be checked against them.
+ *
+ * The default errors we get from the OS and the uuid crate are pretty plain, so
+ * we wrap them in a more helpful ChumError.
+ */
+pub fn populate_queue(
+    queue: &SharedQueue,
+    readlist: String,
+    sized: bool,
+) -> Result<HashMap<String, u64>, ChumError> {
+    let file = File::open(readlist).map_err(|e| {
+        ChumError::new(&format!(
+            "failed to open read listing file: {}",
+            e.to_string()
+        ))
+    })?;
+    let br = BufReader::new(file);
+    let mut sizes = HashMap::new();
+
+    for pathstr in br.lines() {
+        let pathstr: String = match pathstr {
+            Ok(x) => x,
+            Err(_) => {
+                return Err(ChumError::new(
+                    "failed to read line from read listing file",
+                ))
+            }
+        };
+
+        let (path, weight, size) = parse_read_list_line(&pathstr, sized)?;
+        if let Some(size) = size {
+            sizes.insert(path.clone(), size);
+        }
+        queue.insert_weighted(path, weight);
+    }
+
+    Ok(sizes)
+}
+
+/*
+ * A line of a read list is an object name, optionally followed by whitespace
+ * and a weight, e.g. 'v2/chum/ab/abcd 10' to read that object ten times as
+ * often as one with no weight. Names may have spaces in them, so only a
+ * number at the end is taken as a weight.
+ *
+ * If the list is 'sized' the name is followed by the object's size in bytes,
+ * before any weight, e.g. 'v2/chum/ab/abcd 1048576 10'. The size has to be
+ * there, so one number at the end is a size and two are a size and a weight.
+ */
+fn parse_read_list_line(
+    line: &str,
+    sized: bool,
+) -> Result<(String, u64, Option<u64>), ChumError> {
+    let (mut path, mut weight) = match trailing_number(line) {
+        Some((path, weight)) => (path, Some(weight)),
+        None => (line, None),
+    };
+
+    let mut size = None;
+    if sized {
+        match (weight, trailing_number(path)) {
+            (Some(w), Some((p, s))) => {
+                path = p;
+                size = Some(s);
+                weight = Some(w);
+            }
+            (Some(s), None) => {
+                size = Some(s);
+                weight = None;
+            }
+            (None, _) => {
+                return Err(ChumError::new(&format!(
+                    "read list line '{}' has no size",
+                    line
+                )))
+            }
+        }
+    }
+
+    match weight {
+        Some(0) => Err(ChumError::new(&format!(
+            "read list weight for '{}' must be at least 1",
+            path
+        ))),
+        Some(w) => Ok((path.to_string(), w, size)),
+        None => Ok((path.to_string(), 1, size)),
+    }
+}
+
+/*
+ * Split a number off the end of a read list line, returning the rest of the
+ * line without the whitespace before the number.
+ */
+fn trailing_number(line: &str) -> Option<(&str, u64)> {
+    let (rest, num) = line.trim_end().rsplit_once(char::is_whitespace)?;
+    let num = num.parse::<u64>().ok()?;
+    Some((rest.trim_end(), num))
+}
+
+/*
+ * Write the names of the objects in the queues to a file in the same format as
+ * the read list, so that a later run can read them with --read-list.
+ */
+pub fn save_queue(
+    queues: &[Arc<SharedQueue>],
+    path: &str,
+) -> Result<(), ChumError> {
+    let err = |e: std::io::Error| {
+        ChumError::new(&format!("failed to save queue to {}: {}", path, e))
+    };
+    let mut out = BufWriter::new(File::create(path).map_err(err)?);
+
+    for queue in queues {
+        for (name, weight) in queue.weighted_items() {
+            match weight {
+                1 => writeln!(out, "{}", name),
+                _ => writeln!(out, "{} {}", name, weight),
+            }
+            .map_err(err)?;
+        }
+    }
+    out.flush().map_err(err)
+}
+
+/*
+ * The files that objects are copied from with --source-dir: every regular
+ * file directly inside 'dir', sorted so that a seeded run picks the same ones.
+ */
+pub fn source_files(dir: &str) -> Result<Vec<PathBuf>, ChumError> {
+    let err = |e: std::io::Error| {
+        ChumError::new(&format!("failed to read source dir {}: {}", dir, e))
+    };
+
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir).map_err(err)? {
+        let path = entry.map_err(err)?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return Err(ChumError::new(&format!(
+            "source dir {} has no files in it",
+            dir
+        )));
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::{QueueMode, DEF_QUEUE_CAP};
+    use rand::rngs::ThreadRng;
+    use rand::thread_rng;
+    use std::io::Read;
+
+    #[test]
+    fn test_parse_human() -> Result<(), ChumError> {
+        assert_eq!(parse_human("4k")?, 4096);
+        assert_eq!(parse_human("1M")?, 1048576);
+        assert_eq!(parse_human("1g")?, 1073741824);
+        assert_eq!(parse_human("1T")?, 1099511627776);
+        assert_eq!(parse_human("0")?, 0);
+        assert_eq!(parse_human("0k")?, 0);
+        assert_eq!(parse_human("1.5M")?, 1572864);
+        assert_eq!(parse_human("0.5k")?, 512);
+        assert_eq!(parse_human("1.1k")?, 1126); /* 1126.4 */
+        assert_eq!(parse_human("2.50g")?, 2684354560);
+
+        assert_eq!(
+            parse_human("1Y"),
+            Err(ChumError::new(
+                "provided value \
+                 must be a positive number with a unit suffix"
+            ))
+        );
+        assert_eq!(
+            parse_human("1024b"),
+            Err(ChumError::new(
+                "provided value \
+                 must be a positive number with a unit suffix"
+            ))
+        );
+        assert_eq!(
+            parse_human("1234"),
+            Err(ChumError::new(
+                "provided value \
+                 must be a positive number with a unit suffix"
+            ))
+        );
+
+        assert_eq!(
+            parse_human("-1G"),
+            Err(ChumError::new(
+                "provided value \
+                 must be a positive number with a unit suffix"
+            ))
+        );
+        assert_eq!(
+            parse_human("T1"),
+            Err(ChumError::new(
+                "provided value \
+                 must be a positive number with a unit suffix"
+            ))
+        );
+        for bad in &["1.5", ".5M", "1.M", "1..5M", "1.5.5M", "1,5M", "-1.5G"] {
+            assert_eq!(
+                parse_human(bad),
+                Err(ChumError::new(
+                    "provided value \
+                     must be a positive number with a unit suffix"
+                )),
+                "{}",
+                bad
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_human_overflow() {
+        for big in &["10000000000T", "16777216T", "99999999999999999999999k"] {
+            assert_eq!(
+                parse_human(big),
+                Err(ChumError::new(&format!("'{}' is too large", big)))
+            );
+        }
+        assert_eq!(parse_human("16777215T").unwrap(), u64::MAX - (1 << 40) + 1);
+    }
+
+    #[test]
+    fn test_parse_duration() -> Result<(), ChumError> {
+        assert_eq!(parse_duration("0")?, time::Duration::from_secs(0));
+        assert_eq!(parse_duration("30s")?, time::Duration::from_secs(30));
+        assert_eq!(parse_duration("5m")?, time::Duration::from_secs(300));
+        assert_eq!(parse_duration("1h")?, time::Duration::from_secs(3600));
+        assert_eq!(parse_duration("250ms")?, time::Duration::from_millis(250));
+
+        let big = format!("{}h", u64::MAX / 60);
+        assert_eq!(
+            parse_duration(&big),
+            Err(ChumError::new(&format!("'{}' is too large", big)))
+        );
+        assert!(parse_duration(&format!("{}m", u64::MAX)).is_err());
+
+        for bad in &["30", "5M", "1d", "-1s", "s1", "1.5h", "ms", "5sm"] {
+            assert_eq!(
+                parse_duration(bad),
+                Err(ChumError::new(
+                    "provided duration must be a positive number \
+                     with a unit suffix (ms, s, m, h)"
+                ))
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_optional_duration() -> Result<(), ChumError> {
+        assert_eq!(parse_optional_duration(None)?, None);
+        assert_eq!(parse_optional_duration(Some("0"))?, None);
+        assert_eq!(
+            parse_optional_duration(Some("2m"))?,
+            Some(time::Duration::from_secs(120))
+        );
+
+        /* e.g. '-T 500ms' is a short run, not one without a time limit. */
+        assert_eq!(
+            parse_optional_duration(Some("500ms"))?,
+            Some(time::Duration::from_millis(500))
+        );
+        assert!(parse_optional_duration(Some("5")).is_err());
+        Ok(())
+    }
+
+    /*
+     * libcurl may ask for any amount of data in each read callback. Make sure
+     * we produce exactly the requested number of bytes regardless of how the
+     * object is chunked up.
+     */
+    #[test]
+    fn test_fill_payload() {
+        let src: Vec<u8> = (0..65536).map(|i| (i % 251) as u8).collect();
+
+        for size in &[1024 * 1024, 100 * 1024, 1, 0] {
+            for chunk in &[16384, 65536, 100_000, 524_288] {
+                let mut into = vec![0u8; *chunk];
+                let mut offset = 0;
+                let mut payload = Vec::new();
+                loop {
+                    let n = fill_payload(&mut into, &src, &mut offset, *size);
+                    if n == 0 {
+                        break;
+                    }
+                    payload.extend_from_slice(&into[..n]);
+                }
+                assert_eq!(offset, *size);
+                assert_eq!(payload.len() as u64, *size);
+                for (i, b) in payload.iter().enumerate() {
+                    assert_eq!(*b, src[i % src.len()]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_build_payload() {
+        let src: Vec<u8> = (0..65536).map(|i| (i % 251) as u8).collect();
+
+        assert_eq!(build_payload(&src, 1024 * 1024).len(), 1024 * 1024);
+        assert_eq!(build_payload(&src, 100 * 1024).len(), 100 * 1024);
+        assert_eq!(build_payload(&src, 65536), src);
+        assert!(build_payload(&src, 0).is_empty());
+    }
+
+    /*
+     * Objects much bigger than the source buffer are streamed out in full,
+     * without being built in memory.
+     */
+    #[test]
+    fn test_payload_reader() {
+        let src: Vec<u8> = (0..65536).map(|i| (i % 251) as u8).collect();
+
+        let size = 1024 * 1024 * 1024 + 123;
+        let mut reader = PayloadReader::new(&src, size);
+        let copied = std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+        assert_eq!(copied, size);
+
+        let mut payload = Vec::new();
+        PayloadReader::new(&src, 100_000)
+            .read_to_end(&mut payload)
+            .unwrap();
+        assert_eq!(payload, build_payload(&src, 100_000));
+    }
+
+    #[test]
+    fn test_size_stats() {
+        assert!(size_buckets(&[4096, 4096]).is_empty());
+        let buckets = size_buckets(&[524288, 4096, 524288, 1024]);
+        assert_eq!(buckets, vec![1024, 4096, 524288]);
+        assert_eq!(size_label(524288), "512k");
+        assert_eq!(size_label(1 << 30), "1g");
+        assert_eq!(size_label(1000), "1000");
+
+        let mut sizes = SizeStats::new(&buckets);
+        for (op, size) in &[
+            (Operation::Write, 1024),
+            (Operation::Write, 4096),
+            (Operation::Read, 2000),
+            (Operation::Read, 1 << 20),
+            (Operation::Delete, 0),
+        ] {
+            sizes.add_result(&WorkerInfo {
+                id: thread::current().id(),
+                op: *op,
+                size: *size,
+                ttfb: 0,
+                rtt: 10,
+                continue_ms: None,
+                new_conn: None,
+                entries: 0,
+            });
+        }
+
+        /* Bigger than every bucket goes in the biggest. */
+        let keys: Vec<(Operation, u64)> =
+            sizes.sorted().into_iter().map(|(key, _)| *key).collect();
+        assert_eq!(
+            keys,
+            vec![
+                (Operation::Read, 4096),
+                (Operation::Read, 524288),
+                (Operation::Write, 1024),
+                (Operation::Write, 4096),
+            ]
+        );
+        assert_eq!(sizes.json()["write"]["4k"]["objs"], json!(1));
+    }
+
+    #[test]
+    fn test_window() {
+        let tick = |writes: u64| {
+            let mut stat = WorkerStat::new();
+            for _ in 0..writes {
+                stat.add_result(&WorkerInfo {
+                    id: thread::current().id(),
+                    op: Operation::Write,
+                    size: 1024,
+                    ttfb: 0,
+                    rtt: 0,
+                    continue_ms: None,
+                    new_conn: None,
+                    entries: 0,
+                });
+            }
+            let mut ticks = HashMap::new();
+            ticks.insert(Operation::Write, stat);
+            ticks
+        };
+
+        let mut w = Window::new(3);
+        w.push(1.0, &tick(100));
+        w.push(1.0, &tick(10));
+        w.push(2.0, &tick(50));
+        let rates = w.rates();
+        assert_eq!(rates.len(), 1);
+        assert_eq!(rates[0].objs, 40.0);
+        assert_eq!(rates[0].data, 40 * 1024);
+
+        /* The first interval falls out of the window. */
+        w.push(1.0, &tick(20));
+        assert_eq!(w.rates()[0].objs, 20.0);
+    }
+
+    #[test]
+    fn test_run_totals() {
+        let info = |op, size| WorkerInfo {
+            id: thread::current().id(),
+            op,
+            size,
+            ttfb: 1,
+            rtt: 2,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        };
+        let mut op_agg = HashMap::new();
+        for (op, size) in &[
+            (Operation::Read, 100),
+            (Operation::Write, 200),
+            (Operation::Write, 300),
+            (Operation::Retry, 0),
+            (Operation::Exists, 0),
+            (Operation::Substituted, 0),
+            (Operation::Error(ErrorKind::Timeout), 0),
+        ] {
+            op_agg
+                .entry(*op)
+                .or_insert_with(WorkerStat::new)
+                .add_result(&info(*op, *size));
+        }
+
+        /*
+         * Errors, retries, writes that found an object already there, and
+         * substituted reads are counted, but they aren't operations.
+         */
+        let totals = RunTotals::new(SystemTime::now(), &op_agg);
+        assert_eq!(totals.ops, 3);
+        assert_eq!(totals.bytes, 600);
+        assert_eq!(totals.errors, 1);
+        assert_eq!(totals.retries, 1);
+        assert_eq!(totals.exists, 1);
+        assert_eq!(totals.substituted, 1);
+    }
+
+    #[test]
+    fn test_stats_out() {
+        let mut op_agg = HashMap::new();
+        op_agg.insert(Operation::Write, WorkerStat::new());
+
+        /* Stats go wherever they're asked to, not necessarily stdout. */
+        let mut out = Vec::new();
+        print_tabular_summary(&mut out, "Summary", SystemTime::now(), &op_agg)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("# summary "));
+        assert_eq!(out.lines().count(), 1);
+
+        /* So is the count of objects cleaned up after the run. */
+        for (format, text) in &[
+            (OutputFormat::Tabular, "# cleanup 3\n"),
+            (OutputFormat::Human, "Cleaned up\t3 objects\n"),
+            (OutputFormat::Json, "{\"deleted\":3,\"type\":\"cleanup\"}\n"),
+        ] {
+            let mut out = Vec::new();
+            print_cleanup(&mut out, format, 3).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), *text);
+        }
+
+        /* A snapshot part way through is labelled as one. */
+        for (format, start) in &[
+            (OutputFormat::Tabular, "# snapshot "),
+            (OutputFormat::Human, "=== Snapshot ===\n"),
+            (OutputFormat::Json, "{"),
+        ] {
+            let mut out = Vec::new();
+            print_summary(
+                &mut out,
+                format,
+                "Snapshot",
+                SystemTime::now(),
+                &mut op_agg,
+                None,
+                None,
+                &IntervalRates::new(),
+                &mut SizeStats::new(&[]),
+                None,
+                TimestampFormat::Epoch,
+            )
+            .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert!(out.starts_with(start), "{}", out);
+            if *format == OutputFormat::Json {
+                assert!(out.contains("\"type\":\"snapshot\""), "{}", out);
+            }
+        }
+
+        /* The final summary says what was run. */
+        let run = RunInfo {
+            command: String::from("chum worker fs -t /var/tmp"),
+            version: "1.0.0",
+            protocol: String::from("fs"),
+            targets: vec![String::from("/var/tmp")],
+            concurrency: 4,
+            auto_concurrency: false,
+            distribution: String::from("128k"),
+            workload: String::from("r,w"),
+            started: UNIX_EPOCH + time::Duration::from_secs(1_600_000_000),
+        };
+        for format in &[OutputFormat::Human, OutputFormat::Json] {
+            let mut out = Vec::new();
+            print_summary(
+                &mut out,
+                format,
+                "Summary",
+                SystemTime::now(),
+                &mut op_agg,
+                None,
+                None,
+                &IntervalRates::new(),
+                &mut SizeStats::new(&[]),
+                Some(&run),
+                TimestampFormat::Rfc3339,
+            )
+            .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            if *format == OutputFormat::Json {
+                assert!(out.contains("\"concurrency\":4"), "{}", out);
+                assert!(
+                    out.contains("\"started\":\"2020-09-13T12:26:40Z\""),
+                    "{}",
+                    out
+                );
+            } else {
+                assert!(out.starts_with(
+                    "=== Summary ===\nCommand\t\tchum worker fs -t /var/tmp\n"
+                ));
+                assert!(out.contains("Target\t\tfs /var/tmp\n"), "{}", out);
+                assert!(out.contains("Workers\t\t4\n"), "{}", out);
+            }
+        }
+
+        /* A warmup of part of a second isn't rounded away. */
+        for (format, text) in &[
+            (OutputFormat::Human, "Warmup\t\t1.5s,"),
+            (OutputFormat::Json, "\"warmup_sec\":1.5"),
+        ] {
+            let mut out = Vec::new();
+            print_summary(
+                &mut out,
+                format,
+                "Summary",
+                SystemTime::now(),
+                &mut op_agg,
+                Some(time::Duration::from_millis(1500)),
+                None,
+                &IntervalRates::new(),
+                &mut SizeStats::new(&[]),
+                None,
+                TimestampFormat::Epoch,
+            )
+            .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            assert!(out.contains(text), "{}", out);
+        }
+    }
+
+    #[test]
+    fn test_command_line() {
+        let args = |line: &str| {
+            command_line(line.split(' ').map(String::from).collect::<Vec<_>>())
+        };
+        assert_eq!(
+            args("chum worker s3 -t 127.0.0.1:9000 -d 128k:3,256k"),
+            "chum worker s3 -t 127.0.0.1:9000 -d 128k:3,256k"
+        );
+        assert_eq!(
+            args("chum worker s3 --secret-key hunter2 --access-key me"),
+            "chum worker s3 --secret-key ... --access-key me"
+        );
+        assert_eq!(
+            args("chum worker http --proxy=http://u:p@proxy -H X-Auth:abc"),
+            "chum worker http --proxy=... -H ..."
+        );
+        assert_eq!(
+            command_line(vec![String::from("it's here"), String::new()]),
+            "'it'\\''s here' ''"
+        );
+    }
+
+    #[test]
+    fn test_think_time() {
+        let ms = time::Duration::from_millis;
+        assert_eq!(
+            "100ms".parse::<ThinkTime>().unwrap(),
+            ThinkTime::Constant(ms(100))
+        );
+        assert_eq!(
+            "constant:0".parse::<ThinkTime>().unwrap(),
+            ThinkTime::Constant(ms(0))
+        );
+        assert_eq!(
+            "uniform:50ms-1s".parse::<ThinkTime>().unwrap(),
+            ThinkTime::Uniform(ms(50), ms(1000))
+        );
+        assert_eq!(
+            "exp:20ms".parse::<ThinkTime>().unwrap(),
+            ThinkTime::Exponential(ms(20))
+        );
+        for bad in &["100", "uniform:50ms", "uniform:2s-1s", "normal:1s"] {
+            assert!(bad.parse::<ThinkTime>().is_err(), "{}", bad);
+        }
+
+        let mut rng = new_rng(Some(1));
+        assert_eq!(ThinkTime::Constant(ms(5)).choose(&mut rng), ms(5));
+        let uniform = ThinkTime::Uniform(ms(50), ms(60));
+        for _ in 0..100 {
+            let d = uniform.choose(&mut rng);
+            assert!(d >= ms(50) && d <= ms(60), "{:?}", d);
+        }
+
+        /* The exponential ones average out to the mean. */
+        let exp = ThinkTime::Exponential(ms(100));
+        let total: time::Duration =
+            (0..10_000).map(|_| exp.choose(&mut rng)).sum();
+        let mean = total.as_secs_f64() / 10_000.0;
+        assert!((mean - 0.1).abs() < 0.01, "{}", mean);
+    }
+
+    #[test]
+    fn test_name_scheme() {
+        let seq = Namer::new(NameScheme::Seq);
+        let other = seq.clone();
+        assert_eq!(seq.name(), "0000000000000000");
+        assert_eq!(other.name(), "0000000000000001");
+
+        /* Hashed names don't start in order, but are the same every run. */
+        let hash = Namer::new(NameScheme::Hash);
+        let names: Vec<_> = (0..16).map(|_| hash.name()).collect();
+        assert!(names[0].ends_with("-0000000000000000"));
+        assert_eq!(names[0].len(), 25);
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_ne!(sorted, names);
+        assert_eq!(Namer::new(NameScheme::Hash).name(), names[0]);
+
+        let stamp = Namer::new(NameScheme::Timestamp);
+        let (a, b) = (stamp.name(), stamp.name());
+        assert!(a[..16] <= b[..16]);
+
+        assert!("uuid".parse::<NameScheme>().is_ok());
+        assert!("random".parse::<NameScheme>().is_err());
+    }
+
+    #[test]
+    fn test_interval_rates() {
+        let mut rates = IntervalRates::new();
+        for objs in &[100, 100, 90, 0, 100] {
+            let mut op_ticks = HashMap::new();
+            let mut stat = WorkerStat::new();
+            stat.objs = *objs;
+            stat.data = *objs * 1024 * 1024;
+            op_ticks.insert(Operation::Write, stat);
+            rates.push(2.0, &op_ticks);
+        }
+        rates.push(0.0, &HashMap::new());
+        assert_eq!(rates.ops, vec![50.0, 50.0, 45.0, 0.0, 50.0]);
+
+        /* The stall stands out at the bottom, away from the rest. */
+        let hist = RateHistogram::new(&rates.data);
+        assert_eq!(hist.bounds.len(), RATE_BINS + 1);
+        assert_eq!(hist.bounds[RATE_BINS], 50.0);
+        assert_eq!(hist.counts, vec![1, 0, 0, 0, 0, 0, 0, 0, 0, 4]);
+
+        let mut out = Vec::new();
+        rates.write_human(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("Ops/s in each of 5 intervals\n"));
+        assert!(
+            text.contains(&format!("4 {}\n", "#".repeat(40))),
+            "{}",
+            text
+        );
+
+        /* Nothing happening at all is one bin of zeros. */
+        let hist = RateHistogram::new(&[0.0, 0.0]);
+        assert_eq!(hist.counts[0], 2);
+    }
+
+    #[test]
+    fn test_tabular_columns() {
+        /* Every column is named, and each row has all of them. */
+        for sep in &[' ', '\t', ','] {
+            let mut op_ticks = HashMap::new();
+            op_ticks.insert(Operation::Write, WorkerStat::new());
+            let mut out = Vec::new();
+            print_tabular(
+                &mut out,
+                SystemTime::now(),
+                &OutputFormat::Tabular,
+                HashMap::new(),
+                op_ticks,
+                &mut HashMap::new(),
+                *sep,
+                TimestampFormat::Epoch,
+            )
+            .unwrap();
+            let out = String::from_utf8(out).unwrap();
+            let row: Vec<_> = out.trim_end().split(*sep).collect();
+            assert_eq!(row.len(), tabular_columns().len());
+        }
+
+        let cols = tabular_columns();
+        assert_eq!(cols[0], "time");
+        assert_eq!(cols[4], "write_bytes");
+        assert!(cols.contains(&String::from("5xx_errors")));
+        assert!(!cols.contains(&String::from("undeleted_errors")));
+        assert_eq!(cols.last().unwrap(), "size_errors");
+
+        assert_eq!(parse_separator("comma").unwrap(), ',');
+        assert!(parse_separator(";").is_err());
+    }
+
+    #[test]
+    fn test_timestamp_format() {
+        let time = UNIX_EPOCH + time::Duration::from_secs(1_591_012_800);
+        assert_eq!(TimestampFormat::Epoch.format(time), "1591012800");
+        assert_eq!(
+            TimestampFormat::Rfc3339.format(time),
+            "2020-06-01T12:00:00Z"
+        );
+        assert_eq!(TimestampFormat::Epoch.json(time), json!(1591012800));
+        assert_eq!(
+            TimestampFormat::Rfc3339.json(time),
+            json!("2020-06-01T12:00:00Z")
+        );
+        assert!("iso".parse::<TimestampFormat>().is_err());
+    }
+
+    #[test]
+    fn test_write_event() {
+        let info = WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Write,
+            size: 1024,
+            ttfb: 3,
+            rtt: 5,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
         };
+        let mut ok = WorkerResult::new(Operation::Write, Ok(info));
+        ok.time = UNIX_EPOCH + time::Duration::from_millis(1_600_000_000_250);
+        let err = WorkerResult::new(
+            Operation::Read,
+            Err(ChumError::with_kind(ErrorKind::Timeout, "timed out")),
+        );
+
+        let mut out = Vec::new();
+        write_event(&mut out, &ok).unwrap();
+        write_event(&mut out, &err).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let rows: Vec<Vec<&str>> =
+            out.lines().map(|l| l.split(',').collect()).collect();
+
+        /* Every row has a value for every column in the header. */
+        let columns = EVENT_LOG_HEADER.split(',').count();
+        assert!(rows.iter().all(|r| r.len() == columns));
+
+        assert_eq!(rows[0][0], "1600000000.250");
+        assert_eq!(&rows[0][2..], &["write", "1024", "3", "5", "ok"]);
+        assert_eq!(&rows[1][2..], &["read", "0", "0", "0", "timeout"]);
+
+        /* The scatter file only has the reads and writes that worked. */
+        let mut out = Vec::new();
+        write_scatter(&mut out, &ok).unwrap();
+        write_scatter(&mut out, &err).unwrap();
+        let retry = WorkerResult::new(
+            Operation::Write,
+            Ok(WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Retry,
+                size: 0,
+                ttfb: 0,
+                rtt: 0,
+                continue_ms: None,
+                new_conn: None,
+                entries: 0,
+            }),
+        );
+        write_scatter(&mut out, &retry).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "write,1024,5\n");
+        assert_eq!(SCATTER_HEADER.split(',').count(), 3);
     }
 
-    Ok(gen_distr)
-}
+    #[test]
+    fn test_check_errors() {
+        let info = |op| WorkerInfo {
+            id: thread::current().id(),
+            op,
+            size: 0,
+            ttfb: 1,
+            rtt: 2,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        };
+        let mut op_ticks = HashMap::new();
+        for op in &[
+            Operation::Read,
+            Operation::Write,
+            Operation::Write,
+            Operation::Retry,
+            Operation::Error(ErrorKind::Timeout),
+        ] {
+            op_ticks
+                .entry(*op)
+                .or_insert_with(WorkerStat::new)
+                .add_result(&info(*op));
+        }
+        assert_eq!(count_errors(&op_ticks), 1);
 
-/*
- * Converts a distribution created by expand_distribution into a Vec of numbers
- * based on the unit size.
- */
-pub fn convert_numeric_distribution(
-    dstr: Vec<String>,
-) -> Result<Vec<u64>, ChumError> {
-    let mut gen_distr = Vec::new();
+        /* One of four operations failed, retries aside. */
+        assert!(check_errors(&op_ticks, 1, None, None).is_none());
+        assert!(check_errors(&op_ticks, 1, Some(25.0), None).is_none());
+        assert!(check_errors(&op_ticks, 1, Some(20.0), None).is_some());
 
-    for s in dstr {
-        gen_distr.push(parse_human(&s)?);
+        /* The error count is over the whole run, not the tick. */
+        assert!(check_errors(&op_ticks, 5, None, Some(5)).is_none());
+        assert!(check_errors(&op_ticks, 6, None, Some(5)).is_some());
+
+        /* An idle tick never trips the rate. */
+        assert!(check_errors(&HashMap::new(), 0, Some(0.0), None).is_none());
     }
 
-    Ok(gen_distr)
-}
+    /* An even distribution of the given sizes. */
+    fn sizes(sizes: &[u64]) -> SizeDistribution {
+        SizeDistribution::new(sizes.iter().map(|s| (*s, 1)).collect()).unwrap()
+    }
 
-pub fn convert_operation_distribution(
-    dstr: Vec<String>,
-) -> Result<Vec<Operation>, ChumError> {
-    let mut gen_distr = Vec::new();
+    #[test]
+    fn test_choose_read_range() {
+        let mut rng = thread_rng();
 
-    for s in dstr {
-        gen_distr.push(s.parse()?);
+        /* No distribution, or a zero length, means a whole-object read. */
+        let none = SizeDistribution::default();
+        assert_eq!(choose_read_range(&none, 1024, &mut rng), None);
+        assert_eq!(choose_read_range(&sizes(&[0]), 1024, &mut rng), None);
+
+        /* Ranges that cover the whole object are whole-object reads. */
+        assert_eq!(choose_read_range(&sizes(&[1024]), 1024, &mut rng), None);
+        assert_eq!(choose_read_range(&sizes(&[4096]), 1024, &mut rng), None);
+
+        for _ in 0..1000 {
+            let (offset, len) =
+                choose_read_range(&sizes(&[100]), 1024, &mut rng).unwrap();
+            assert_eq!(len, 100);
+            assert!(offset + len <= 1024);
+        }
     }
 
-    Ok(gen_distr)
-}
+    #[test]
+    fn test_choose_read_size() {
+        let mut rng = thread_rng();
 
-/*
- * The user provided the path to a file. This file contains a listing of objects
- * in the 'chum' namespace that chum should read back.
- *
- * This function pulls each of these file names from the listing file and
- * inserts them into the chum read queue. The read worker will then pull them
- * off the queue as it normally would (using whatever algorithm the user
- * specified).
- *
- * The default errors we get from the OS and the uuid crate are pretty plain, so
- * we wrap them in a more helpful ChumError.
- */
-pub fn populate_queue(
-    queue: Arc<Mutex<Queue<String>>>,
-    readlist: String,
-) -> Result<(), ChumError> {
-    let file = File::open(readlist).map_err(|e| {
-        ChumError::new(&format!(
-            "failed to open read listing file: {}",
-            e.to_string()
-        ))
-    })?;
-    let br = BufReader::new(file);
+        /* Objects no bigger than the read size are read whole. */
+        assert_eq!(choose_read_size(&sizes(&[0]), 1024, &mut rng), None);
+        assert_eq!(choose_read_size(&sizes(&[4096]), 1024, &mut rng), None);
 
-    let mut q = queue.lock().unwrap();
-    for pathstr in br.lines() {
-        let pathstr: String = match pathstr {
-            Ok(x) => x,
-            Err(_) => {
-                return Err(ChumError::new(
-                    "failed to read line from read listing file",
-                ))
-            }
-        };
+        let dist = sizes(&[100, 200]);
+        for _ in 0..1000 {
+            let (offset, len) =
+                choose_read_size(&dist, 1024, &mut rng).unwrap();
+            assert_eq!(offset, 0);
+            assert!(len == 100 || len == 200);
+        }
+    }
 
-        q.insert(pathstr);
+    #[test]
+    fn test_range_header() {
+        assert_eq!(range_header(0, 1024), "bytes=0-1023");
+        assert_eq!(range_header(100, 1), "bytes=100-100");
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_payload_checksum() {
+        let src: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        for size in &[0, 1, 999, 1000, 1001, 123_456] {
+            assert_eq!(
+                payload_checksum(&src, *size),
+                crc32fast::hash(&build_payload(&src, *size))
+            );
+        }
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_payload_md5() {
+        /* The MD5 of nothing is d41d8cd98f00b204e9800998ecf8427e. */
+        assert_eq!(payload_md5(b"abc", 0), "1B2M2Y8AsgTpgAmY7PhCfg==");
+
+        let src: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        for size in &[1, 999, 1000, 1001, 123_456] {
+            assert_eq!(
+                payload_md5(&src, *size),
+                base64::encode(&md5::compute(build_payload(&src, *size)).0)
+            );
+        }
+    }
 
     #[test]
-    fn test_parse_human() -> Result<(), ChumError> {
-        assert_eq!(parse_human("4k")?, 4096);
-        assert_eq!(parse_human("1M")?, 1048576);
-        assert_eq!(parse_human("1g")?, 1073741824);
-        assert_eq!(parse_human("1T")?, 1099511627776);
+    fn test_append_checksum() {
+        let a: Vec<u8> = (0..100).collect();
+        let b: Vec<u8> = (100..200).collect();
+        let mut whole = build_payload(&a, 250);
+        whole.extend(build_payload(&b, 130));
 
-        assert_eq!(
-            parse_human("1Y"),
-            Err(ChumError::new(
-                "provided value \
-                 must be a positive number with a unit suffix"
-            ))
-        );
-        assert_eq!(
-            parse_human("1024b"),
-            Err(ChumError::new(
-                "provided value \
-                 must be a positive number with a unit suffix"
-            ))
-        );
-        assert_eq!(
-            parse_human("1234"),
-            Err(ChumError::new(
-                "provided value \
-                 must be a positive number with a unit suffix"
-            ))
-        );
+        let sum = append_checksum(payload_checksum(&a, 250), &b, 130);
+        assert_eq!(sum, crc32fast::hash(&whole));
+        assert_eq!(append_checksum(sum, &b, 0), sum);
+    }
 
+    #[test]
+    fn test_verify_checksum() {
+        let sums: Checksums = Arc::new(Mutex::new(HashMap::new()));
+        sums.lock().unwrap().insert("obj".to_string(), 0x1234);
+
+        assert!(verify_checksum(&sums, "obj", 0x1234).is_ok());
+
+        /* We don't know anything about objects we didn't write. */
+        assert!(verify_checksum(&sums, "other", 0x5678).is_ok());
+
+        let err = verify_checksum(&sums, "obj", 0x5678).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Corruption);
         assert_eq!(
-            parse_human("-1G"),
-            Err(ChumError::new(
-                "provided value \
-                 must be a positive number with a unit suffix"
-            ))
-        );
-        assert_eq!(
-            parse_human("T1"),
-            Err(ChumError::new(
-                "provided value \
-                 must be a positive number with a unit suffix"
-            ))
+            ChumError::new("some other failure").kind(),
+            ErrorKind::Other
         );
-        Ok(())
     }
 
     #[test]
-    #[should_panic(expected = "attempt to multiply with overflow")]
-    fn test_parse_human_panic() {
-        /* Ideally we would handle these cases without panicking */
-        let _ = parse_human("10000000000T");
+    fn test_verify_size() {
+        let mut sizes = HashMap::new();
+        sizes.insert("obj".to_string(), 1024);
+        let sizes: ExpectedSizes = Arc::new(sizes);
+
+        assert!(verify_size(&sizes, "obj", 1024).is_ok());
+        assert!(verify_size(&sizes, "written", 10).is_ok());
+
+        let err = verify_size(&sizes, "obj", 1000).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Size);
+        assert!(!err.kind().is_transient());
+    }
+
+    #[test]
+    fn test_build_payload_range() {
+        let src: Vec<u8> = (0..65536).map(|i| (i % 251) as u8).collect();
+        let whole = build_payload(&src, 300_000);
+
+        let mut pieces = Vec::new();
+        pieces.extend(build_payload_range(&src, 0, 100_000));
+        pieces.extend(build_payload_range(&src, 100_000, 150_000));
+        pieces.extend(build_payload_range(&src, 250_000, 50_000));
+        assert_eq!(pieces, whole);
     }
 
     #[test]
@@ -575,28 +4077,368 @@ mod tests {
     }
 
     #[test]
-    fn test_convert_numeric_distribution() -> Result<(), ChumError> {
+    fn test_parse_distribution() -> Result<(), ChumError> {
         assert_eq!(
-            convert_numeric_distribution(expand_distribution("1k,2k,3k")?)?,
-            vec![1024, 2048, 3072]
+            parse_distribution("1k,2k,3k")?.sizes(),
+            &[1024, 2048, 3072]
         );
 
         assert_eq!(
-            convert_numeric_distribution(expand_distribution("1,2,3")?),
-            Err(ChumError::new(
+            parse_distribution("1,2,3").unwrap_err(),
+            ChumError::new(
                 "provided value must be a positive number \
                  with a unit suffix"
-            ))
+            )
         );
 
         assert_eq!(
-            convert_numeric_distribution(expand_distribution("a,b,c")?),
-            Err(ChumError::new(
+            parse_distribution("a,b,c").unwrap_err(),
+            ChumError::new(
                 "provided value must be a positive number \
                  with a unit suffix"
-            ))
+            )
+        );
+
+        /* The long form is the same as the short form. */
+        let dist = parse_distribution("128k,128k,128k,512k")?;
+        assert_eq!(dist.sizes(), &[131072, 524288]);
+
+        /* Sizes that can't be chosen are left out. */
+        assert_eq!(parse_distribution("1k:0,2k")?.sizes(), &[2048]);
+        assert!(parse_distribution("1k:0").is_err());
+
+        /*
+         * Weights aren't expanded, so big ones are fine, and sizes are chosen
+         * in proportion to them.
+         */
+        let dist = parse_distribution("1k:4000000000,2k:1000000000")?;
+        assert_eq!(dist.sizes(), &[1024, 2048]);
+        let mut rng = new_rng(Some(7));
+        let small = (0..10000)
+            .filter(|_| choose_size(&dist, &mut rng) == 1024)
+            .count();
+        assert!(small > 7500 && small < 8500, "{}", small);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_http_status() {
+        let err = ChumError::http_status("Writing abcd failed", 503);
+        assert_eq!(err.to_string(), "Writing abcd failed: 503");
+        assert_eq!(err.kind(), ErrorKind::Server);
+        assert!(err.is_transient());
+
+        let err = ChumError::http_status("Reading abcd failed", 429);
+        assert_eq!(err.kind(), ErrorKind::Throttled);
+        assert!(err.is_transient());
+
+        let err = ChumError::http_status("Reading abcd failed", 404);
+        assert_eq!(err.kind(), ErrorKind::Client);
+        assert!(!err.is_transient());
+
+        /* e.g. a 200 when --expect-status says otherwise */
+        let err = ChumError::http_status("Reading abcd failed", 200);
+        assert_eq!(err.kind(), ErrorKind::Other);
+
+        assert!(!ChumError::new("some other failure").is_transient());
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header("X-Auth-Token: abc").unwrap(),
+            ("X-Auth-Token".to_string(), "abc".to_string())
+        );
+        assert_eq!(
+            parse_header("x-amz-storage-class:STANDARD_IA").unwrap(),
+            ("x-amz-storage-class".to_string(), "STANDARD_IA".to_string())
+        );
+
+        /* Only the first colon separates the name from the value. */
+        assert_eq!(
+            parse_header("Forwarded: for=10.0.0.1:8080").unwrap(),
+            ("Forwarded".to_string(), "for=10.0.0.1:8080".to_string())
+        );
+
+        /* An empty value is how curl is told to drop a header it'd send. */
+        assert_eq!(
+            parse_header("Expect:").unwrap(),
+            ("Expect".to_string(), String::new())
+        );
+
+        assert!(parse_header("X-Auth-Token").is_err());
+        assert!(parse_header(": abc").is_err());
+        assert!(parse_header("X Auth: abc").is_err());
+    }
+
+    #[test]
+    fn test_payload_buffer() {
+        const PAYLOAD_BUF_SIZE: usize = 65536;
+        let mut rng = thread_rng();
+        let zeros = |buf: &[u8]| buf.iter().filter(|b| **b == 0).count();
+        let payload_buffer =
+            |rng: &mut ThreadRng, c| payload_buffer(rng, c, PAYLOAD_BUF_SIZE);
+
+        let buf = payload_buffer(&mut rng, 1.0);
+        assert_eq!(buf.len(), PAYLOAD_BUF_SIZE);
+        assert_eq!(zeros(&buf), PAYLOAD_BUF_SIZE);
+
+        /*
+         * Random data has the odd zero byte in it, so allow for some slack
+         * beyond the zero-filled part of each block.
+         */
+        let buf = payload_buffer(&mut rng, 0.0);
+        assert!(zeros(&buf) < PAYLOAD_BUF_SIZE / 100);
+
+        let buf = payload_buffer(&mut rng, 0.5);
+        assert!(buf[..COMPRESS_BLOCK_SIZE / 2].iter().all(|b| *b == 0));
+        assert!(zeros(&buf) >= PAYLOAD_BUF_SIZE / 2);
+        assert!(zeros(&buf) < PAYLOAD_BUF_SIZE / 2 + PAYLOAD_BUF_SIZE / 100);
+    }
+
+    #[test]
+    fn test_parse_io_buffer() {
+        assert_eq!(parse_io_buffer("1k").unwrap(), 1024);
+        assert_eq!(parse_io_buffer("4k").unwrap(), 4096);
+        assert_eq!(parse_io_buffer("64m").unwrap(), 64 * 1024 * 1024);
+        assert!(parse_io_buffer("0").is_err());
+        assert!(parse_io_buffer("512").is_err());
+        assert!(parse_io_buffer("1g").is_err());
+        assert!(parse_io_buffer("lots").is_err());
+
+        /* Buffers needn't be a whole number of compression blocks. */
+        let buf = payload_buffer(&mut thread_rng(), 0.5, 6000);
+        assert_eq!(buf.len(), 6000);
+        assert!(buf[4096..4096 + 952].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_spawn_named() {
+        assert_eq!(parse_thread_stack_size("1m").unwrap(), 1024 * 1024);
+        assert!(parse_thread_stack_size("4k").is_err());
+        assert!(parse_thread_stack_size("big").is_err());
+
+        let hdl = spawn_named("chum-test", Some(256 * 1024), || {
+            thread::current().name().map(String::from)
+        });
+        assert_eq!(hdl.join().unwrap().as_deref(), Some("chum-test"));
+    }
+
+    #[test]
+    fn test_parse_compressibility() {
+        assert_eq!(parse_compressibility("0").unwrap(), 0.0);
+        assert_eq!(parse_compressibility("0.25").unwrap(), 0.25);
+        assert_eq!(parse_compressibility("1.0").unwrap(), 1.0);
+        assert!(parse_compressibility("1.5").is_err());
+        assert!(parse_compressibility("-0.1").is_err());
+        assert!(parse_compressibility("half").is_err());
+    }
+
+    /* The same seed should give the same run. */
+    #[test]
+    fn test_seeded_rng() {
+        let dist = parse_distribution("4k,128k:2,512k,1m,4m").unwrap();
+        let sizes = |seed| {
+            let mut rng = new_rng(Some(seed));
+            (0..100)
+                .map(|_| choose_size(&dist, &mut rng))
+                .collect::<Vec<u64>>()
+        };
+
+        assert_eq!(sizes(42), sizes(42));
+        assert_ne!(sizes(42), sizes(43));
+
+        assert_eq!(
+            payload_buffer(&mut new_rng(Some(42)), 0.25, 65536),
+            payload_buffer(&mut new_rng(Some(42)), 0.25, 65536)
+        );
+    }
+
+    #[test]
+    fn test_parse_targets() {
+        assert_eq!(parse_targets(&["127.0.0.1:80"]), vec!["127.0.0.1:80"]);
+        assert_eq!(
+            parse_targets(&["host1:80,host2:80", "host3:80"]),
+            vec!["host1:80", "host2:80", "host3:80"]
+        );
+        assert_eq!(parse_targets(&["host1:80, host2:80,"]).len(), 2);
+        assert!(parse_targets(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_target_url() {
+        for (target, url) in &[
+            ("127.0.0.1", "http://127.0.0.1"),
+            ("127.0.0.1:80", "http://127.0.0.1:80"),
+            (
+                "storage.example.com:8080",
+                "http://storage.example.com:8080",
+            ),
+            ("https://minio:9000", "https://minio:9000"),
+            ("http://minio:9000/", "http://minio:9000"),
+            ("host:8080/dav", "http://host:8080/dav"),
+            ("[2001:db8::1]:8080", "http://[2001:db8::1]:8080"),
+            ("https://[::1]", "https://[::1]"),
+            ("2001:db8::1", "http://[2001:db8::1]"),
+            ("::1", "http://[::1]"),
+        ] {
+            assert_eq!(parse_target_url(target).unwrap(), *url);
+        }
+
+        for bad in &[
+            "",
+            ":80",
+            "host:",
+            "host:http",
+            "host:0",
+            "host:65536",
+            "ftp://host:21",
+            "[2001:db8::1",
+            "[2001:db8::1]8080",
+            "[example.com]:80",
+            "2001:db8::1:8080:x",
+        ] {
+            assert!(parse_target_url(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_parse_storage_classes() {
+        assert_eq!(
+            parse_storage_classes("STANDARD:2,GLACIER").unwrap(),
+            vec!["STANDARD", "STANDARD", "GLACIER"]
+        );
+        assert_eq!(
+            parse_storage_classes("STANDARD_IA").unwrap(),
+            vec!["STANDARD_IA"]
+        );
+        assert!(parse_storage_classes("standard").is_err());
+        assert!(parse_storage_classes("STANDARD,").is_err());
+        assert!(parse_storage_classes("STANDARD:x").is_err());
+    }
+
+    #[test]
+    fn test_parse_concurrency() {
+        let targets = vec!["host1:80".to_string(), "host2:80".to_string()];
+        assert_eq!(
+            parse_concurrency("8", &targets).unwrap(),
+            Concurrency::Workers(8)
+        );
+        assert_eq!(
+            parse_concurrency("host1:80=8,host2:80=4", &targets).unwrap(),
+            Concurrency::PerTarget(vec![8, 4])
+        );
+        assert_eq!(
+            parse_concurrency("host2:80=4, host1:80=8", &targets).unwrap(),
+            Concurrency::PerTarget(vec![8, 4])
+        );
+
+        /* Targets that aren't listed get the default, if there is one. */
+        assert_eq!(
+            parse_concurrency("host2:80=8,2", &targets).unwrap(),
+            Concurrency::PerTarget(vec![2, 8])
         );
+        assert!(parse_concurrency("host2:80=8", &targets).is_err());
+
+        assert!(parse_concurrency("0", &targets).is_err());
+        assert!(parse_concurrency("host1:80=", &targets).is_err());
+        assert!(parse_concurrency("host3:80=8,2", &targets).is_err());
+        assert!(parse_concurrency("host1:80=8,", &targets).is_err());
+    }
+
+    #[test]
+    fn test_pin_workers() {
+        assert_eq!(pin_workers(&[3, 1]), vec![0, 1, 0, 0]);
+        assert_eq!(pin_workers(&[1, 2, 2]), vec![0, 1, 2, 1, 2]);
+        assert_eq!(pin_workers(&[2]), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_shard_prefix() {
+        assert_eq!(shard_prefix(Some("chum"), 3), "chum/worker-3");
+        assert_eq!(shard_prefix(Some("a/b"), 0), "a/b/worker-0");
+        assert_eq!(shard_prefix(None, 12), "worker-12");
+    }
+
+    #[test]
+    fn test_parse_target_weights() {
+        assert_eq!(parse_target_weights("3,1", 2).unwrap(), vec![3, 1]);
+        assert_eq!(parse_target_weights("1", 1).unwrap(), vec![1]);
+        assert!(parse_target_weights("3,1", 3).is_err());
+        assert!(parse_target_weights("3,0", 2).is_err());
+        assert!(parse_target_weights("3,x", 2).is_err());
+    }
+
+    /* What a write run saves, a read run should load. */
+    #[test]
+    fn test_save_queue() -> Result<(), ChumError> {
+        let path = std::env::temp_dir()
+            .join(format!("chum-test-queue-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let saved =
+            Arc::new(SharedQueue::new(QueueMode::Lru, None, DEF_QUEUE_CAP, 1));
+        for i in 0..100 {
+            saved.insert_weighted(
+                format!("v2/chum/{:02}/obj{}", i, i),
+                i % 3 + 1,
+            );
+        }
+        save_queue(std::slice::from_ref(&saved), path)?;
+
+        let loaded = SharedQueue::new(QueueMode::Lru, None, DEF_QUEUE_CAP, 1);
+        populate_queue(&loaded, path.to_string(), false)?;
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(saved.weighted_items(), loaded.weighted_items());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_read_list_line() {
+        let parse = |line| parse_read_list_line(line, false).unwrap();
+        assert_eq!(parse("a/b"), (String::from("a/b"), 1, None));
+        assert_eq!(parse("a/b 10"), (String::from("a/b"), 10, None));
+        assert_eq!(parse("a/b\t 3 "), (String::from("a/b"), 3, None));
+        assert_eq!(
+            parse("my file.txt"),
+            (String::from("my file.txt"), 1, None)
+        );
+        assert_eq!(parse("my file 2"), (String::from("my file"), 2, None));
+        assert!(parse_read_list_line("a/b 0", false).is_err());
+
+        /* With sizes, the first number is the size and any second a weight. */
+        let parse = |line| parse_read_list_line(line, true).unwrap();
+        assert_eq!(parse("a/b 1024"), (String::from("a/b"), 1, Some(1024)));
+        assert_eq!(parse("a/b 0"), (String::from("a/b"), 1, Some(0)));
+        assert_eq!(parse("a/b 1024 5"), (String::from("a/b"), 5, Some(1024)));
+        assert_eq!(
+            parse("my file 1024 5"),
+            (String::from("my file"), 5, Some(1024))
+        );
+        assert!(parse_read_list_line("a/b", true).is_err());
+        assert!(parse_read_list_line("a/b 1024 0", true).is_err());
+    }
+
+    #[test]
+    fn test_source_files() -> Result<(), ChumError> {
+        let dir = std::env::temp_dir()
+            .join(format!("chum-test-source-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("subdir")).unwrap();
+        let dirstr = dir.to_str().unwrap();
+
+        assert!(source_files(dirstr).is_err());
+
+        for name in &["b.json", "a.csv"] {
+            std::fs::write(dir.join(name), name).unwrap();
+        }
+        let files = source_files(dirstr);
+        std::fs::remove_dir_all(&dir).unwrap();
 
+        /* Subdirectories are skipped. */
+        assert_eq!(files?, vec![dir.join("a.csv"), dir.join("b.json")]);
+        assert!(source_files(dirstr).is_err());
         Ok(())
     }
 }
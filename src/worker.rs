@@ -6,40 +6,338 @@
  * Copyright 2020 Joyent, Inc.
  */
 
+use log::{debug, error, info, warn};
 use rand::prelude::*;
+use std::path::PathBuf;
 use std::sync::{
+    atomic::{AtomicBool, Ordering},
     mpsc::{SendError, Sender},
-    Arc, Mutex,
+    Arc,
 };
 use std::time;
 use std::{thread, thread::ThreadId};
 
-use crate::fs::Fs;
-use crate::queue::Queue;
+use crate::azblob::AzBlob;
+use crate::breaker::CircuitBreaker;
+use crate::fs::{Fs, FsSync};
+use crate::histogram::Histogram;
+use crate::http::Http;
+use crate::pause::PauseSwitch;
+use crate::queue::{DeletePolicy, SharedQueue};
+use crate::ratelimit::RateLimiter;
 use crate::s3::S3;
 use crate::state::State;
-use crate::utils::ChumError;
-use crate::webdav::WebDav;
-
-pub const DIR: &str = "chum";
+use crate::utils::{
+    append_checksum, choose_read_range, choose_read_size, choose_size, new_rng,
+    payload_checksum, verify_checksum, verify_size, Checksums, ChumError,
+    ErrorKind, ExpectedSizes, Namer, SizeDistribution, ThinkTime, PROTOCOLS,
+};
+use crate::webdav::{OkStatus, WebDav};
 
 #[derive(Clone)]
 pub struct WorkerOptions {
     pub protocol: String,
     pub read_queue: bool,
     pub operations: Vec<Operation>,
-    pub distribution: Vec<u64>,
+    pub distribution: SizeDistribution,
+    pub read_range: SizeDistribution,
+    pub read_distribution: SizeDistribution,
     pub target: String,
-    pub sleep: u64,
-    pub tx: Sender<Result<WorkerInfo, ChumError>>,
+    pub targets: Vec<Target>,
+    pub target_weights: Vec<u32>,
+    pub think_time: ThinkTime,
+    pub bandwidth: Option<Arc<RateLimiter>>,
+    pub op_rate: Option<Arc<RateLimiter>>,
+    pub pause_switch: Arc<PauseSwitch>, /* for SIGUSR1 */
+    pub tx: Sender<WorkerResult>,
     pub debug_tx: Option<Sender<State>>,
-    pub queue: Arc<Mutex<Queue<String>>>,
+    pub queue: Arc<SharedQueue>,
+    pub delete_policy: DeletePolicy,
+    pub verify_delete: bool,
+    pub checksums: Option<Checksums>,
+    pub expected_sizes: Option<ExpectedSizes>, /* for --verify-size */
+    pub content_md5: bool,
+    pub no_overwrite: bool,
+    pub prefix: Option<String>,
+    pub namer: Namer,
+    pub io_buffer: usize,
+
+    /*
+     * The random (or partly zero) data that object payloads are built from.
+     * It's made once and shared by every worker, since it's only read and
+     * could be megabytes with a large --io-buffer.
+     */
+    pub payload: Arc<Vec<u8>>,
+
+    /* Files to upload instead of the payload, from --source-dir. */
+    pub source_files: Arc<Vec<PathBuf>>,
+    pub seed: Option<u64>,
+    pub retries: u32,
+    pub retry_backoff: u64,
+    pub min_objects: usize,
+    pub connect_timeout: Option<time::Duration>,
+    pub request_timeout: Option<time::Duration>,
 
     /* FS worker. */
-    pub sync: bool,
+    pub fs_sync: FsSync,
+    pub fs_shard: u32,
+    pub fs_mmap: bool,
+    pub fs_direct: bool,
 
     /* WebDAV worker. */
     pub http2: bool,
+    pub http2_prior_knowledge: bool,
+    pub chunked: bool,
+    pub expect_100: bool,
+    pub insecure: bool,
+    pub cacert: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub pinned_hosts: Vec<String>, /* curl resolve entries, for --pin-dns */
+    pub proxy: Option<String>,     /* may have credentials, don't log it */
+    pub no_proxy: Option<String>,
+    pub keepalive: Option<time::Duration>,
+    pub max_connections: u32, /* 0 means a new connection every time */
+    pub ok_status: OkStatus,
+
+    /* S3 worker. */
+    pub access_key: Option<String>,
+    pub secret_key: Option<String>,
+    pub region: Option<String>,
+    pub create_bucket: bool, /* also creates Azure containers */
+    pub storage_classes: Vec<String>,
+    pub multipart_threshold: Option<u64>,
+    pub multipart_part_size: u64,
+    pub multipart_parallelism: u32,
+    pub batch_delete: Option<usize>, /* objects per DeleteObjects request */
+
+    /* Azure Blob worker. */
+    pub azure_endpoint: Option<String>,
+}
+
+/*
+ * A target, and the queue of objects that have been written to it. Objects are
+ * only read from (or deleted from) the target they were written to.
+ */
+#[derive(Clone)]
+pub struct Target {
+    pub name: String,
+    pub queue: Arc<SharedQueue>,
+    pub breaker: Option<Arc<CircuitBreaker>>, /* for --breaker-threshold */
+}
+
+/*
+ * Helpers for --read-range and --read-distribution, which both make reads of
+ * less than the whole object.
+ */
+impl WorkerOptions {
+    /*
+     * Whether reads may be partial. Backends need the size of the object to
+     * choose what to read, so they only look it up if so.
+     */
+    pub fn partial_reads(&self) -> bool {
+        !self.read_range.is_empty() || !self.read_distribution.is_empty()
+    }
+
+    /*
+     * Choose the offset and length to read from an object of 'size' bytes, or
+     * None to read the whole object.
+     */
+    pub fn choose_read<R: Rng>(
+        &self,
+        size: u64,
+        rng: &mut R,
+    ) -> Option<(u64, u64)> {
+        if self.read_distribution.is_empty() {
+            choose_read_range(&self.read_range, size, rng)
+        } else {
+            choose_read_size(&self.read_distribution, size, rng)
+        }
+    }
+}
+
+/*
+ * Helpers for --verify. These are no-ops when verification is off so backends
+ * can call them unconditionally.
+ */
+impl WorkerOptions {
+    /* Remember the checksum of an object that was just written. */
+    pub fn record_checksum(&self, name: &str, src: &[u8], size: u64) {
+        if let Some(sums) = &self.checksums {
+            let sum = payload_checksum(src, size);
+            sums.lock().unwrap().insert(name.to_string(), sum);
+        }
+    }
+
+    /*
+     * Compare an object that was just read with what was written. The
+     * checksum of the data read is only computed if we're verifying.
+     */
+    pub fn verify_checksum<F>(
+        &self,
+        name: &str,
+        checksum: F,
+    ) -> Result<(), ChumError>
+    where
+        F: FnOnce() -> u32,
+    {
+        match &self.checksums {
+            Some(sums) => verify_checksum(sums, name, checksum()),
+            None => Ok(()),
+        }
+    }
+
+    /*
+     * Compare the size of a whole object that was just read with the size
+     * the read list has for it.
+     */
+    pub fn verify_size(&self, name: &str, got: u64) -> Result<(), ChumError> {
+        match &self.expected_sizes {
+            Some(sizes) => verify_size(sizes, name, got),
+            None => Ok(()),
+        }
+    }
+
+    /* A copy of an object reads back the same as the original. */
+    pub fn copy_checksum(&self, from: &str, to: &str) {
+        if let Some(sums) = &self.checksums {
+            let mut sums = sums.lock().unwrap();
+            if let Some(&sum) = sums.get(from) {
+                sums.insert(to.to_string(), sum);
+            }
+        }
+    }
+
+    /*
+     * Carry an object's checksum on over data appended to it. Objects that
+     * were there before the run have no checksum to carry on.
+     */
+    pub fn append_checksum(&self, name: &str, src: &[u8], size: u64) {
+        if let Some(sums) = &self.checksums {
+            if let Some(sum) = sums.lock().unwrap().get_mut(name) {
+                *sum = append_checksum(*sum, src, size);
+            }
+        }
+    }
+
+    pub fn forget_checksum(&self, name: &str) {
+        if let Some(sums) = &self.checksums {
+            sums.lock().unwrap().remove(name);
+        }
+    }
+
+    /*
+     * What the next object written is made of, and its size. Normally that's
+     * the shared payload buffer and a size from the distribution. With
+     * --source-dir it's the whole of a randomly chosen file, which is then
+     * sent, checksummed, and verified like any other payload.
+     */
+    pub fn choose_payload<R: Rng>(
+        &self,
+        buf: &Arc<Vec<u8>>,
+        rng: &mut R,
+    ) -> Result<(Arc<Vec<u8>>, u64), ChumError> {
+        let path = match self.source_files.choose(rng) {
+            Some(path) => path,
+            None => {
+                let size = choose_size(&self.distribution, rng);
+                return Ok((buf.clone(), size));
+            }
+        };
+
+        let data = std::fs::read(path).map_err(|e| {
+            ChumError::new(&format!(
+                "Reading source file {} failed: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let size = data.len() as u64;
+        Ok((Arc::new(data), size))
+    }
+}
+
+/*
+ * Options for trying out a backend on its own in tests: the defaults for
+ * 'protocol' against one target, writing objects of 'distribution' sizes that
+ * are queued to be read back. Nothing is sent on 'tx', only workers use it.
+ */
+#[cfg(test)]
+pub fn test_options(
+    protocol: &str,
+    target: &str,
+    distribution: &str,
+) -> WorkerOptions {
+    use crate::queue::{QueueMode, DEF_QUEUE_CAP};
+    use crate::utils::{parse_distribution, payload_buffer, NameScheme};
+
+    let queue =
+        Arc::new(SharedQueue::new(QueueMode::Rand, Some(1), DEF_QUEUE_CAP, 1));
+    WorkerOptions {
+        protocol: protocol.to_string(),
+        read_queue: true,
+        operations: vec![Operation::Write],
+        distribution: parse_distribution(distribution).unwrap(),
+        read_range: SizeDistribution::default(),
+        read_distribution: SizeDistribution::default(),
+        target: target.to_string(),
+        targets: vec![Target {
+            name: target.to_string(),
+            queue: queue.clone(),
+            breaker: None,
+        }],
+        target_weights: vec![1],
+        think_time: ThinkTime::Constant(time::Duration::from_secs(0)),
+        bandwidth: None,
+        op_rate: None,
+        pause_switch: Arc::new(PauseSwitch::new()),
+        tx: std::sync::mpsc::channel().0,
+        debug_tx: None,
+        queue,
+        delete_policy: DeletePolicy::Queue,
+        verify_delete: false,
+        checksums: None,
+        expected_sizes: None,
+        content_md5: false,
+        no_overwrite: false,
+        prefix: None,
+        namer: Namer::new(NameScheme::Uuid),
+        io_buffer: 65536,
+        payload: Arc::new(payload_buffer(&mut new_rng(Some(1)), 0.0, 65536)),
+        source_files: Arc::new(vec![]),
+        seed: Some(1),
+        retries: 0,
+        retry_backoff: 0,
+        min_objects: 0,
+        connect_timeout: None,
+        request_timeout: None,
+        fs_sync: FsSync::None,
+        fs_shard: 0,
+        fs_mmap: false,
+        fs_direct: false,
+        http2: false,
+        http2_prior_knowledge: false,
+        chunked: false,
+        expect_100: false,
+        insecure: false,
+        cacert: None,
+        headers: vec![],
+        pinned_hosts: vec![],
+        proxy: None,
+        no_proxy: None,
+        keepalive: None,
+        max_connections: 5,
+        ok_status: OkStatus::defaults(protocol.parse().unwrap()),
+        access_key: None,
+        secret_key: None,
+        region: None,
+        create_bucket: false,
+        storage_classes: vec![],
+        multipart_threshold: None,
+        multipart_part_size: 8 * 1024 * 1024,
+        multipart_parallelism: 1,
+        batch_delete: None,
+        azure_endpoint: None,
+    }
 }
 
 #[derive(Debug)]
@@ -49,21 +347,81 @@ pub struct WorkerInfo {
     pub size: u64,     /* in bytes */
     pub ttfb: u128,    /* millis */
     pub rtt: u128,     /* millis */
+
+    /* Millis until a 100 Continue, for writes with --expect-100. */
+    pub continue_ms: Option<u64>,
+
+    /*
+     * Whether the operation had to open a new connection rather than reuse
+     * one that was kept alive. None for backends that don't say.
+     */
+    pub new_conn: Option<bool>,
+    pub entries: u64, /* objects listed, or deleted by a batch delete */
+}
+
+/*
+ * What a worker sends to the stat thread after each attempt at an operation,
+ * along with which thread made the attempt, when it finished, and what the
+ * operation was. A retry is sent as an Operation::Retry WorkerInfo, so 'op'
+ * is the only place the operation being retried shows up.
+ */
+pub struct WorkerResult {
+    pub id: ThreadId,
+    pub time: time::SystemTime,
+    pub op: Operation,
+    pub res: Result<WorkerInfo, ChumError>,
+}
+
+impl WorkerResult {
+    pub fn new(op: Operation, res: Result<WorkerInfo, ChumError>) -> Self {
+        WorkerResult {
+            id: thread::current().id(),
+            time: time::SystemTime::now(),
+            op,
+            res,
+        }
+    }
 }
 
 /*
  * WorkerInfos can be aggregated into WorkerStats.
+ *
+ * Along with the sums (for averages) we keep a histogram of ttfb and rtt so
+ * that tail latency can be reported.
  */
 pub struct WorkerStat {
     pub objs: u64,
     pub data: u64,
     pub ttfb: u128,
     pub rtt: u128,
+    pub entries: u64,
+    pub slow: u64, /* over --slow-threshold */
+    pub continue_ms: u128,
+    pub continues: u64, /* operations that got a 100 Continue */
+    pub connects: u64,  /* operations that opened a new connection */
+    pub reused: u64,    /* operations that reused a kept-alive connection */
+    pub ttfb_hist: Histogram,
+    pub rtt_hist: Histogram,
 }
 
-fn bytes_to_human(bytes: u64) -> String {
-    /* Need to decide if we really care about decimal precision. */
-    format!("{:.3}MB", bytes / 1024 / 1024)
+/*
+ * Format a byte count in the biggest unit it has at least one of, e.g.
+ * '64.000KB' or '5.000GB'. Less than a kilobyte is printed as it is.
+ */
+pub fn bytes_to_human(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+
+    let mut val = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while val >= 1024.0 && unit < UNITS.len() - 1 {
+        val /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.3}{}", val, UNITS[unit])
 }
 
 impl WorkerStat {
@@ -73,6 +431,14 @@ impl WorkerStat {
             data: 0,
             ttfb: 0,
             rtt: 0,
+            entries: 0,
+            slow: 0,
+            continue_ms: 0,
+            continues: 0,
+            connects: 0,
+            reused: 0,
+            ttfb_hist: Histogram::new(),
+            rtt_hist: Histogram::new(),
         }
     }
     pub fn add_result(&mut self, res: &WorkerInfo) {
@@ -80,6 +446,56 @@ impl WorkerStat {
         self.data += res.size;
         self.ttfb += res.ttfb;
         self.rtt += res.rtt;
+        self.entries += res.entries;
+        if let Some(ms) = res.continue_ms {
+            self.continue_ms += u128::from(ms);
+            self.continues += 1;
+        }
+        match res.new_conn {
+            Some(true) => self.connects += 1,
+            Some(false) => self.reused += 1,
+            None => (),
+        }
+        self.ttfb_hist.record(res.ttfb as u64);
+        self.rtt_hist.record(res.rtt as u64);
+    }
+
+    /* Fold another set of stats into this one. */
+    pub fn merge(&mut self, other: &WorkerStat) {
+        self.objs += other.objs;
+        self.data += other.data;
+        self.ttfb += other.ttfb;
+        self.rtt += other.rtt;
+        self.entries += other.entries;
+        self.slow += other.slow;
+        self.continue_ms += other.continue_ms;
+        self.continues += other.continues;
+        self.connects += other.connects;
+        self.reused += other.reused;
+        self.ttfb_hist.merge(&other.ttfb_hist);
+        self.rtt_hist.merge(&other.rtt_hist);
+    }
+
+    pub fn avg_ttfb(&self) -> u128 {
+        if self.objs == 0 {
+            return 0;
+        }
+        self.ttfb / u128::from(self.objs)
+    }
+
+    pub fn avg_rtt(&self) -> u128 {
+        if self.objs == 0 {
+            return 0;
+        }
+        self.rtt / u128::from(self.objs)
+    }
+
+    /* Averaged over only the operations that got a 100 Continue. */
+    pub fn avg_continue(&self) -> Option<u128> {
+        if self.continues == 0 {
+            return None;
+        }
+        Some(self.continue_ms / u128::from(self.continues))
     }
 
     pub fn clear(&mut self) {
@@ -87,16 +503,79 @@ impl WorkerStat {
         self.data = 0;
         self.ttfb = 0;
         self.rtt = 0;
+        self.entries = 0;
+        self.slow = 0;
+        self.continue_ms = 0;
+        self.continues = 0;
+        self.connects = 0;
+        self.reused = 0;
+        self.ttfb_hist.clear();
+        self.rtt_hist.clear();
+    }
+
+    /* Latency percentiles, e.g. '1/2/10/50ms'. */
+    fn serialize_percentiles(hist: &Histogram) -> String {
+        format!(
+            "{}/{}/{}/{}ms",
+            hist.percentile(50.0),
+            hist.percentile(90.0),
+            hist.percentile(99.0),
+            hist.max()
+        )
+    }
+
+    pub fn serialize_latency(&self) -> String {
+        let latency = format!(
+            "ttfb p50/p90/p99/max {}, rtt p50/p90/p99/max {}",
+            WorkerStat::serialize_percentiles(&self.ttfb_hist),
+            WorkerStat::serialize_percentiles(&self.rtt_hist)
+        );
+
+        /* Only worth mentioning when there's a threshold and it was hit. */
+        let latency = if self.slow > 0 {
+            format!("{}, {} slow", latency, self.slow)
+        } else {
+            latency
+        };
+
+        /* Likewise, only writes with --expect-100 get a 100 Continue. */
+        let latency = match self.avg_continue() {
+            Some(ms) => format!("{}, avg 100-continue {}ms", latency, ms),
+            None => latency,
+        };
+
+        /* And only the curl-based backends report on their connections. */
+        if self.connects + self.reused > 0 {
+            format!(
+                "{}, {} new connections, {} reused",
+                latency, self.connects, self.reused
+            )
+        } else {
+            latency
+        }
     }
 
     /* For easy printing when the caller doesn't care about time. */
     pub fn serialize_relative(&mut self) -> String {
         format!(
-            "{} objects, {}, avg ttfb {}ms, avg rtt {}ms",
+            "{} objects, {}, avg ttfb {}ms, avg rtt {}ms, {}",
             self.objs,
             bytes_to_human(self.data),
-            self.ttfb / u128::from(self.objs),
-            self.rtt / u128::from(self.objs)
+            self.avg_ttfb(),
+            self.avg_rtt(),
+            self.serialize_latency()
+        )
+    }
+
+    /* Listings don't move any data, but we count what they return. */
+    pub fn serialize_listing(&self) -> String {
+        format!(
+            "{} listings, {} entries, avg ttfb {}ms, avg rtt {}ms, {}",
+            self.objs,
+            self.entries,
+            self.avg_ttfb(),
+            self.avg_rtt(),
+            self.serialize_latency()
         )
     }
 
@@ -105,23 +584,53 @@ impl WorkerStat {
      * average throughput).
      */
     pub fn serialize_absolute(&mut self, d: u64) -> String {
+        /* Less than a second may have passed, don't divide by zero. */
+        let objs_rate = self.objs.checked_div(d).unwrap_or(0);
+        let data_rate = self.data.checked_div(d).unwrap_or(0);
+
         format!(
-            "{} objects, {}, {}s, avg {} objs/s, avg {}/s",
+            "{} objects, {}, {}s, avg {} objs/s, avg {}/s, {}",
             self.objs,
             bytes_to_human(self.data),
             d,
-            self.objs / d,
-            bytes_to_human(self.data / d)
+            objs_rate,
+            bytes_to_human(data_rate),
+            self.serialize_latency()
         )
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+/* Ordered as they're printed in the stats. */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Operation {
     Read,
     Write,
     Delete,
-    Error,
+    List,
+    Stat,
+    Copy,
+    Append,
+    Expire,           /* a delete of an object older than --ttl */
+    Error(ErrorKind), /* e.g. a timeout, or a corrupt object */
+    Retry,            /* an operation failed and was tried again */
+    Exists,           /* a --no-overwrite write found the object there */
+    Substituted,      /* a read done as a write, see --min-objects */
+}
+
+impl Operation {
+    /*
+     * Some operations don't represent completed work and are only counted.
+     * This is what to call them when printing the count.
+     */
+    pub fn count_label(&self) -> Option<&'static str> {
+        match self {
+            Operation::Error(_) => Some("errors"),
+            Operation::Retry => Some("retries"),
+            Operation::Exists => Some("already existed"),
+            Operation::Substituted => Some("reads substituted"),
+            _ => None,
+        }
+    }
 }
 
 impl std::fmt::Display for Operation {
@@ -130,7 +639,15 @@ impl std::fmt::Display for Operation {
             Operation::Read => "read",
             Operation::Write => "write",
             Operation::Delete => "delete",
-            Operation::Error => "error",
+            Operation::List => "list",
+            Operation::Stat => "stat",
+            Operation::Copy => "copy",
+            Operation::Append => "append",
+            Operation::Expire => "expire",
+            Operation::Error(kind) => return write!(f, "{} error", kind),
+            Operation::Retry => "retry",
+            Operation::Exists => "exists",
+            Operation::Substituted => "substituted",
         };
         write!(f, "{}", str)
     }
@@ -144,25 +661,198 @@ impl std::str::FromStr for Operation {
             "r" => Ok(Operation::Read),
             "w" => Ok(Operation::Write),
             "d" => Ok(Operation::Delete),
-            "e" => Ok(Operation::Error),
+            "l" => Ok(Operation::List),
+            "s" => Ok(Operation::Stat),
+            "c" => Ok(Operation::Copy),
+            "a" => Ok(Operation::Append),
+            "e" => Ok(Operation::Error(ErrorKind::Other)),
             _ => Err(ChumError::new("invalid operation requested")),
         }
     }
 }
 
-pub trait Backend {
+/*
+ * One method per operation in a workload (see Operation). Each performs one
+ * operation against the target and returns what it did, or None if there was
+ * nothing to do (e.g. a read before anything was written). Backends take
+ * &mut self since they keep per-thread state like a curl handle or an rng.
+ *
+ * Writes, reads, and deletes are what every target does. The rest have a
+ * default that fails, for backends that don't support them. main() refuses
+ * workloads that a backend can't do, so the defaults are only a backstop.
+ */
+pub trait Backend: Send {
     fn write(&mut self) -> Result<Option<WorkerInfo>, ChumError>;
     fn read(&mut self) -> Result<Option<WorkerInfo>, ChumError>;
     fn delete(&mut self) -> Result<Option<WorkerInfo>, ChumError>;
+
+    /* A page of the objects under the prefix. */
+    fn list(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        Err(ChumError::new("list isn't supported by this worker"))
+    }
+
+    /* A metadata-only request (e.g. a HEAD) for an object we wrote. */
+    fn stat(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        Err(ChumError::new("stat isn't supported by this worker"))
+    }
+
+    /*
+     * A server-side copy of an object we wrote to a new name. Not every
+     * target has a way to do this, and main() checks for those.
+     */
+    fn copy(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        Err(ChumError::new("copy isn't supported by this worker"))
+    }
+
+    /*
+     * Add data to the end of an object we wrote. Few targets can do this, and
+     * main() checks for those that can't.
+     */
+    fn append(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+        Err(ChumError::new("append isn't supported by this worker"))
+    }
+
+    /*
+     * Delete every object under the prefix, for --cleanup. Returns how many
+     * were deleted.
+     */
+    fn cleanup(&mut self) -> Result<u64, ChumError> {
+        Err(ChumError::new("cleanup isn't supported by this worker"))
+    }
+}
+
+/* The kinds of backend, one per worker subcommand (see utils::PROTOCOLS). */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    WebDav,
+    Http,
+    S3,
+    AzBlob,
+    Fs,
+}
+
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let str = match self {
+            Protocol::WebDav => "webdav",
+            Protocol::Http => "http",
+            Protocol::S3 => "s3",
+            Protocol::AzBlob => "azblob",
+            Protocol::Fs => "fs",
+        };
+        write!(f, "{}", str)
+    }
+}
+
+impl std::str::FromStr for Protocol {
+    type Err = ChumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "webdav" => Ok(Protocol::WebDav),
+            "http" => Ok(Protocol::Http),
+            "s3" => Ok(Protocol::S3),
+            "azblob" => Ok(Protocol::AzBlob),
+            "fs" => Ok(Protocol::Fs),
+            _ => Err(ChumError::new(&format!(
+                "unknown protocol '{}', expected one of {}",
+                s,
+                PROTOCOLS.join(", ")
+            ))),
+        }
+    }
+}
+
+impl Protocol {
+    /*
+     * The operations a workload can ask this kind of backend for. A plain
+     * HTTP service has no standard way to list or copy objects, and objects
+     * can only be appended to on a filesystem.
+     */
+    pub fn operations(self) -> Vec<Operation> {
+        WORKLOAD_OPS
+            .iter()
+            .copied()
+            .filter(|op| match op {
+                Operation::List | Operation::Copy => self != Protocol::Http,
+                Operation::Append => self == Protocol::Fs,
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+/* The operations that map to a Backend method. */
+const WORKLOAD_OPS: [Operation; 7] = [
+    Operation::Read,
+    Operation::Write,
+    Operation::Delete,
+    Operation::List,
+    Operation::Stat,
+    Operation::Copy,
+    Operation::Append,
+];
+
+/*
+ * Check that the backend can do everything the workload asks of it, so that
+ * it's a usage error rather than errors part way through the run.
+ */
+pub fn check_operations(
+    protocol: Protocol,
+    ops: &[Operation],
+) -> Result<(), ChumError> {
+    let supported = protocol.operations();
+    match ops.iter().find(|op| !supported.contains(op)) {
+        Some(op) => {
+            let names: Vec<_> =
+                supported.iter().map(|op| op.to_string()).collect();
+            Err(ChumError::new(&format!(
+                "the {} worker doesn't support {} operations (it supports {})",
+                protocol,
+                op,
+                names.join(", ")
+            )))
+        }
+        None => Ok(()),
+    }
+}
+
+/* Make a client of the given kind for one target. */
+fn new_backend(
+    protocol: Protocol,
+    bopts: WorkerOptions,
+) -> Result<Box<dyn Backend>, ChumError> {
+    Ok(match protocol {
+        Protocol::WebDav => Box::new(WebDav::new(bopts)),
+        Protocol::Http => Box::new(Http::new(bopts)),
+        Protocol::S3 => Box::new(S3::new(bopts)?),
+        Protocol::AzBlob => Box::new(AzBlob::new(bopts)?),
+        Protocol::Fs => Box::new(Fs::new(bopts)),
+    })
 }
 
 pub struct Worker {
-    backend: Box<dyn Backend>,
-    tx: Sender<Result<WorkerInfo, ChumError>>,
-    pause: u64,
+    backends: Vec<Box<dyn Backend>>,
+    breakers: Vec<Option<Arc<CircuitBreaker>>>, /* one per backend */
+    schedule: Vec<usize>,
+    next: usize,
+    tx: Sender<WorkerResult>,
+    think_time: ThinkTime,
     ops: Vec<Operation>,
+    bandwidth: Option<Arc<RateLimiter>>,
+    op_rate: Option<Arc<RateLimiter>>,
+    pause_switch: Arc<PauseSwitch>,
+    retries: u32,
+    retry_backoff: u64,
+    rng: StdRng,
+    retired: Option<Arc<AtomicBool>>,
+    queues: Vec<Arc<SharedQueue>>, /* one per backend */
+    min_objects: usize,
 }
 
+/* The longest we'll wait before retrying an operation, in millis. */
+const MAX_RETRY_BACKOFF: u64 = 30_000;
+
 /*
  * A Worker is something that interacts with a target. It should emit events
  * in the form of a WorkerInfo for every operation performed.
@@ -172,68 +862,230 @@ pub struct Worker {
  */
 #[allow(clippy::too_many_arguments)]
 impl Worker {
-    pub fn new(wopts: WorkerOptions) -> Worker {
-        let protocol = wopts.protocol.clone();
-        let pause = wopts.sleep;
+    pub fn new(mut wopts: WorkerOptions) -> Result<Worker, ChumError> {
+        let protocol: Protocol = wopts.protocol.parse()?;
+        let think_time = wopts.think_time;
         let ops = wopts.operations.clone();
         let tx = wopts.tx.clone();
+        let bandwidth = wopts.bandwidth.clone();
+        let op_rate = wopts.op_rate.clone();
+        let pause_switch = wopts.pause_switch.clone();
+        let retries = wopts.retries;
+        let retry_backoff = wopts.retry_backoff;
+        let min_objects = wopts.min_objects;
+
+        let mut rng = new_rng(wopts.seed);
 
         /*
-         * Construct a client of the given type.
+         * Construct a client of the given type for each target.
          *
          * The S3 client needs a lot more up-front setup vs libcurl. libcurl
          * keeps around a bunch of global state that we overwrite each time
          * we use it.
          */
-        let backend: Box<dyn Backend> = match protocol.as_ref() {
-            "webdav" => Box::new(WebDav::new(wopts)),
-            "s3" => Box::new(S3::new(wopts)),
-            "fs" => Box::new(Fs::new(wopts)),
-            _ => panic!("unknown client protocol"),
-        };
+        let targets = std::mem::take(&mut wopts.targets);
+        let mut backends: Vec<Box<dyn Backend>> = Vec::new();
+        let mut breakers = Vec::new();
+        let mut queues = Vec::new();
+        for target in targets {
+            breakers.push(target.breaker);
+            queues.push(target.queue.clone());
+            let mut bopts = wopts.clone();
+            bopts.target = target.name;
+            bopts.queue = target.queue;
 
-        Worker {
-            backend,
+            /*
+             * Each backend gets its own stream of random numbers, seeded from
+             * ours, so that choosing operations doesn't change the sizes it
+             * chooses.
+             */
+            bopts.seed = wopts.seed.map(|_| rng.gen());
+
+            backends.push(new_backend(protocol, bopts)?);
+        }
+
+        /*
+         * Targets are used in turn, according to their weights. Workers start
+         * at different points in the schedule so they don't all hit the same
+         * target at once.
+         */
+        let schedule = target_schedule(&wopts.target_weights);
+        let next = rng.gen_range(0, schedule.len());
+
+        Ok(Worker {
+            backends,
+            breakers,
+            schedule,
+            next,
             tx,
-            pause,
+            think_time,
             ops,
-        }
+            bandwidth,
+            op_rate,
+            pause_switch,
+            retries,
+            retry_backoff,
+            rng,
+            retired: None,
+            queues,
+            min_objects,
+        })
+    }
+
+    /*
+     * Have the worker stop, after the operation it's doing, once 'retired' is
+     * set. This is how --auto-concurrency takes workers away.
+     */
+    pub fn retire_on(&mut self, retired: Arc<AtomicBool>) {
+        self.retired = Some(retired);
     }
 
     pub fn process_result(
         &self,
+        op: Operation,
         res: Result<Option<WorkerInfo>, ChumError>,
-    ) -> Result<(), SendError<Result<WorkerInfo, ChumError>>> {
+    ) -> Result<(), SendError<WorkerResult>> {
         match res {
             Ok(val) => {
                 if let Some(wr) = val {
-                    self.tx.send(Ok(wr))
+                    debug!(
+                        "{} {} bytes, ttfb {}ms, rtt {}ms",
+                        wr.op, wr.size, wr.ttfb, wr.rtt
+                    );
+                    self.tx.send(WorkerResult::new(op, Ok(wr)))
                 } else {
                     Ok(()) /* no-op, like a read operation with an empty queue */
                 }
             }
-            Err(e) => self.tx.send(Err(e)),
+            Err(e) => {
+                warn!("{} failed: {}", op, e);
+                self.tx.send(WorkerResult::new(op, Err(e)))
+            }
         }
     }
 
     pub fn work(&mut self) {
-        let mut rng = thread_rng();
-
         loop {
             /* Thread exits when it receives a signal over its channel. */
+            if let Some(retired) = &self.retired {
+                if retired.load(Ordering::Relaxed) {
+                    return;
+                }
+            }
+
+            /* Hold off while the workload is paused, see PauseSwitch. */
+            self.pause_switch.wait();
 
-            let res = match self
-                .ops
-                .choose(&mut rng)
-                .expect("choosing operation failed")
+            /*
+             * We don't know how much data an operation will move until it's
+             * done, so wait for any debt from previous operations to be paid
+             * off before starting and charge for this one afterward.
+             */
+            if let Some(bw) = &self.bandwidth {
+                bw.wait();
+            }
+
+            /*
+             * Unlike the sleep between operations, the op rate limit is
+             * shared by all threads and tracks wall clock time, so the
+             * aggregate rate converges on the target even as the latency of
+             * individual operations varies.
+             */
+            if let Some(or) = &self.op_rate {
+                or.wait();
+                or.consume(1);
+            }
+
+            let op = match self.ops.choose(&mut self.rng) {
+                Some(op) => *op,
+                None => {
+                    return self.exit(ChumError::new("no operations to do"));
+                }
+            };
+
+            let target = self.schedule[self.next];
+
+            /*
+             * Until the target has --min-objects objects to read, reads are
+             * done as writes instead, so that a run doesn't start with a
+             * burst of reads that find nothing. Counting the queue takes the
+             * lock of every shard, so don't when there's no minimum.
+             */
+            let op = if op == Operation::Read
+                && self.min_objects > 0
+                && self.queues[target].len() < self.min_objects
             {
-                Operation::Read => self.backend.read(),
-                Operation::Write => self.backend.write(),
-                Operation::Delete => self.backend.delete(),
-                _ => panic!("unrecognized operator"),
+                let info = count_info(Operation::Substituted);
+                if self.tx.send(WorkerResult::new(op, Ok(info))).is_err() {
+                    return;
+                }
+                Operation::Write
+            } else {
+                op
             };
 
-            match self.process_result(res) {
+            let backend = &mut self.backends[target];
+            self.next = (self.next + 1) % self.schedule.len();
+
+            /* Leave a failing target alone while its breaker is open. */
+            let breaker = &self.breakers[target];
+            if let Some(cb) = breaker {
+                cb.wait();
+            }
+
+            /*
+             * Transient failures are retried after a backoff. Each retry is
+             * counted, but only the final outcome of the operation counts as
+             * a success or an error.
+             */
+            let mut attempt = 0;
+            let res = loop {
+                let res = match op {
+                    Operation::Read => backend.read(),
+                    Operation::Write => backend.write(),
+                    Operation::Delete => backend.delete(),
+                    Operation::List => backend.list(),
+                    Operation::Stat => backend.stat(),
+                    Operation::Copy => backend.copy(),
+                    Operation::Append => backend.append(),
+                    _ => {
+                        return self.exit(ChumError::new(&format!(
+                            "{} isn't an operation a worker can do",
+                            op
+                        )));
+                    }
+                };
+
+                match &res {
+                    Err(e) if e.is_transient() && attempt < self.retries => {
+                        attempt += 1;
+                        info!("{} failed, retry {}: {}", op, attempt, e);
+                        let retry = WorkerResult::new(
+                            op,
+                            Ok(count_info(Operation::Retry)),
+                        );
+                        if self.tx.send(retry).is_err() {
+                            return;
+                        }
+                        thread::sleep(retry_delay(
+                            self.retry_backoff,
+                            attempt,
+                            &mut self.rng,
+                        ));
+                    }
+                    _ => break res,
+                }
+            };
+
+            if let Some(cb) = breaker {
+                cb.record(&res);
+            }
+
+            if let (Some(bw), Ok(Some(wi))) = (&self.bandwidth, &res) {
+                bw.consume(wi.size);
+            }
+
+            match self.process_result(op, res) {
                 Ok(_) => (),
                 Err(_) => {
                     /*
@@ -248,9 +1100,505 @@ impl Worker {
         }
     }
 
+    /*
+     * Delete the objects that have been in the workload's queues, one per
+     * backend, for at least 'ttl'. This is what --ttl runs every so often in a
+     * thread of its own. The objects are moved to this worker's own queues
+     * and deleted from there, so a delete that fails leaves the rest for the
+     * next pass. The deletes are counted as expires rather than deletes, so
+     * they can be told apart from the ones the workload asked for.
+     */
+    pub fn expire(
+        &mut self,
+        queues: &[Arc<SharedQueue>],
+        ttl: time::Duration,
+    ) -> Result<(), SendError<WorkerResult>> {
+        for (i, queue) in queues.iter().enumerate() {
+            for name in queue.remove_expired(ttl) {
+                self.queues[i].insert(name);
+            }
+            loop {
+                match self.backends[i].delete() {
+                    Ok(None) => break,
+                    Ok(Some(mut wi)) => {
+                        wi.op = Operation::Expire;
+                        self.process_result(Operation::Expire, Ok(Some(wi)))?;
+                    }
+                    Err(e) => {
+                        self.process_result(Operation::Expire, Err(e))?;
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*
+     * Stop this worker because it can't go on. The error is sent along like
+     * any other so that it's counted, and so the run doesn't quietly carry on
+     * with one worker fewer than it says. main() checks the workload before
+     * starting, so this should never happen.
+     */
+    fn exit(&self, e: ChumError) {
+        error!("worker exiting: {}", e);
+        let _ = self
+            .tx
+            .send(WorkerResult::new(Operation::Error(e.kind()), Err(e)));
+    }
+
+    /*
+     * Delete everything under the prefix on each of our targets once the run
+     * is over, for --cleanup. Returns how many objects were deleted.
+     */
+    pub fn cleanup(&mut self) -> Result<u64, ChumError> {
+        let mut deleted = 0;
+        for backend in self.backends.iter_mut() {
+            deleted += backend.cleanup()?;
+        }
+        Ok(deleted)
+    }
+
     fn sleep(&mut self) {
-        if self.pause > 0 {
-            thread::sleep(time::Duration::from_millis(self.pause));
+        let pause = self.think_time.choose(&mut self.rng);
+        if pause > time::Duration::from_secs(0) {
+            thread::sleep(pause);
         }
     }
 }
+
+/*
+ * The order in which a worker uses its targets, as indexes into the list of
+ * targets. Each target appears as many times as its weight, e.g. weights of
+ * [3, 1] give [0, 0, 0, 1].
+ */
+fn target_schedule(weights: &[u32]) -> Vec<usize> {
+    weights
+        .iter()
+        .enumerate()
+        .flat_map(|(i, w)| vec![i; *w as usize])
+        .collect()
+}
+
+/*
+ * Retries and the like don't move any data, they're only counted. See
+ * Operation::count_label().
+ */
+fn count_info(op: Operation) -> WorkerInfo {
+    WorkerInfo {
+        id: thread::current().id(),
+        op,
+        size: 0,
+        ttfb: 0,
+        rtt: 0,
+        continue_ms: None,
+        new_conn: None,
+        entries: 0,
+    }
+}
+
+/*
+ * How long to wait before the given retry (counting from one). The backoff
+ * doubles with each attempt and is jittered so that threads that failed at the
+ * same time, e.g. because the server restarted, don't all retry in lockstep.
+ */
+fn retry_delay<R: Rng>(base: u64, attempt: u32, rng: &mut R) -> time::Duration {
+    let shift = attempt.saturating_sub(1).min(32);
+    let backoff = base.saturating_mul(1 << shift).min(MAX_RETRY_BACKOFF);
+    let jitter = rng.gen_range(0, backoff / 2 + 1);
+    time::Duration::from_millis(backoff / 2 + jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::QueueMode;
+
+    #[test]
+    fn test_protocol() {
+        for name in PROTOCOLS {
+            assert!(name.parse::<Protocol>().is_ok(), "{}", name);
+        }
+
+        /* e.g. 'chum foo:1.2.3.4' is a usage error, not a panic. */
+        let err = "foo".parse::<Protocol>().unwrap_err();
+        assert!(err.to_string().contains("unknown protocol 'foo'"));
+    }
+
+    #[test]
+    fn test_check_operations() {
+        let ops = [Operation::Write, Operation::Read, Operation::Copy];
+        assert!(check_operations(Protocol::S3, &ops).is_ok());
+        assert!(check_operations(Protocol::Fs, &[Operation::Append]).is_ok());
+
+        let err = check_operations(Protocol::Http, &ops).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the http worker doesn't support copy operations (it supports \
+             read, write, delete, stat)"
+        );
+        assert!(
+            check_operations(Protocol::WebDav, &[Operation::Append]).is_err()
+        );
+
+        /* No backend can do something that isn't a workload operation. */
+        let ops = [Operation::Write, Operation::Error(ErrorKind::Other)];
+        assert!(check_operations(Protocol::Fs, &ops).is_err());
+    }
+
+    /* A backend whose writes always succeed and move no data. */
+    struct Nop;
+
+    impl Backend for Nop {
+        fn write(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+            Ok(Some(count_info(Operation::Write)))
+        }
+        fn read(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+            Ok(None)
+        }
+        fn delete(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+            Ok(None)
+        }
+    }
+
+    fn nop_worker(ops: Vec<Operation>, tx: Sender<WorkerResult>) -> Worker {
+        Worker {
+            backends: vec![Box::new(Nop)],
+            breakers: vec![None],
+            schedule: vec![0],
+            next: 0,
+            tx,
+            think_time: ThinkTime::Constant(time::Duration::from_secs(0)),
+            ops,
+            bandwidth: None,
+            op_rate: None,
+            pause_switch: Arc::new(PauseSwitch::new()),
+            retries: 0,
+            retry_backoff: 0,
+            rng: new_rng(Some(1)),
+            retired: None,
+            queues: vec![Arc::new(SharedQueue::new(
+                QueueMode::Rand,
+                None,
+                10,
+                1,
+            ))],
+            min_objects: 0,
+        }
+    }
+
+    #[test]
+    fn test_worker_exit() {
+        /* Workers stop when the stat thread has gone away. */
+        let (tx, rx) = std::sync::mpsc::channel();
+        drop(rx);
+        nop_worker(vec![Operation::Write], tx).work();
+
+        /* They also stop, and say why, when they can't do what's asked. */
+        for ops in &[vec![], vec![Operation::Retry]] {
+            let (tx, rx) = std::sync::mpsc::channel();
+            nop_worker(ops.clone(), tx).work();
+            let msgs: Vec<_> = rx.try_iter().collect();
+            assert_eq!(msgs.len(), 1);
+            assert!(msgs[0].res.is_err());
+        }
+    }
+
+    #[test]
+    fn test_min_objects() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut worker = nop_worker(vec![Operation::Read], tx);
+        worker.min_objects = 2;
+        let queue = worker.queues[0].clone();
+        queue.insert(String::from("obj"));
+        let hdl = thread::spawn(move || worker.work());
+
+        /* One object isn't enough, so the read is done as a write. */
+        let msgs: Vec<_> = rx.iter().take(2).collect();
+        assert_eq!(msgs[0].res.as_ref().unwrap().op, Operation::Substituted);
+        assert_eq!(msgs[1].res.as_ref().unwrap().op, Operation::Write);
+        drop(rx);
+        hdl.join().unwrap();
+    }
+
+    /* Deletes whatever is in its queue, like a real backend. */
+    struct Drain(Arc<SharedQueue>);
+
+    impl Backend for Drain {
+        fn write(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+            Ok(None)
+        }
+        fn read(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+            Ok(None)
+        }
+        fn delete(&mut self) -> Result<Option<WorkerInfo>, ChumError> {
+            Ok(self.0.remove().map(|_| count_info(Operation::Delete)))
+        }
+    }
+
+    #[test]
+    fn test_worker_expire() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut worker = nop_worker(vec![Operation::Delete], tx);
+        worker.backends = vec![Box::new(Drain(worker.queues[0].clone()))];
+
+        let workload = Arc::new(SharedQueue::new(QueueMode::Lru, None, 10, 1));
+        workload.insert(String::from("old"));
+        thread::sleep(time::Duration::from_millis(50));
+        workload.insert(String::from("new"));
+
+        worker
+            .expire(
+                std::slice::from_ref(&workload),
+                time::Duration::from_millis(50),
+            )
+            .unwrap();
+        let msgs: Vec<_> = rx.try_iter().collect();
+        assert_eq!(msgs.len(), 1);
+        assert_eq!(msgs[0].res.as_ref().unwrap().op, Operation::Expire);
+        assert_eq!(workload.weighted_items(), vec![(String::from("new"), 1)]);
+        assert_eq!(worker.queues[0].len(), 0);
+    }
+
+    #[test]
+    fn test_worker_retire() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut worker = nop_worker(vec![Operation::Write], tx);
+        let retired = Arc::new(AtomicBool::new(true));
+        worker.retire_on(retired);
+        worker.work();
+        assert_eq!(rx.try_iter().count(), 0);
+    }
+
+    /*
+     * With --source-dir a write sends the whole of one of the files, rather
+     * than the payload buffer, whatever its size. The file here is bigger than
+     * the buffer and not a multiple of it.
+     */
+    #[test]
+    fn test_write_source_file() -> Result<(), ChumError> {
+        let dir = std::env::temp_dir()
+            .join(format!("chum-test-source-{}", std::process::id()));
+        let target = dir.join("target");
+        std::fs::create_dir_all(&target)?;
+        let source = dir.join("source");
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&source, &data)?;
+
+        let mut wopts = test_options("fs", target.to_str().unwrap(), "4k");
+        wopts.source_files = Arc::new(vec![source]);
+        let buf = wopts.payload.clone();
+        let (src, size) = wopts.choose_payload(&buf, &mut new_rng(Some(1)))?;
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(*src, data);
+
+        let info = Fs::new(wopts).write()?.unwrap();
+        assert_eq!(info.size, data.len() as u64);
+
+        /* The object is the only file under the target, however deep. */
+        let mut dirs = vec![target];
+        let mut written = vec![];
+        while let Some(d) = dirs.pop() {
+            for entry in std::fs::read_dir(d)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    written.push(std::fs::read(path)?);
+                }
+            }
+        }
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(written, vec![data]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bytes_to_human() {
+        assert_eq!(bytes_to_human(0), "0B");
+        assert_eq!(bytes_to_human(512), "512B");
+        assert_eq!(bytes_to_human(65536), "64.000KB");
+        assert_eq!(bytes_to_human(1536), "1.500KB");
+        assert_eq!(bytes_to_human(1024 * 1024), "1.000MB");
+        assert_eq!(bytes_to_human(5 * 1024 * 1024 * 1024), "5.000GB");
+        assert_eq!(bytes_to_human(2048 << 40), "2048.000TB");
+    }
+
+    /*
+     * Intervals with no completed operations are common when the target is
+     * slow or returning errors. Make sure we can still print them.
+     */
+    #[test]
+    fn test_serialize_empty_stat() {
+        let mut ws = WorkerStat::new();
+        let lat = "ttfb p50/p90/p99/max 0/0/0/0ms, \
+                   rtt p50/p90/p99/max 0/0/0/0ms";
+        assert_eq!(
+            ws.serialize_relative(),
+            format!("0 objects, 0B, avg ttfb 0ms, avg rtt 0ms, {}", lat)
+        );
+        assert_eq!(
+            ws.serialize_absolute(0),
+            format!("0 objects, 0B, 0s, avg 0 objs/s, avg 0B/s, {}", lat)
+        );
+        assert_eq!(
+            ws.serialize_absolute(10),
+            format!("0 objects, 0B, 10s, avg 0 objs/s, avg 0B/s, {}", lat)
+        );
+    }
+
+    #[test]
+    fn test_serialize_zero_duration() {
+        let mut ws = WorkerStat::new();
+        ws.add_result(&WorkerInfo {
+            id: thread::current().id(),
+            op: Operation::Write,
+            size: 2 * 1024 * 1024,
+            ttfb: 4,
+            rtt: 10,
+            continue_ms: None,
+            new_conn: None,
+            entries: 0,
+        });
+
+        let lat = "ttfb p50/p90/p99/max 4/4/4/4ms, \
+                   rtt p50/p90/p99/max 10/10/10/10ms";
+        assert_eq!(
+            ws.serialize_relative(),
+            format!("1 objects, 2.000MB, avg ttfb 4ms, avg rtt 10ms, {}", lat)
+        );
+        assert_eq!(
+            ws.serialize_absolute(0),
+            format!("1 objects, 2.000MB, 0s, avg 0 objs/s, avg 0B/s, {}", lat)
+        );
+    }
+
+    #[test]
+    fn test_serialize_slow() {
+        let mut a = WorkerStat::new();
+        let mut b = WorkerStat::new();
+        a.slow = 2;
+        b.slow = 3;
+        a.merge(&b);
+        assert_eq!(a.slow, 5);
+        assert!(a.serialize_relative().ends_with("0/0/0/0ms, 5 slow"));
+
+        a.clear();
+        assert!(!a.serialize_relative().contains("slow"));
+    }
+
+    #[test]
+    fn test_continue_stats() {
+        let mut a = WorkerStat::new();
+        for continue_ms in &[None, Some(10), Some(30)] {
+            a.add_result(&WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Write,
+                size: 1024,
+                ttfb: 50,
+                rtt: 100,
+                continue_ms: *continue_ms,
+                new_conn: None,
+                entries: 0,
+            });
+        }
+
+        /* Writes without a 100 Continue don't count toward the average. */
+        assert_eq!(a.avg_continue(), Some(20));
+        assert!(a.serialize_relative().ends_with("avg 100-continue 20ms"));
+
+        a.clear();
+        assert_eq!(a.avg_continue(), None);
+        assert!(!a.serialize_relative().contains("100-continue"));
+    }
+
+    #[test]
+    fn test_connection_stats() {
+        let mut a = WorkerStat::new();
+        for new_conn in &[None, Some(true), Some(false), Some(false)] {
+            a.add_result(&WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Read,
+                size: 1024,
+                ttfb: 50,
+                rtt: 100,
+                continue_ms: None,
+                new_conn: *new_conn,
+                entries: 0,
+            });
+        }
+
+        assert_eq!((a.connects, a.reused), (1, 2));
+        assert!(a
+            .serialize_relative()
+            .ends_with("1 new connections, 2 reused"));
+
+        a.clear();
+        assert!(!a.serialize_relative().contains("connections"));
+    }
+
+    /*
+     * Stats from each worker thread are merged into per-tick and total
+     * stats, so merging must preserve the latency distribution.
+     */
+    #[test]
+    fn test_merge_stats() {
+        let mut a = WorkerStat::new();
+        let mut b = WorkerStat::new();
+        for rtt in 1..=50 {
+            a.add_result(&WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Read,
+                size: 1024,
+                ttfb: 1,
+                rtt,
+                continue_ms: None,
+                new_conn: None,
+                entries: 0,
+            });
+        }
+        for rtt in 51..=100 {
+            b.add_result(&WorkerInfo {
+                id: thread::current().id(),
+                op: Operation::Read,
+                size: 1024,
+                ttfb: 1,
+                rtt,
+                continue_ms: None,
+                new_conn: None,
+                entries: 0,
+            });
+        }
+
+        a.merge(&b);
+        assert_eq!(a.objs, 100);
+        assert_eq!(a.data, 100 * 1024);
+        assert_eq!(a.avg_rtt(), 50);
+        assert_eq!(a.rtt_hist.percentile(1.0), 1);
+        assert_eq!(a.rtt_hist.percentile(50.0), 50);
+        assert_eq!(a.rtt_hist.max(), 100);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let mut rng = thread_rng();
+        for _ in 0..100 {
+            let d = retry_delay(100, 1, &mut rng).as_millis();
+            assert!((50..=100).contains(&d), "{}", d);
+            let d = retry_delay(100, 4, &mut rng).as_millis();
+            assert!((400..=800).contains(&d), "{}", d);
+
+            /* The backoff is capped no matter how many times we've tried. */
+            let d = retry_delay(100, 1000, &mut rng).as_millis();
+            assert!((15_000..=30_000).contains(&d), "{}", d);
+        }
+        assert_eq!(retry_delay(0, 3, &mut rng).as_millis(), 0);
+    }
+
+    #[test]
+    fn test_target_schedule() {
+        assert_eq!(target_schedule(&[1]), vec![0]);
+        assert_eq!(target_schedule(&[1, 1, 1]), vec![0, 1, 2]);
+        assert_eq!(target_schedule(&[3, 1]), vec![0, 0, 0, 1]);
+    }
+}